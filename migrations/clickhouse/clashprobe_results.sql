@@ -0,0 +1,20 @@
+-- Table expected by the ClickHouse reporter. Latency queries filter by node
+-- and proxy over a time range, which is exactly the sort key. Rename the
+-- table to match `[clickhouse] table` if you changed it.
+CREATE TABLE IF NOT EXISTS clashprobe_results
+(
+    timestamp  DateTime64(3, 'UTC') CODEC(Delta, ZSTD),
+    node       LowCardinality(String),
+    name       String,
+    protocol   LowCardinality(String),
+    server     String,
+    port       UInt16,
+    alive      Bool,
+    delay_ms   Nullable(UInt32) CODEC(T64, ZSTD),
+    loss_pct   Float32,
+    error_kind LowCardinality(String)
+)
+ENGINE = MergeTree
+PARTITION BY toYYYYMM(timestamp)
+ORDER BY (node, name, timestamp)
+TTL toDateTime(timestamp) + INTERVAL 90 DAY;
@@ -0,0 +1,31 @@
+CREATE TABLE rounds (
+    id          BIGSERIAL PRIMARY KEY,
+    node        TEXT        NOT NULL,
+    finished_at TIMESTAMPTZ NOT NULL,
+    total       INTEGER     NOT NULL,
+    alive       INTEGER     NOT NULL
+);
+
+CREATE INDEX rounds_node_finished_at ON rounds (node, finished_at);
+
+CREATE TABLE proxies (
+    id       BIGSERIAL PRIMARY KEY,
+    name     TEXT    NOT NULL,
+    protocol TEXT    NOT NULL,
+    server   TEXT    NOT NULL,
+    port     INTEGER NOT NULL,
+    UNIQUE (name, protocol, server, port)
+);
+
+CREATE TABLE results (
+    round_id   BIGINT           NOT NULL REFERENCES rounds (id) ON DELETE CASCADE,
+    proxy_id   BIGINT           NOT NULL REFERENCES proxies (id),
+    alive      BOOLEAN          NOT NULL,
+    delay_ms   INTEGER,
+    loss_pct   DOUBLE PRECISION NOT NULL,
+    error_kind TEXT,
+    error      TEXT,
+    PRIMARY KEY (round_id, proxy_id)
+);
+
+CREATE INDEX results_proxy_id ON results (proxy_id);
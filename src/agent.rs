@@ -0,0 +1,100 @@
+use crate::config::AgentConfig;
+use crate::probe_result::ProbeResult;
+use crate::reporter::ProbeReporter;
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+use tokio::time::Instant;
+
+/// Body of `POST /api/ingest`: one agent's round.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Ingest {
+    pub node: String,
+    pub results: Vec<ProbeResult>,
+}
+
+/// The latest round an agent pushed to this controller.
+pub struct NodeReport {
+    pub received_at: Instant,
+    pub results: Vec<ProbeResult>,
+}
+
+/// Latest push of every agent, by node name.
+pub type SharedNodeReports = Arc<RwLock<BTreeMap<String, NodeReport>>>;
+
+/// Store `ingest` as its node's latest round, replacing the previous one.
+pub fn store(reports: &SharedNodeReports, ingest: Ingest) {
+    let report = NodeReport {
+        received_at: Instant::now(),
+        results: ingest.results,
+    };
+    reports.write().unwrap().insert(ingest.node, report);
+}
+
+/// Results of every agent heard from within `stale_after`, each named
+/// `<proxy> @ <node>` so the same proxy seen from two nodes stays apart.
+/// Agents gone quiet for longer are dropped.
+pub fn merge(reports: &SharedNodeReports, stale_after: Duration) -> Vec<ProbeResult> {
+    let mut reports = reports.write().unwrap();
+    reports.retain(|_, report| report.received_at.elapsed() < stale_after);
+    reports
+        .iter()
+        .flat_map(|(node, report)| {
+            report.results.iter().map(move |result| ProbeResult {
+                name: format!("{} @ {}", result.name, node),
+                node: Some(node.clone()),
+                ..result.clone()
+            })
+        })
+        .collect()
+}
+
+/// Pushes every round to a controller's `/api/ingest`, so only the
+/// controller needs credentials for the outputs behind it.
+pub struct AgentReporter {
+    client: reqwest::Client,
+    url: String,
+    token: String,
+    node_name: String,
+}
+
+impl AgentReporter {
+    pub fn new(config: &AgentConfig) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.timeout))
+            .build()?;
+        Ok(Self {
+            client,
+            url: format!("{}/api/ingest", config.controller_url.trim_end_matches('/')),
+            token: config.token.clone(),
+            node_name: config.node_name.clone(),
+        })
+    }
+}
+
+#[async_trait]
+impl ProbeReporter for AgentReporter {
+    async fn report(&self, results: &[ProbeResult]) -> Result<()> {
+        let body = Ingest {
+            node: self.node_name.clone(),
+            results: results.to_vec(),
+        };
+        self.client
+            .post(&self.url)
+            .bearer_auth(&self.token)
+            .json(&body)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "Agent"
+    }
+}
@@ -0,0 +1,100 @@
+use crate::config::WebConfig;
+use axum::{
+    extract::{Request, State},
+    http::{StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use base64::{Engine, prelude::BASE64_STANDARD};
+use std::sync::Arc;
+
+/// Credentials accepted by the web API. With neither a token nor basic-auth
+/// credentials configured every request is let through.
+#[derive(Debug, Clone, Default)]
+pub struct WebAuth {
+    token: Option<String>,
+    basic: Option<(String, String)>,
+}
+
+impl WebAuth {
+    pub fn from_config(config: &WebConfig) -> Self {
+        let basic = match (&config.basic_auth_user, &config.basic_auth_password) {
+            (Some(user), Some(password)) => Some((user.clone(), password.clone())),
+            _ => None,
+        };
+        Self {
+            token: config.auth_token.clone().filter(|t| !t.is_empty()),
+            basic,
+        }
+    }
+
+    /// Accepts only `token`, as a bearer token or `?token=`.
+    pub fn token(token: &str) -> Self {
+        Self {
+            token: Some(token.to_string()).filter(|t| !t.is_empty()),
+            basic: None,
+        }
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.token.is_some() || self.basic.is_some()
+    }
+
+    /// Accepts `Authorization: Bearer <token>`, `?token=<token>` (EventSource
+    /// can't set headers) and `Authorization: Basic ...`.
+    fn authorize(&self, req: &Request) -> bool {
+        let authorization = req
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok());
+
+        if let Some(token) = &self.token {
+            let bearer = authorization.and_then(|v| v.strip_prefix("Bearer "));
+            let query = req.uri().query().and_then(|q| {
+                url::form_urlencoded::parse(q.as_bytes())
+                    .find(|(key, _)| key == "token")
+                    .map(|(_, value)| value.into_owned())
+            });
+            if bearer.is_some_and(|b| constant_time_eq(b, token))
+                || query.is_some_and(|q| constant_time_eq(&q, token))
+            {
+                return true;
+            }
+        }
+
+        if let Some((user, password)) = &self.basic {
+            let credentials = authorization
+                .and_then(|v| v.strip_prefix("Basic "))
+                .and_then(|b| BASE64_STANDARD.decode(b).ok())
+                .and_then(|b| String::from_utf8(b).ok());
+            if let Some((u, p)) = credentials.as_deref().and_then(|c| c.split_once(':')) {
+                return constant_time_eq(u, user) & constant_time_eq(p, password);
+            }
+        }
+
+        false
+    }
+}
+
+pub async fn require_auth(State(auth): State<Arc<WebAuth>>, req: Request, next: Next) -> Response {
+    if !auth.is_enabled() || auth.authorize(&req) {
+        return next.run(req).await;
+    }
+
+    let mut response = (StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+    if auth.basic.is_some() {
+        response.headers_mut().insert(
+            header::WWW_AUTHENTICATE,
+            header::HeaderValue::from_static("Basic realm=\"clashprobe\""),
+        );
+    }
+    response
+}
+
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0u8, |acc, (x, y)| acc | (x ^ y))
+            == 0
+}
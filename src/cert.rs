@@ -0,0 +1,179 @@
+use crate::dns;
+use crate::parser::{self, ProxyConfig};
+use anyhow::Result;
+use chrono::{DateTime, TimeDelta, Utc};
+use clash_lib::app::dns::ThreadSafeDNSResolver;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+use tokio_rustls::rustls::{
+    self, ClientConfig, DigitallySignedStruct, SignatureScheme,
+    client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    crypto::{self, CryptoProvider},
+    pki_types::{CertificateDer, ServerName, UnixTime},
+};
+
+/// Where a TLS proxy's certificate is served: its server, port and the
+/// name it sends as SNI.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CertTarget {
+    server: String,
+    port: u16,
+    sni: String,
+}
+
+impl CertTarget {
+    /// The target of a trojan proxy, or a vmess or vless one with TLS on.
+    /// REALITY servers present someone else's certificate, so are skipped.
+    pub fn from_config(raw: &ProxyConfig) -> Option<Self> {
+        let str_of = |key: &str| raw.get(key).and_then(|v| v.as_str());
+        let tls = match str_of("type")? {
+            "trojan" => true,
+            "vmess" | "vless" => raw.get("tls").and_then(|v| v.as_bool()).unwrap_or(false),
+            _ => false,
+        };
+        if !tls || raw.contains_key("reality-opts") {
+            return None;
+        }
+        let (server, port) = parser::endpoint(raw)?;
+        let sni = str_of("sni")
+            .or(str_of("servername"))
+            .filter(|sni| !sni.is_empty())
+            .unwrap_or(&server)
+            .to_string();
+        Some(Self { server, port, sni })
+    }
+}
+
+impl fmt::Display for CertTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.server.contains(':') {
+            write!(f, "[{}]:{}", self.server, self.port)?;
+        } else {
+            write!(f, "{}:{}", self.server, self.port)?;
+        }
+        if self.sni != self.server {
+            write!(f, " ({})", self.sni)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CertInfo {
+    pub not_after: DateTime<Utc>,
+    /// Of the leaf certificate's DER, lower-case hex.
+    pub sha256: String,
+}
+
+impl CertInfo {
+    /// Whether this certificate looks like the routine renewal of `old`:
+    /// `old` was within `window` of expiry and this one lasts longer.
+    pub fn renews(&self, old: &CertInfo, now: DateTime<Utc>, window: TimeDelta) -> bool {
+        old.not_after - now <= window && self.not_after > old.not_after
+    }
+}
+
+/// Handshake with `target` directly, not through the proxy, and read the
+/// leaf certificate. It is not verified: an expired or self-signed one is
+/// still worth reporting.
+pub async fn fetch(
+    target: &CertTarget,
+    resolver: ThreadSafeDNSResolver,
+    timeout: Duration,
+) -> Result<CertInfo> {
+    tokio::time::timeout(timeout, handshake(target, resolver))
+        .await
+        .map_err(|_| anyhow::anyhow!("timed out after {}s", timeout.as_secs()))?
+}
+
+async fn handshake(target: &CertTarget, resolver: ThreadSafeDNSResolver) -> Result<CertInfo> {
+    let ip = dns::resolve_ip(&resolver, &target.server).await?;
+    let stream = TcpStream::connect(SocketAddr::new(ip, target.port)).await?;
+    let name = ServerName::try_from(target.sni.clone())?;
+    let tls = connector()?.connect(name, stream).await?;
+    let (_, session) = tls.get_ref();
+    let leaf = session
+        .peer_certificates()
+        .and_then(|certs| certs.first())
+        .ok_or_else(|| anyhow::anyhow!("server sent no certificate"))?;
+    parse(leaf)
+}
+
+fn parse(der: &CertificateDer<'_>) -> Result<CertInfo> {
+    let (_, cert) = x509_parser::parse_x509_certificate(der)
+        .map_err(|e| anyhow::anyhow!("Failed to parse certificate: {}", e))?;
+    let not_after = DateTime::from_timestamp(cert.validity().not_after.timestamp(), 0)
+        .ok_or_else(|| anyhow::anyhow!("certificate expiry is out of range"))?;
+    let sha256 = Sha256::digest(der)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    Ok(CertInfo { not_after, sha256 })
+}
+
+fn connector() -> Result<TlsConnector> {
+    let provider = CryptoProvider::get_default()
+        .cloned()
+        .ok_or_else(|| anyhow::anyhow!("no TLS crypto provider installed"))?;
+    let config = ClientConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(AcceptAny(provider)))
+        .with_no_client_auth();
+    Ok(TlsConnector::from(Arc::new(config)))
+}
+
+/// Accepts any certificate, still checking handshake signatures so the
+/// server has to hold the key of the certificate it sent.
+#[derive(Debug)]
+struct AcceptAny(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for AcceptAny {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
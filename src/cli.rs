@@ -0,0 +1,121 @@
+use crate::probe_result::ProbeResult;
+use crate::reporter::ProbeReporter;
+use anyhow::Result;
+use async_trait::async_trait;
+use std::fmt::Write;
+
+/// How one-shot results are written out.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// Aligned table for humans
+    #[default]
+    Table,
+    /// JSON array of results
+    Json,
+    /// CSV with a header row
+    Csv,
+}
+
+/// Prints every round as a table on stdout.
+pub struct CliReporter;
+
+#[async_trait]
+impl ProbeReporter for CliReporter {
+    async fn report(&self, results: &[ProbeResult]) -> Result<()> {
+        print!("{}", render_table(results));
+        Ok(())
+    }
+
+    fn is_continuous(&self) -> bool {
+        false
+    }
+
+    fn name(&self) -> &str {
+        "cli"
+    }
+}
+
+pub fn render(results: &[ProbeResult], format: OutputFormat) -> Result<String> {
+    Ok(match format {
+        OutputFormat::Table => render_table(results),
+        OutputFormat::Json => serde_json::to_string_pretty(results)? + "\n",
+        OutputFormat::Csv => render_csv(results),
+    })
+}
+
+pub fn render_table(results: &[ProbeResult]) -> String {
+    let rows: Vec<[String; 6]> = results
+        .iter()
+        .map(|r| {
+            [
+                r.name.clone(),
+                r.protocol.clone(),
+                if r.alive { "ALIVE" } else { "DEAD" }.to_string(),
+                r.delay_ms
+                    .map(|ms| format!("{}ms", ms))
+                    .unwrap_or_else(|| "-".to_string()),
+                format!("{:.0}%", r.loss_pct),
+                r.error.clone().unwrap_or_default(),
+            ]
+        })
+        .collect();
+
+    let header = ["NAME", "PROTOCOL", "STATUS", "DELAY", "LOSS", "ERROR"];
+    let mut widths = header.map(|h| h.chars().count());
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let mut out = String::new();
+    for row in std::iter::once(header.map(String::from)).chain(rows) {
+        let line: Vec<String> = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        let _ = writeln!(out, "{}", line.join("  ").trim_end());
+    }
+
+    let alive = results.iter().filter(|r| r.alive).count();
+    let _ = writeln!(out, "\n{}/{} proxies alive", alive, results.len());
+    out
+}
+
+pub fn render_csv(results: &[ProbeResult]) -> String {
+    let mut out = format!("{}\n", CSV_HEADER);
+    for r in results {
+        let _ = writeln!(out, "{}", csv_row(r));
+    }
+    out
+}
+
+pub(crate) const CSV_HEADER: &str =
+    "name,server,port,protocol,alive,delay_ms,loss_pct,error_kind,error";
+
+/// One CSV line for `r` matching `CSV_HEADER`, without the trailing newline.
+pub(crate) fn csv_row(r: &ProbeResult) -> String {
+    let fields = [
+        csv_field(&r.name),
+        csv_field(&r.server),
+        r.port.to_string(),
+        csv_field(&r.protocol),
+        r.alive.to_string(),
+        r.delay_ms.map(|ms| ms.to_string()).unwrap_or_default(),
+        format!("{:.1}", r.loss_pct),
+        r.error_kind
+            .map(|k| k.as_str().to_string())
+            .unwrap_or_default(),
+        csv_field(r.error.as_deref().unwrap_or_default()),
+    ];
+    fields.join(",")
+}
+
+pub(crate) fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
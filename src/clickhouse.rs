@@ -0,0 +1,110 @@
+use crate::config::ClickHouseConfig;
+use crate::probe_result::ProbeResult;
+use crate::reporter::ProbeReporter;
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::Utc;
+use serde::Serialize;
+use std::time::Duration;
+
+#[derive(Serialize)]
+struct Row<'a> {
+    timestamp: &'a str,
+    node: &'a str,
+    name: &'a str,
+    protocol: &'a str,
+    server: &'a str,
+    port: u16,
+    alive: bool,
+    delay_ms: Option<u64>,
+    loss_pct: f64,
+    error_kind: &'a str,
+}
+
+/// Inserts every round into ClickHouse over the HTTP interface as one
+/// `JSONEachRow` batch. The expected table is in
+/// `migrations/clickhouse/clashprobe_results.sql`; with `async_insert` the
+/// server buffers small batches from many nodes into fewer parts.
+pub struct ClickHouseReporter {
+    client: reqwest::Client,
+    config: ClickHouseConfig,
+}
+
+impl ClickHouseReporter {
+    pub fn new(config: &ClickHouseConfig) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.timeout))
+            .build()?;
+        Ok(Self {
+            client,
+            config: config.clone(),
+        })
+    }
+
+    fn body(&self, results: &[ProbeResult]) -> Result<String> {
+        let timestamp = Utc::now().format("%Y-%m-%d %H:%M:%S%.3f").to_string();
+        let mut body = String::new();
+        for result in results {
+            body.push_str(&serde_json::to_string(&Row {
+                timestamp: &timestamp,
+                node: &self.config.node_name,
+                name: &result.name,
+                protocol: &result.protocol,
+                server: &result.server,
+                port: result.port,
+                alive: result.alive,
+                delay_ms: result.delay_ms,
+                loss_pct: result.loss_pct,
+                error_kind: result.error_kind.map_or("", |k| k.as_str()),
+            })?);
+            body.push('\n');
+        }
+        Ok(body)
+    }
+}
+
+#[async_trait]
+impl ProbeReporter for ClickHouseReporter {
+    async fn report(&self, results: &[ProbeResult]) -> Result<()> {
+        if results.is_empty() {
+            return Ok(());
+        }
+
+        let query = format!(
+            "INSERT INTO {}.{} FORMAT JSONEachRow",
+            self.config.database, self.config.table
+        );
+        let async_insert = if self.config.async_insert { "1" } else { "0" };
+        let mut request = self
+            .client
+            .post(&self.config.url)
+            .query(&[
+                ("query", query.as_str()),
+                ("async_insert", async_insert),
+                ("wait_for_async_insert", "0"),
+            ])
+            .body(self.body(results)?);
+        if let Some(user) = &self.config.user {
+            request = request.header("X-ClickHouse-User", user);
+        }
+        if let Some(password) = &self.config.password {
+            request = request.header("X-ClickHouse-Key", password);
+        }
+
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let message = response.text().await.unwrap_or_default();
+            return Err(anyhow::anyhow!(
+                "ClickHouse insert failed: {} {}",
+                status,
+                message.trim()
+            ));
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "ClickHouse"
+    }
+}
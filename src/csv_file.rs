@@ -0,0 +1,82 @@
+use crate::cli::{CSV_HEADER, csv_field, csv_row};
+use crate::config::CsvConfig;
+use crate::probe_result::ProbeResult;
+use crate::reporter::ProbeReporter;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, SecondsFormat, Utc};
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use tokio::fs::{self, OpenOptions};
+use tokio::io::AsyncWriteExt;
+
+/// Appends one row per proxy per round to a CSV file, writing the header
+/// whenever it starts a new file.
+pub struct CsvFileReporter {
+    config: CsvConfig,
+}
+
+impl CsvFileReporter {
+    pub fn new(config: &CsvConfig) -> Self {
+        Self {
+            config: config.clone(),
+        }
+    }
+
+    /// With daily rotation the UTC date goes between the file stem and
+    /// extension: `results.csv` becomes `results-2024-01-31.csv`.
+    fn path_for(&self, now: DateTime<Utc>) -> PathBuf {
+        let path = Path::new(&self.config.path);
+        if !self.config.rotate_daily {
+            return path.to_path_buf();
+        }
+        let stem = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let date = now.format("%Y-%m-%d");
+        let name = match path.extension() {
+            Some(ext) => format!("{}-{}.{}", stem, date, ext.to_string_lossy()),
+            None => format!("{}-{}", stem, date),
+        };
+        path.with_file_name(name)
+    }
+}
+
+#[async_trait]
+impl ProbeReporter for CsvFileReporter {
+    async fn report(&self, results: &[ProbeResult]) -> Result<()> {
+        let now = Utc::now();
+        let path = self.path_for(now);
+        if let Some(parent) = path.parent()
+            && !parent.as_os_str().is_empty()
+        {
+            fs::create_dir_all(parent).await?;
+        }
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await
+            .with_context(|| format!("failed to open {}", path.display()))?;
+
+        let mut out = String::new();
+        if file.metadata().await?.len() == 0 {
+            let _ = writeln!(out, "timestamp,node,{}", CSV_HEADER);
+        }
+        let timestamp = now.to_rfc3339_opts(SecondsFormat::Secs, true);
+        let node = csv_field(&self.config.node_name);
+        for result in results {
+            let _ = writeln!(out, "{},{},{}", timestamp, node, csv_row(result));
+        }
+
+        file.write_all(out.as_bytes()).await?;
+        file.flush().await?;
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "CSV"
+    }
+}
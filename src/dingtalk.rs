@@ -0,0 +1,101 @@
+use crate::config::DingTalkConfig;
+use crate::probe_result::ProbeResult;
+use crate::reporter::ProbeReporter;
+use crate::summary::{FASTEST, RoundSummary, Severity, StateTracker};
+use anyhow::Result;
+use async_trait::async_trait;
+use base64::{Engine, prelude::BASE64_STANDARD};
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use serde_json::{Value, json};
+use sha2::Sha256;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Posts a text summary of every round to a DingTalk custom robot,
+/// mentioning everyone when no proxy is alive.
+pub struct DingTalkReporter {
+    client: reqwest::Client,
+    config: DingTalkConfig,
+    state: Mutex<StateTracker>,
+}
+
+impl DingTalkReporter {
+    pub fn new(config: &DingTalkConfig) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.timeout))
+            .build()?;
+        Ok(Self {
+            client,
+            config: config.clone(),
+            state: Mutex::new(StateTracker::default()),
+        })
+    }
+
+    /// Robots with "additional signature" security need `timestamp` and
+    /// `sign` query parameters, where `sign` is the HMAC-SHA256 of
+    /// `"<timestamp>\n<secret>"` keyed with the secret.
+    fn url(&self) -> Result<String> {
+        let Some(secret) = &self.config.secret else {
+            return Ok(self.config.webhook_url.clone());
+        };
+        let timestamp = Utc::now().timestamp_millis();
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())?;
+        mac.update(format!("{}\n{}", timestamp, secret).as_bytes());
+        let sign = BASE64_STANDARD.encode(mac.finalize().into_bytes());
+        Ok(format!(
+            "{}&timestamp={}&sign={}",
+            self.config.webhook_url,
+            timestamp,
+            urlencoding::encode(&sign)
+        ))
+    }
+
+    /// Post `summary` as a text message.
+    async fn send(&self, summary: &RoundSummary) -> Result<()> {
+        let body = json!({
+            "msgtype": "text",
+            "text": { "content": summary.chat_text(&self.config.node_name) },
+            "at": { "isAtAll": summary.severity() == Severity::Critical },
+        });
+        // Robot errors come back as HTTP 200 with a non-zero errcode.
+        let response: Value = self
+            .client
+            .post(self.url()?)
+            .json(&body)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let code = response["errcode"].as_i64().unwrap_or(0);
+        if code != 0 {
+            return Err(anyhow::anyhow!(
+                "DingTalk rejected the message: {} {}",
+                code,
+                response["errmsg"].as_str().unwrap_or_default()
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ProbeReporter for DingTalkReporter {
+    async fn report(&self, results: &[ProbeResult]) -> Result<()> {
+        let summary = self.state.lock().unwrap().summarize(results, FASTEST);
+        if !self.config.only_on_change || summary.changed() {
+            self.send(&summary).await?;
+        }
+        self.state.lock().unwrap().record(results);
+        Ok(())
+    }
+
+    async fn restore(&self, results: &[ProbeResult]) {
+        self.state.lock().unwrap().record(results);
+    }
+
+    fn name(&self) -> &str {
+        "DingTalk"
+    }
+}
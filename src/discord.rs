@@ -0,0 +1,159 @@
+use crate::config::DiscordConfig;
+use crate::probe_result::ProbeResult;
+use crate::reporter::ProbeReporter;
+use crate::summary::{FASTEST, RoundSummary, Severity, StateTracker};
+use anyhow::Result;
+use async_trait::async_trait;
+use serde_json::{Value, json};
+use std::fmt::Write as _;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Names listed in an alert before collapsing the rest, keeping embeds well
+/// under Discord's 1024 character field limit.
+const MAX_LISTED: usize = 20;
+
+const COLOR_INFO: u32 = 0x2ecc71;
+const COLOR_WARNING: u32 = 0xf1c40f;
+const COLOR_CRITICAL: u32 = 0xe74c3c;
+
+/// Posts a summary embed of every round to a Discord webhook, plus an alert
+/// embed mentioning the configured roles when proxies die or recover.
+pub struct DiscordReporter {
+    client: reqwest::Client,
+    config: DiscordConfig,
+    state: Mutex<StateTracker>,
+}
+
+impl DiscordReporter {
+    pub fn new(config: &DiscordConfig) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.timeout))
+            .build()?;
+        Ok(Self {
+            client,
+            config: config.clone(),
+            state: Mutex::new(StateTracker::default()),
+        })
+    }
+
+    fn roles(&self, severity: Severity) -> &[String] {
+        let roles = &self.config.mention_roles;
+        match severity {
+            Severity::Info => &roles.info,
+            Severity::Warning => &roles.warning,
+            Severity::Critical => &roles.critical,
+        }
+    }
+
+    fn message(&self, summary: &RoundSummary) -> Value {
+        let severity = summary.severity();
+        let mut fastest = String::new();
+        for (i, (name, delay)) in summary.fastest.iter().enumerate() {
+            let _ = writeln!(fastest, "{}. {} — {} ms", i + 1, name, delay);
+        }
+        if fastest.is_empty() {
+            fastest.push_str("none");
+        }
+
+        let alive = format!("{}/{}", summary.alive, summary.total);
+        let mut embeds = vec![json!({
+            "title": format!("clashprobe · {}", self.config.node_name),
+            "color": color(severity),
+            "fields": [
+                { "name": "Alive", "value": alive, "inline": true },
+                { "name": "Dead", "value": summary.dead().to_string(), "inline": true },
+                { "name": "Fastest", "value": fastest },
+            ],
+            "timestamp": chrono::Utc::now().to_rfc3339(),
+        })];
+
+        let mut content = String::new();
+        let mut roles = Vec::new();
+        if summary.is_alert() {
+            let mut description = String::new();
+            if severity == Severity::Critical {
+                description.push_str("**No proxy is alive.**\n");
+            }
+            if !summary.newly_dead.is_empty() {
+                description.push_str(&name_list("Newly dead", &summary.newly_dead));
+            }
+            if !summary.recovered.is_empty() {
+                description.push_str(&name_list("Recovered", &summary.recovered));
+            }
+            embeds.push(json!({
+                "title": format!("{:?}", severity),
+                "color": color(severity),
+                "description": description,
+            }));
+
+            roles = self.roles(severity).to_vec();
+            content = roles
+                .iter()
+                .map(|id| format!("<@&{}>", id))
+                .collect::<Vec<_>>()
+                .join(" ");
+        }
+
+        json!({
+            "content": content,
+            "embeds": embeds,
+            "allowed_mentions": { "roles": roles },
+        })
+    }
+}
+
+fn color(severity: Severity) -> u32 {
+    match severity {
+        Severity::Info => COLOR_INFO,
+        Severity::Warning => COLOR_WARNING,
+        Severity::Critical => COLOR_CRITICAL,
+    }
+}
+
+fn name_list(title: &str, names: &[String]) -> String {
+    let mut text = format!("**{}**\n", title);
+    for name in names.iter().take(MAX_LISTED) {
+        let _ = writeln!(text, "• {}", name);
+    }
+    if names.len() > MAX_LISTED {
+        let _ = writeln!(text, "…and {} more", names.len() - MAX_LISTED);
+    }
+    text
+}
+
+#[async_trait]
+impl ProbeReporter for DiscordReporter {
+    async fn report(&self, results: &[ProbeResult]) -> Result<()> {
+        let summary = self.state.lock().unwrap().summarize(results, FASTEST);
+        if !self.config.only_on_change || summary.changed() {
+            self.client
+                .post(&self.config.webhook_url)
+                .json(&self.message(&summary))
+                .send()
+                .await?
+                .error_for_status()?;
+        }
+        self.state.lock().unwrap().record(results);
+        Ok(())
+    }
+
+    async fn report_notice(&self, text: &str) -> Result<()> {
+        let text = format!("clashprobe · {}: {}", self.config.node_name, text);
+        self.client
+            .post(&self.config.webhook_url)
+            .json(&json!({ "content": text }))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    async fn restore(&self, results: &[ProbeResult]) {
+        self.state.lock().unwrap().record(results);
+    }
+
+    fn name(&self) -> &str {
+        "Discord"
+    }
+}
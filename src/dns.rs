@@ -0,0 +1,46 @@
+use crate::config::DnsConfig;
+use anyhow::Result;
+use clash_lib::{
+    app::dns::{self, EnhancedResolver, SystemResolver, ThreadSafeDNSResolver},
+    config::def,
+};
+use std::net::IpAddr;
+use std::sync::Arc;
+use tracing::info;
+
+/// Build the resolver used for proxy server lookups. Without configured
+/// nameservers this is the system resolver; otherwise the `[dns]` section is
+/// fed through clash-lib's own Clash DNS config parsing, so the usual
+/// `udp://`, `tcp://`, `tls://` and `https://` nameserver forms all work.
+pub async fn build_resolver(config: &DnsConfig) -> Result<ThreadSafeDNSResolver> {
+    if config.nameservers.is_empty() {
+        info!("Using system DNS resolver");
+        let resolver = SystemResolver::new(config.ipv6)
+            .map_err(|e| anyhow::anyhow!("Failed to create DNS resolver: {}", e))?;
+        return Ok(Arc::new(resolver));
+    }
+
+    let mut raw = def::Config::default();
+    raw.dns.enable = true;
+    raw.dns.ipv6 = config.ipv6;
+    raw.dns.nameserver = config.nameservers.clone();
+    raw.dns.default_nameserver = config.default_nameservers.clone();
+
+    let dns_config = dns::Config::try_from(&raw)
+        .map_err(|e| anyhow::anyhow!("Invalid [dns] configuration: {}", e))?;
+
+    info!("Using DNS nameservers: {}", config.nameservers.join(", "));
+    let resolver = EnhancedResolver::new(dns_config, None, None).await;
+    Ok(Arc::new(resolver))
+}
+
+/// Address of `host`, which may already be an IP literal.
+pub async fn resolve_ip(resolver: &ThreadSafeDNSResolver, host: &str) -> Result<IpAddr> {
+    if let Ok(ip) = host.parse() {
+        return Ok(ip);
+    }
+    resolver
+        .resolve(host, false)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("{} did not resolve", host))
+}
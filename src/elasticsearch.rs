@@ -0,0 +1,136 @@
+use crate::config::ElasticsearchConfig;
+use crate::probe_result::ProbeResult;
+use crate::reporter::ProbeReporter;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::{Value, json};
+use std::time::Duration;
+
+/// Mappings for the daily indices. `index_patterns` is replaced to match the
+/// configured prefix.
+const INDEX_TEMPLATE: &str = include_str!("../migrations/elasticsearch/index_template.json");
+
+#[derive(Serialize)]
+struct Document<'a> {
+    #[serde(rename = "@timestamp")]
+    timestamp: DateTime<Utc>,
+    node: &'a str,
+    #[serde(flatten)]
+    result: &'a ProbeResult,
+}
+
+/// Bulk-indexes one document per proxy per round into a daily index,
+/// `<index_prefix>-YYYY.MM.DD`. Works with Elasticsearch 7.8+ and OpenSearch.
+pub struct ElasticsearchReporter {
+    client: reqwest::Client,
+    config: ElasticsearchConfig,
+}
+
+impl ElasticsearchReporter {
+    /// Install the index template, so indices created from now on get the
+    /// clashprobe mappings.
+    pub async fn connect(config: &ElasticsearchConfig) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.timeout))
+            .build()?;
+        let reporter = Self {
+            client,
+            config: config.clone(),
+        };
+
+        let mut template: Value = serde_json::from_str(INDEX_TEMPLATE)?;
+        template["index_patterns"] = json!([format!("{}-*", config.index_prefix)]);
+        let url = format!(
+            "{}/_index_template/{}",
+            reporter.base_url(),
+            config.index_prefix
+        );
+        reporter
+            .request(reporter.client.put(url))
+            .json(&template)
+            .send()
+            .await?
+            .error_for_status()
+            .context("failed to install the Elasticsearch index template")?;
+        Ok(reporter)
+    }
+
+    fn base_url(&self) -> &str {
+        self.config.url.trim_end_matches('/')
+    }
+
+    fn request(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        if let Some(api_key) = &self.config.api_key {
+            request.header("Authorization", format!("ApiKey {}", api_key))
+        } else if let Some(user) = &self.config.username {
+            request.basic_auth(user, self.config.password.as_ref())
+        } else {
+            request
+        }
+    }
+
+    fn body(&self, results: &[ProbeResult]) -> Result<String> {
+        let now = Utc::now();
+        let index = format!("{}-{}", self.config.index_prefix, now.format("%Y.%m.%d"));
+        let action = serde_json::to_string(&json!({ "index": { "_index": index } }))?;
+
+        let mut body = String::new();
+        for result in results {
+            body.push_str(&action);
+            body.push('\n');
+            body.push_str(&serde_json::to_string(&Document {
+                timestamp: now,
+                node: &self.config.node_name,
+                result,
+            })?);
+            body.push('\n');
+        }
+        Ok(body)
+    }
+}
+
+#[async_trait]
+impl ProbeReporter for ElasticsearchReporter {
+    async fn report(&self, results: &[ProbeResult]) -> Result<()> {
+        if results.is_empty() {
+            return Ok(());
+        }
+
+        let url = format!("{}/_bulk", self.base_url());
+        let response: Value = self
+            .request(self.client.post(url))
+            .header("Content-Type", "application/x-ndjson")
+            .body(self.body(results)?)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        // A bulk request succeeds as a whole even when items fail.
+        if response["errors"].as_bool() == Some(true) {
+            let items = response["items"]
+                .as_array()
+                .map(Vec::as_slice)
+                .unwrap_or_default();
+            let failed: Vec<&Value> = items
+                .iter()
+                .map(|item| &item["index"]["error"])
+                .filter(|error| !error.is_null())
+                .collect();
+            return Err(anyhow::anyhow!(
+                "Elasticsearch rejected {}/{} documents, first error: {}",
+                failed.len(),
+                results.len(),
+                failed.first().map(|e| e.to_string()).unwrap_or_default()
+            ));
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "Elasticsearch"
+    }
+}
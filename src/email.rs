@@ -0,0 +1,192 @@
+use crate::config::{EmailConfig, SmtpTls};
+use crate::probe_result::ProbeResult;
+use crate::reporter::ProbeReporter;
+use crate::summary::{AlertThrottle, RoundSummary, Severity, StateTracker};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Days, Utc};
+use lettre::message::Mailbox;
+use lettre::message::header::ContentType;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Mails an alert when a large share of the fleet dies within one round and,
+/// optionally, a daily digest with the uptime of every proxy.
+pub struct EmailReporter {
+    mailer: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+    to: Vec<Mailbox>,
+    config: EmailConfig,
+    state: Mutex<StateTracker>,
+    throttle: Mutex<AlertThrottle>,
+    digest: Mutex<Digest>,
+}
+
+/// Rounds seen and rounds alive per proxy since the last digest.
+struct Digest {
+    uptime: BTreeMap<String, (u64, u64)>,
+    due: Option<DateTime<Utc>>,
+}
+
+impl EmailReporter {
+    pub fn new(config: &EmailConfig) -> Result<Self> {
+        let builder = match config.tls {
+            SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&config.host)?,
+            SmtpTls::Starttls => {
+                AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.host)?
+            }
+            SmtpTls::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.host),
+        };
+        let mut builder = builder.timeout(Some(Duration::from_secs(config.timeout)));
+        if let Some(port) = config.port {
+            builder = builder.port(port);
+        }
+        if let Some(username) = &config.username {
+            builder =
+                builder.credentials(Credentials::new(username.clone(), config.password.clone()));
+        }
+
+        let from = config
+            .from
+            .parse()
+            .with_context(|| format!("invalid email.from '{}'", config.from))?;
+        let to = config
+            .to
+            .iter()
+            .map(|to| {
+                to.parse()
+                    .with_context(|| format!("invalid email.to '{}'", to))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            mailer: builder.build(),
+            from,
+            to,
+            config: config.clone(),
+            state: Mutex::new(StateTracker::default()),
+            throttle: Mutex::new(AlertThrottle::new(Duration::from_secs(config.min_interval))),
+            digest: Mutex::new(Digest {
+                uptime: BTreeMap::new(),
+                due: config
+                    .daily_summary_hour
+                    .map(|hour| next_digest_at(hour, Utc::now())),
+            }),
+        })
+    }
+
+    /// Whether enough of the fleet died this round to mail about it.
+    fn is_fleet_alert(&self, summary: &RoundSummary) -> bool {
+        if summary.severity() == Severity::Critical {
+            return true;
+        }
+        let dead_pct = summary.newly_dead.len() as f64 * 100.0 / summary.total.max(1) as f64;
+        !summary.newly_dead.is_empty() && dead_pct >= self.config.dead_threshold_pct
+    }
+
+    /// Record `results` and return the digest body once it is due.
+    fn take_digest(&self, results: &[ProbeResult], now: DateTime<Utc>) -> Option<String> {
+        let hour = self.config.daily_summary_hour?;
+        let mut digest = self.digest.lock().unwrap();
+        for result in results {
+            let (rounds, alive) = digest.uptime.entry(result.name.clone()).or_default();
+            *rounds += 1;
+            *alive += result.alive as u64;
+        }
+        if digest.due.is_none_or(|due| now < due) {
+            return None;
+        }
+        digest.due = Some(next_digest_at(hour, now));
+        Some(digest_text(&std::mem::take(&mut digest.uptime)))
+    }
+
+    async fn send(&self, subject: String, body: String) -> Result<()> {
+        let mut message = Message::builder()
+            .from(self.from.clone())
+            .subject(subject)
+            .header(ContentType::TEXT_PLAIN);
+        for to in &self.to {
+            message = message.to(to.clone());
+        }
+        self.mailer.send(message.body(body)?).await?;
+        Ok(())
+    }
+}
+
+/// The next `hour:00` UTC strictly after `now`.
+fn next_digest_at(hour: u32, now: DateTime<Utc>) -> DateTime<Utc> {
+    let today = now
+        .date_naive()
+        .and_hms_opt(hour, 0, 0)
+        .expect("daily_summary_hour is validated")
+        .and_utc();
+    if today > now {
+        today
+    } else {
+        today + Days::new(1)
+    }
+}
+
+/// One line per proxy, least available first.
+fn digest_text(uptime: &BTreeMap<String, (u64, u64)>) -> String {
+    let mut rows: Vec<(&str, f64, u64)> = uptime
+        .iter()
+        .map(|(name, (rounds, alive))| {
+            (
+                name.as_str(),
+                *alive as f64 * 100.0 / *rounds as f64,
+                *rounds,
+            )
+        })
+        .collect();
+    rows.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+    let mut text = String::from("Uptime over the past day:\n\n");
+    for (name, pct, rounds) in rows {
+        let _ = writeln!(text, "{:>6.2}%  {} ({} rounds)", pct, name, rounds);
+    }
+    text
+}
+
+#[async_trait]
+impl ProbeReporter for EmailReporter {
+    async fn report(&self, results: &[ProbeResult]) -> Result<()> {
+        let summary = self.state.lock().unwrap().summarize(results, 0);
+        let severity = summary.severity();
+        if self.is_fleet_alert(&summary) && self.throttle.lock().unwrap().allows(severity) {
+            let subject = format!(
+                "[clashprobe {}] {}: {}/{} proxies dead",
+                self.config.node_name,
+                severity.as_str(),
+                summary.dead(),
+                summary.total
+            );
+            self.send(subject, summary.alert_text()).await?;
+            self.throttle.lock().unwrap().sent();
+        }
+        self.state.lock().unwrap().record(results);
+
+        if let Some(body) = self.take_digest(results, Utc::now()) {
+            let subject = format!("[clashprobe {}] daily summary", self.config.node_name);
+            self.send(subject, body).await?;
+        }
+        Ok(())
+    }
+
+    async fn report_notice(&self, text: &str) -> Result<()> {
+        let subject = format!("[clashprobe {}] notice", self.config.node_name);
+        self.send(subject, text.to_string()).await
+    }
+
+    async fn restore(&self, results: &[ProbeResult]) {
+        self.state.lock().unwrap().record(results);
+    }
+
+    fn name(&self) -> &str {
+        "Email"
+    }
+}
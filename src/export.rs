@@ -0,0 +1,270 @@
+use crate::parser::ProxyConfig;
+use crate::probe_result::ProbeResult;
+use anyhow::Result;
+use base64::{
+    Engine,
+    prelude::{BASE64_STANDARD, BASE64_URL_SAFE_NO_PAD},
+};
+use serde_yaml::{Mapping, Value};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+
+/// Raw configs of every proxy in the subscription, keyed by proxy ID, so
+/// entries sharing a name don't overwrite each other.
+pub type ProxyConfigs = Arc<HashMap<String, ProxyConfig>>;
+
+/// Live [`ProxyConfigs`], swapped out when the subscription is refreshed.
+pub type SharedProxyConfigs = Arc<RwLock<ProxyConfigs>>;
+
+/// Raw configs of the alive proxies, fastest first, at most `top` of them.
+/// Each config is listed once, even if several results carry its ID.
+pub fn alive_configs<'a>(
+    results: &[ProbeResult],
+    configs: &'a ProxyConfigs,
+    top: Option<usize>,
+) -> Vec<&'a ProxyConfig> {
+    let mut alive: Vec<&ProbeResult> = results.iter().filter(|r| r.alive).collect();
+    alive.sort_by_key(|r| r.delay_ms);
+    let mut seen = HashSet::new();
+    alive
+        .into_iter()
+        .filter(|r| seen.insert(r.proxy_id.as_str()))
+        .filter_map(|r| configs.get(&r.proxy_id))
+        .take(top.unwrap_or(usize::MAX))
+        .collect()
+}
+
+/// Clash config with the alive proxies, a select group over them and a
+/// catch-all rule. Usable both as a full config and as a proxy provider.
+pub fn clash_yaml(proxies: &[&ProxyConfig]) -> Result<String> {
+    let names: Vec<Value> = proxies
+        .iter()
+        .filter_map(|p| p.get("name").cloned())
+        .collect();
+
+    let mut group = Mapping::new();
+    group.insert("name".into(), "clashprobe".into());
+    group.insert("type".into(), "select".into());
+    group.insert("proxies".into(), Value::Sequence(names));
+
+    let mut config = Mapping::new();
+    config.insert(
+        "proxies".into(),
+        Value::Sequence(proxies.iter().map(|p| ordered(p)).collect()),
+    );
+    config.insert(
+        "proxy-groups".into(),
+        Value::Sequence(vec![Value::Mapping(group)]),
+    );
+    config.insert(
+        "rules".into(),
+        Value::Sequence(vec!["MATCH,clashprobe".into()]),
+    );
+
+    Ok(serde_yaml::to_string(&config)?)
+}
+
+/// Stable key order so repeated exports diff cleanly.
+fn ordered(config: &ProxyConfig) -> Value {
+    let sorted: BTreeMap<&String, &Value> = config.iter().collect();
+    Value::Mapping(
+        sorted
+            .into_iter()
+            .map(|(k, v)| (Value::String(k.clone()), v.clone()))
+            .collect(),
+    )
+}
+
+/// Classic subscription: base64 of newline-separated proxy URIs. Proxies
+/// whose type has no URI form are skipped.
+pub fn uri_list(proxies: &[&ProxyConfig]) -> String {
+    let uris: Vec<String> = proxies.iter().filter_map(|p| to_uri(p)).collect();
+    BASE64_STANDARD.encode(uris.join("\n"))
+}
+
+fn to_uri(config: &ProxyConfig) -> Option<String> {
+    let name = urlencoding::encode(str_field(config, "name")?).into_owned();
+    let server = host_field(config)?;
+    let port = port_field(config)?;
+
+    let uri = match str_field(config, "type")? {
+        "ss" => {
+            let user_info = format!(
+                "{}:{}",
+                str_field(config, "cipher")?,
+                str_field(config, "password")?
+            );
+            // SIP002: URL-safe and unpadded, since `/` would end the
+            // userinfo.
+            format!(
+                "ss://{}@{}:{}#{}",
+                BASE64_URL_SAFE_NO_PAD.encode(user_info),
+                server,
+                port,
+                name
+            )
+        }
+        "trojan" => {
+            let mut query = Vec::new();
+            if let Some(sni) = str_field(config, "sni") {
+                query.push(format!("sni={}", urlencoding::encode(sni)));
+            }
+            if bool_field(config, "skip-cert-verify") {
+                query.push("allowInsecure=1".to_string());
+            }
+            query.extend(transport_params(config));
+            format!(
+                "trojan://{}@{}:{}{}#{}",
+                urlencoding::encode(str_field(config, "password")?),
+                server,
+                port,
+                query_string(&query),
+                name
+            )
+        }
+        "vless" => {
+            let mut query = vec!["encryption=none".to_string()];
+            if bool_field(config, "tls") {
+                query.push("security=tls".to_string());
+            }
+            if let Some(sni) = str_field(config, "servername") {
+                query.push(format!("sni={}", urlencoding::encode(sni)));
+            }
+            query.extend(transport_params(config));
+            format!(
+                "vless://{}@{}:{}{}#{}",
+                str_field(config, "uuid")?,
+                server,
+                port,
+                query_string(&query),
+                name
+            )
+        }
+        "vmess" => {
+            let ws_opts = config.get("ws-opts");
+            let path = ws_opts
+                .and_then(|o| o.get("path"))
+                .and_then(|v| v.as_str())
+                .unwrap_or("");
+            let host = ws_opts
+                .and_then(|o| o.get("headers"))
+                .and_then(|h| h.get("Host"))
+                .and_then(|v| v.as_str())
+                .unwrap_or("");
+            let json = serde_json::json!({
+                "v": "2",
+                "ps": str_field(config, "name")?,
+                // The JSON field takes IPv6 addresses unbracketed.
+                "add": str_field(config, "server")?,
+                "port": port.to_string(),
+                "id": str_field(config, "uuid")?,
+                "aid": config.get("alterId").and_then(|v| v.as_u64()).unwrap_or(0).to_string(),
+                "scy": str_field(config, "cipher").unwrap_or("auto"),
+                "net": str_field(config, "network").unwrap_or("tcp"),
+                "path": path,
+                "host": host,
+                "tls": if bool_field(config, "tls") { "tls" } else { "" },
+                "sni": str_field(config, "servername").unwrap_or(""),
+            });
+            format!("vmess://{}", BASE64_STANDARD.encode(json.to_string()))
+        }
+        "socks5" => {
+            let auth = match (str_field(config, "username"), str_field(config, "password")) {
+                (Some(user), Some(password)) => format!(
+                    "{}:{}@",
+                    urlencoding::encode(user),
+                    urlencoding::encode(password)
+                ),
+                (Some(user), None) => format!("{}@", urlencoding::encode(user)),
+                _ => String::new(),
+            };
+            format!("socks5://{}{}:{}#{}", auth, server, port, name)
+        }
+        _ => return None,
+    };
+
+    Some(uri)
+}
+
+/// `alpn`, `type` and the ws or grpc options of a trojan or vless link.
+fn transport_params(config: &ProxyConfig) -> Vec<String> {
+    let alpn = config
+        .get("alpn")
+        .and_then(|v| v.as_sequence())
+        .map(|alpn| {
+            let protocols: Vec<&str> = alpn.iter().filter_map(|p| p.as_str()).collect();
+            protocols.join(",")
+        });
+    let ws_opts = config.get("ws-opts");
+    let grpc_opts = config.get("grpc-opts");
+
+    let mut params: Vec<(&str, &str)> = Vec::new();
+    params.extend(alpn.as_deref().map(|alpn| ("alpn", alpn)));
+    match str_field(config, "network") {
+        Some("ws") => {
+            params.push(("type", "ws"));
+            params.extend(
+                ws_opts
+                    .and_then(|o| o.get("path"))
+                    .and_then(|v| v.as_str())
+                    .map(|path| ("path", path)),
+            );
+            params.extend(
+                ws_opts
+                    .and_then(|o| o.get("headers"))
+                    .and_then(|h| h.get("Host"))
+                    .and_then(|v| v.as_str())
+                    .map(|host| ("host", host)),
+            );
+        }
+        Some("grpc") => {
+            params.push(("type", "grpc"));
+            params.extend(
+                grpc_opts
+                    .and_then(|o| o.get("grpc-service-name"))
+                    .and_then(|v| v.as_str())
+                    .map(|name| ("serviceName", name)),
+            );
+        }
+        Some(network) => params.push(("type", network)),
+        None => {}
+    }
+    params
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, urlencoding::encode(value)))
+        .collect()
+}
+
+fn str_field<'a>(config: &'a ProxyConfig, key: &str) -> Option<&'a str> {
+    config.get(key).and_then(|v| v.as_str())
+}
+
+fn bool_field(config: &ProxyConfig, key: &str) -> bool {
+    config.get(key).and_then(|v| v.as_bool()).unwrap_or(false)
+}
+
+fn port_field(config: &ProxyConfig) -> Option<u16> {
+    match config.get("port")? {
+        Value::Number(n) => n.as_u64().and_then(|p| u16::try_from(p).ok()),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    }
+}
+
+/// Server address in URI authority form, bracketing IPv6 literals.
+fn host_field(config: &ProxyConfig) -> Option<String> {
+    let server = str_field(config, "server")?;
+    if server.contains(':') {
+        Some(format!("[{}]", server))
+    } else {
+        Some(server.to_string())
+    }
+}
+
+fn query_string(params: &[String]) -> String {
+    if params.is_empty() {
+        String::new()
+    } else {
+        format!("?{}", params.join("&"))
+    }
+}
@@ -0,0 +1,94 @@
+use crate::config::FeishuConfig;
+use crate::probe_result::ProbeResult;
+use crate::reporter::ProbeReporter;
+use crate::summary::{FASTEST, RoundSummary, StateTracker};
+use anyhow::Result;
+use async_trait::async_trait;
+use base64::{Engine, prelude::BASE64_STANDARD};
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use serde_json::{Value, json};
+use sha2::Sha256;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Posts a text summary of every round to a Feishu or Lark custom bot.
+pub struct FeishuReporter {
+    client: reqwest::Client,
+    config: FeishuConfig,
+    state: Mutex<StateTracker>,
+}
+
+impl FeishuReporter {
+    pub fn new(config: &FeishuConfig) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.timeout))
+            .build()?;
+        Ok(Self {
+            client,
+            config: config.clone(),
+            state: Mutex::new(StateTracker::default()),
+        })
+    }
+
+    /// Post `summary` as a text message.
+    async fn send(&self, summary: &RoundSummary) -> Result<()> {
+        let mut body = json!({
+            "msg_type": "text",
+            "content": { "text": summary.chat_text(&self.config.node_name) },
+        });
+        if let Some(secret) = &self.config.secret {
+            let timestamp = Utc::now().timestamp();
+            body["timestamp"] = json!(timestamp.to_string());
+            body["sign"] = json!(sign(timestamp, secret)?);
+        }
+
+        // Bot errors come back as HTTP 200 with a non-zero code.
+        let response: Value = self
+            .client
+            .post(&self.config.webhook_url)
+            .json(&body)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let code = response["code"].as_i64().unwrap_or(0);
+        if code != 0 {
+            return Err(anyhow::anyhow!(
+                "Feishu rejected the message: {} {}",
+                code,
+                response["msg"].as_str().unwrap_or_default()
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Feishu signs `"<timestamp>\n<secret>"` by using it as the HMAC key over
+/// an empty message.
+fn sign(timestamp: i64, secret: &str) -> Result<String> {
+    let key = format!("{}\n{}", timestamp, secret);
+    let mac = Hmac::<Sha256>::new_from_slice(key.as_bytes())?;
+    Ok(BASE64_STANDARD.encode(mac.finalize().into_bytes()))
+}
+
+#[async_trait]
+impl ProbeReporter for FeishuReporter {
+    async fn report(&self, results: &[ProbeResult]) -> Result<()> {
+        let summary = self.state.lock().unwrap().summarize(results, FASTEST);
+        if !self.config.only_on_change || summary.changed() {
+            self.send(&summary).await?;
+        }
+        self.state.lock().unwrap().record(results);
+        Ok(())
+    }
+
+    async fn restore(&self, results: &[ProbeResult]) {
+        self.state.lock().unwrap().record(results);
+    }
+
+    fn name(&self) -> &str {
+        "Feishu"
+    }
+}
@@ -0,0 +1,145 @@
+use crate::config::ResultFilter;
+use crate::probe_result::ProbeResult;
+use anyhow::Result;
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+/// `ProbeResult` fields a filter may keep. `name`, `alive`, `probed_at` and
+/// `round_id` are always kept. Fields not listed are left out of serialized
+/// output, and optional ones are cleared for reporters that read them
+/// directly.
+pub const FIELDS: &[&str] = &[
+    "name",
+    "proxy_id",
+    "probed_at",
+    "round_id",
+    "server",
+    "port",
+    "protocol",
+    "region",
+    "alive",
+    "delay_ms",
+    "loss_pct",
+    "error",
+    "error_kind",
+    "targets",
+    "uptime_1h",
+    "uptime_24h",
+    "consecutive_failures",
+    "last_alive_at",
+    "p50_ms",
+    "p95_ms",
+    "p99_ms",
+    "cert_not_after",
+    "cert_sha256",
+    "udp_ok",
+    "phases",
+    "first_request_ms",
+    "second_request_ms",
+    "tcp_rtt_ms",
+    "node",
+];
+
+/// Names of proxies whose alive state differs from `previous`, including
+/// proxies not seen before.
+pub fn changed_names(previous: &HashMap<String, bool>, results: &[ProbeResult]) -> HashSet<String> {
+    results
+        .iter()
+        .filter(|r| previous.get(&r.name) != Some(&r.alive))
+        .map(|r| r.name.clone())
+        .collect()
+}
+
+/// The subset of `results` one reporter should see. `changed` comes from
+/// [`changed_names`] and is only consulted with `only_changed`.
+pub fn apply(
+    filter: &ResultFilter,
+    results: &[ProbeResult],
+    changed: &HashSet<String>,
+) -> Result<Vec<ProbeResult>> {
+    let name_regex = filter.name_regex.as_deref().map(Regex::new).transpose()?;
+    let fields: Option<Arc<[String]>> = filter.fields.as_deref().map(Arc::from);
+
+    Ok(results
+        .iter()
+        .filter(|r| !filter.only_alive || r.alive)
+        .filter(|r| !filter.only_changed || changed.contains(&r.name))
+        .filter(|r| {
+            filter.protocols.is_empty()
+                || filter
+                    .protocols
+                    .iter()
+                    .any(|p| r.protocol.eq_ignore_ascii_case(p))
+        })
+        .filter(|r| name_regex.as_ref().is_none_or(|re| re.is_match(&r.name)))
+        .map(|r| match &fields {
+            Some(fields) => select_fields(r, fields),
+            None => r.clone(),
+        })
+        .collect())
+}
+
+fn select_fields(result: &ProbeResult, fields: &Arc<[String]>) -> ProbeResult {
+    let keep = |field: &str| fields.iter().any(|f| f == field);
+    let mut selected = result.clone();
+    if !keep("region") {
+        selected.region = None;
+    }
+    if !keep("delay_ms") {
+        selected.delay_ms = None;
+    }
+    if !keep("error") {
+        selected.error = None;
+    }
+    if !keep("error_kind") {
+        selected.error_kind = None;
+    }
+    if !keep("targets") {
+        selected.targets.clear();
+    }
+    if !keep("uptime_1h") {
+        selected.uptime_1h = None;
+    }
+    if !keep("uptime_24h") {
+        selected.uptime_24h = None;
+    }
+    if !keep("last_alive_at") {
+        selected.last_alive_at = None;
+    }
+    if !keep("p50_ms") {
+        selected.p50_ms = None;
+    }
+    if !keep("p95_ms") {
+        selected.p95_ms = None;
+    }
+    if !keep("p99_ms") {
+        selected.p99_ms = None;
+    }
+    if !keep("cert_not_after") {
+        selected.cert_not_after = None;
+    }
+    if !keep("cert_sha256") {
+        selected.cert_sha256 = None;
+    }
+    if !keep("udp_ok") {
+        selected.udp_ok = None;
+    }
+    if !keep("phases") {
+        selected.phases = None;
+    }
+    if !keep("first_request_ms") {
+        selected.first_request_ms = None;
+    }
+    if !keep("second_request_ms") {
+        selected.second_request_ms = None;
+    }
+    if !keep("tcp_rtt_ms") {
+        selected.tcp_rtt_ms = None;
+    }
+    if !keep("node") {
+        selected.node = None;
+    }
+    selected.fields = Some(fields.clone());
+    selected
+}
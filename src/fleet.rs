@@ -0,0 +1,160 @@
+use crate::config::{PeerConfig, PeersConfig};
+use crate::probe_result::ProbeResult;
+use crate::shutdown::{self, ShutdownSignal};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use futures::future::join_all;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+use tokio::time::MissedTickBehavior;
+use tracing::{info, warn};
+
+/// Latest `/api/status` of every peer, polled in the background.
+pub struct Fleet {
+    /// Name this instance's own results appear under.
+    node_name: String,
+    peers: RwLock<BTreeMap<String, PeerState>>,
+}
+
+/// What is known about one peer.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PeerState {
+    /// When `proxies` were fetched; `None` until the first poll succeeds.
+    pub fetched_at: Option<DateTime<Utc>>,
+    /// Why the latest poll failed. `proxies` then stay from the last
+    /// success.
+    pub error: Option<String>,
+    #[serde(skip)]
+    pub proxies: Vec<ProbeResult>,
+}
+
+/// One proxy as seen from every node that probes it.
+#[derive(Debug, Serialize)]
+pub struct FleetProxy {
+    pub name: String,
+    /// Delay by node; `None` where the proxy is dead. Nodes that don't
+    /// probe the proxy are left out.
+    pub delays: BTreeMap<String, Option<u64>>,
+    /// Node with the lowest delay, if any has it alive.
+    pub fastest: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FleetView {
+    pub nodes: BTreeMap<String, PeerState>,
+    pub proxies: Vec<FleetProxy>,
+}
+
+#[derive(Deserialize)]
+struct Status {
+    proxies: Vec<ProbeResult>,
+}
+
+impl Fleet {
+    /// Start polling every peer in `config` until `shutdown`.
+    pub fn start(config: &PeersConfig, shutdown: ShutdownSignal) -> Result<Arc<Self>> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.timeout))
+            .build()?;
+        let fleet = Arc::new(Self {
+            node_name: config.node_name.clone(),
+            peers: RwLock::new(BTreeMap::new()),
+        });
+        tokio::spawn(fleet.clone().poll(client, config.clone(), shutdown));
+        Ok(fleet)
+    }
+
+    async fn poll(
+        self: Arc<Self>,
+        client: reqwest::Client,
+        config: PeersConfig,
+        mut shutdown: ShutdownSignal,
+    ) {
+        info!(
+            "Polling {} peers every {}s",
+            config.instances.len(),
+            config.interval
+        );
+        let mut ticker = tokio::time::interval(Duration::from_secs(config.interval));
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {}
+                _ = shutdown::requested(&mut shutdown) => return,
+            }
+            let fetched = join_all(config.instances.iter().map(|peer| fetch(&client, peer))).await;
+            for (peer, result) in config.instances.iter().zip(fetched) {
+                self.record(&peer.name, result);
+            }
+        }
+    }
+
+    fn record(&self, name: &str, result: Result<Vec<ProbeResult>>) {
+        let mut peers = self.peers.write().unwrap();
+        let state = peers.entry(name.to_string()).or_default();
+        match result {
+            Ok(proxies) => {
+                state.fetched_at = Some(Utc::now());
+                state.error = None;
+                state.proxies = proxies;
+            }
+            Err(e) => {
+                warn!("Failed to poll peer {}: {}", name, e);
+                state.error = Some(e.to_string());
+            }
+        }
+    }
+
+    /// Every proxy's delay from here and from each peer, by proxy name.
+    pub fn view(&self, local: &[ProbeResult]) -> FleetView {
+        let local_state = PeerState {
+            fetched_at: local.iter().map(|r| r.probed_at).max(),
+            error: None,
+            proxies: local.to_vec(),
+        };
+        let mut nodes = self.peers.read().unwrap().clone();
+        nodes.insert(self.node_name.clone(), local_state);
+
+        let mut by_proxy: BTreeMap<&str, BTreeMap<String, Option<u64>>> = BTreeMap::new();
+        for (node, state) in &nodes {
+            for result in &state.proxies {
+                let delay = result.delay_ms.filter(|_| result.alive);
+                by_proxy
+                    .entry(result.name.as_str())
+                    .or_default()
+                    .insert(node.clone(), delay);
+            }
+        }
+        let proxies = by_proxy
+            .into_iter()
+            .map(|(name, delays)| FleetProxy {
+                name: name.to_string(),
+                fastest: fastest(&delays),
+                delays,
+            })
+            .collect();
+        FleetView { nodes, proxies }
+    }
+}
+
+async fn fetch(client: &reqwest::Client, peer: &PeerConfig) -> Result<Vec<ProbeResult>> {
+    let url = format!("{}/api/status", peer.url.trim_end_matches('/'));
+    let mut request = client.get(url);
+    if !peer.token.is_empty() {
+        request = request.bearer_auth(&peer.token);
+    }
+    let status: Status = request.send().await?.error_for_status()?.json().await?;
+    Ok(status.proxies)
+}
+
+fn fastest(delays: &BTreeMap<String, Option<u64>>) -> Option<String> {
+    delays
+        .iter()
+        .filter_map(|(node, delay)| Some((node, (*delay)?)))
+        .min_by_key(|(_, delay)| *delay)
+        .map(|(node, _)| node.clone())
+}
@@ -0,0 +1,89 @@
+use crate::config::GotifyConfig;
+use crate::probe_result::ProbeResult;
+use crate::reporter::ProbeReporter;
+use crate::summary::{AlertThrottle, RoundSummary, Severity, StateTracker};
+use anyhow::Result;
+use async_trait::async_trait;
+use serde_json::json;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Pushes an alert to a Gotify server whenever proxies die or recover,
+/// prioritised by severity.
+pub struct GotifyReporter {
+    client: reqwest::Client,
+    config: GotifyConfig,
+    state: Mutex<StateTracker>,
+    throttle: Mutex<AlertThrottle>,
+}
+
+impl GotifyReporter {
+    pub fn new(config: &GotifyConfig) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.timeout))
+            .build()?;
+        Ok(Self {
+            client,
+            config: config.clone(),
+            state: Mutex::new(StateTracker::default()),
+            throttle: Mutex::new(AlertThrottle::new(Duration::from_secs(config.min_interval))),
+        })
+    }
+
+    /// Push `summary` as an alert.
+    async fn send(&self, summary: &RoundSummary) -> Result<()> {
+        let severity = summary.severity();
+
+        let url = format!("{}/message", self.config.url.trim_end_matches('/'));
+        self.client
+            .post(url)
+            .header("X-Gotify-Key", &self.config.token)
+            .json(&json!({
+                "title": format!("clashprobe {}: {}", self.config.node_name, severity.as_str()),
+                "message": summary.alert_text(),
+                "priority": severity.priority(&self.config.priorities),
+            }))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ProbeReporter for GotifyReporter {
+    async fn report(&self, results: &[ProbeResult]) -> Result<()> {
+        let summary = self.state.lock().unwrap().summarize(results, 0);
+        let severity = summary.severity();
+        if summary.is_alert() && self.throttle.lock().unwrap().allows(severity) {
+            self.send(&summary).await?;
+            self.throttle.lock().unwrap().sent();
+        }
+        self.state.lock().unwrap().record(results);
+        Ok(())
+    }
+
+    async fn report_notice(&self, text: &str) -> Result<()> {
+        let url = format!("{}/message", self.config.url.trim_end_matches('/'));
+        self.client
+            .post(url)
+            .header("X-Gotify-Key", &self.config.token)
+            .json(&json!({
+                "title": format!("clashprobe {}: notice", self.config.node_name),
+                "message": text,
+                "priority": Severity::Warning.priority(&self.config.priorities),
+            }))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    async fn restore(&self, results: &[ProbeResult]) {
+        self.state.lock().unwrap().record(results);
+    }
+
+    fn name(&self) -> &str {
+        "Gotify"
+    }
+}
@@ -0,0 +1,78 @@
+use crate::config::GraphiteConfig;
+use crate::probe_result::ProbeResult;
+use crate::reporter::ProbeReporter;
+use anyhow::Result;
+use async_trait::async_trait;
+use std::fmt::Write as _;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Writes every round to Carbon using the plaintext protocol, one
+/// connection per round.
+pub struct GraphiteReporter {
+    config: GraphiteConfig,
+}
+
+impl GraphiteReporter {
+    pub fn new(config: &GraphiteConfig) -> Self {
+        Self {
+            config: config.clone(),
+        }
+    }
+
+    fn lines(&self, results: &[ProbeResult], timestamp: u64) -> String {
+        let base = format!(
+            "{}.{}",
+            self.config.prefix,
+            sanitize(&self.config.node_name)
+        );
+        let mut out = String::new();
+        for result in results {
+            let path = format!("{}.{}", base, sanitize(&result.name));
+            let _ = writeln!(out, "{}.alive {} {}", path, result.alive as u8, timestamp);
+            let _ = writeln!(out, "{}.loss_pct {} {}", path, result.loss_pct, timestamp);
+            if let Some(delay_ms) = result.delay_ms {
+                let _ = writeln!(out, "{}.delay_ms {} {}", path, delay_ms, timestamp);
+            }
+        }
+        out
+    }
+}
+
+/// Metric paths split on dots and choke on spaces, so keep only a safe
+/// character set. Shared with the StatsD reporter.
+pub(crate) fn sanitize(segment: &str) -> String {
+    segment
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+#[async_trait]
+impl ProbeReporter for GraphiteReporter {
+    async fn report(&self, results: &[ProbeResult]) -> Result<()> {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let payload = self.lines(results, timestamp);
+
+        let address = (self.config.host.as_str(), self.config.port);
+        let mut stream = tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect(address))
+            .await
+            .map_err(|_| anyhow::anyhow!("Carbon connect timed out"))??;
+        stream.write_all(payload.as_bytes()).await?;
+        stream.shutdown().await?;
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "Graphite"
+    }
+}
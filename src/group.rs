@@ -0,0 +1,66 @@
+use crate::config::ProxyGroup;
+use crate::probe_result::ProbeResult;
+use regex::Regex;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Health of one configured proxy group in one round.
+#[derive(Debug, Clone, Serialize)]
+pub struct GroupStats {
+    pub name: String,
+    pub total: usize,
+    pub alive: usize,
+    pub availability_pct: f64,
+    /// Fastest alive member and its delay.
+    pub best_proxy: Option<String>,
+    pub best_delay_ms: Option<u64>,
+}
+
+/// Stats for every group in `groups`. A proxy can be in several groups;
+/// groups with an invalid regex are skipped, config validation reports them.
+pub fn aggregate(
+    groups: &BTreeMap<String, ProxyGroup>,
+    results: &[ProbeResult],
+) -> Vec<GroupStats> {
+    groups
+        .iter()
+        .filter_map(|(name, group)| {
+            let name_regex = group
+                .name_regex
+                .as_deref()
+                .map(Regex::new)
+                .transpose()
+                .ok()?;
+            let members: Vec<&ProbeResult> = results
+                .iter()
+                .filter(|r| name_regex.as_ref().is_none_or(|re| re.is_match(&r.name)))
+                .filter(|r| {
+                    group.region.as_deref().is_none_or(|region| {
+                        r.region
+                            .as_deref()
+                            .is_some_and(|r| r.eq_ignore_ascii_case(region))
+                    })
+                })
+                .collect();
+
+            let alive = members.iter().filter(|r| r.alive).count();
+            let best = members
+                .iter()
+                .filter(|r| r.alive)
+                .filter_map(|r| r.delay_ms.map(|delay| (r, delay)))
+                .min_by_key(|(_, delay)| *delay);
+            Some(GroupStats {
+                name: name.clone(),
+                total: members.len(),
+                alive,
+                availability_pct: if members.is_empty() {
+                    0.0
+                } else {
+                    alive as f64 * 100.0 / members.len() as f64
+                },
+                best_proxy: best.map(|(r, _)| r.name.clone()),
+                best_delay_ms: best.map(|(_, delay)| delay),
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,74 @@
+use crate::config::{HeartbeatConfig, HeartbeatService};
+use crate::probe_result::ProbeResult;
+use crate::reporter::ProbeReporter;
+use anyhow::Result;
+use async_trait::async_trait;
+use std::time::Duration;
+
+/// Dead man's switch: pings a healthchecks.io or Uptime Kuma push monitor
+/// after every round, so the monitor alerts once clashprobe itself stops.
+pub struct HeartbeatReporter {
+    client: reqwest::Client,
+    config: HeartbeatConfig,
+}
+
+impl HeartbeatReporter {
+    pub fn new(config: &HeartbeatConfig) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.timeout))
+            .build()?;
+        Ok(Self {
+            client,
+            config: config.clone(),
+        })
+    }
+
+    /// healthchecks.io takes the message as the request body and marks a
+    /// failure with a `/fail` suffix; Uptime Kuma takes both as query
+    /// parameters on the push URL.
+    async fn ping(&self, ok: bool, message: String) -> Result<()> {
+        let request = match self.config.service {
+            HeartbeatService::Healthchecks => {
+                let url = self.config.url.trim_end_matches('/');
+                let url = if ok {
+                    url.to_string()
+                } else {
+                    format!("{}/fail", url)
+                };
+                self.client.post(url).body(message)
+            }
+            HeartbeatService::UptimeKuma => {
+                let url = self.config.url.split('?').next().unwrap_or_default();
+                let status = if ok { "up" } else { "down" };
+                self.client
+                    .get(url)
+                    .query(&[("status", status), ("msg", message.as_str())])
+            }
+        };
+        request.send().await?.error_for_status()?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ProbeReporter for HeartbeatReporter {
+    async fn report(&self, results: &[ProbeResult]) -> Result<()> {
+        let alive = results.iter().filter(|r| r.alive).count();
+        let message = format!(
+            "{}: {}/{} proxies alive",
+            self.config.node_name,
+            alive,
+            results.len()
+        );
+        self.ping(true, message).await
+    }
+
+    async fn report_failure(&self, error: &str) -> Result<()> {
+        self.ping(false, format!("{}: {}", self.config.node_name, error))
+            .await
+    }
+
+    fn name(&self) -> &str {
+        "Heartbeat"
+    }
+}
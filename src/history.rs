@@ -0,0 +1,62 @@
+use crate::probe_result::ProbeResult;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryPoint {
+    pub timestamp: DateTime<Utc>,
+    pub alive: bool,
+    pub delay_ms: Option<u64>,
+    pub loss_pct: f64,
+    pub error: Option<String>,
+}
+
+/// Last `capacity` results per proxy, keyed by proxy name, oldest first.
+pub struct HistoryStore {
+    capacity: usize,
+    points: HashMap<String, VecDeque<HistoryPoint>>,
+}
+
+impl HistoryStore {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            points: HashMap::new(),
+        }
+    }
+
+    pub fn record(&mut self, results: &[ProbeResult]) {
+        for result in results {
+            let points = self.points.entry(result.name.clone()).or_default();
+            // Proxies skipped by the scheduler repeat their last result.
+            if points
+                .back()
+                .is_some_and(|p| p.timestamp == result.probed_at)
+            {
+                continue;
+            }
+            if points.len() == self.capacity {
+                points.pop_front();
+            }
+            points.push_back(HistoryPoint {
+                timestamp: result.probed_at,
+                alive: result.alive,
+                delay_ms: result.delay_ms,
+                loss_pct: result.loss_pct,
+                error: result.error.clone(),
+            });
+        }
+    }
+
+    /// Up to `limit` most recent points for `name`, oldest first.
+    pub fn recent(&self, name: &str, limit: usize) -> Vec<HistoryPoint> {
+        self.points
+            .get(name)
+            .map(|points| {
+                let skip = points.len().saturating_sub(limit);
+                points.iter().skip(skip).cloned().collect()
+            })
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,191 @@
+use crate::config::{IncidentConfig, IncidentProvider};
+use crate::probe_result::ProbeResult;
+use crate::reporter::ProbeReporter;
+use anyhow::Result;
+use async_trait::async_trait;
+use serde_json::json;
+use std::collections::HashSet;
+use std::sync::Mutex;
+use std::time::Duration;
+
+const PAGERDUTY_URL: &str = "https://events.pagerduty.com/v2/enqueue";
+const OPSGENIE_URL: &str = "https://api.opsgenie.com";
+
+/// Fleet-wide condition that opens its own incident, so a total outage pages
+/// separately from a partial one and each resolves on its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Condition {
+    /// Alive share dropped below `min_alive_pct`.
+    LowAlive,
+    /// No proxy is alive.
+    AllDead,
+}
+
+impl Condition {
+    const ALL: [Condition; 2] = [Condition::LowAlive, Condition::AllDead];
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Condition::LowAlive => "low-alive",
+            Condition::AllDead => "all-dead",
+        }
+    }
+
+    fn is_active(&self, alive: usize, total: usize, min_alive_pct: f64) -> bool {
+        match self {
+            Condition::LowAlive => {
+                total > 0 && (alive as f64 * 100.0 / total as f64) < min_alive_pct
+            }
+            Condition::AllDead => total > 0 && alive == 0,
+        }
+    }
+}
+
+/// Opens a PagerDuty or Opsgenie alert when fleet health crosses a
+/// threshold and resolves it once the fleet recovers.
+pub struct IncidentReporter {
+    client: reqwest::Client,
+    config: IncidentConfig,
+    /// Conditions with an incident currently open.
+    open: Mutex<HashSet<Condition>>,
+}
+
+impl IncidentReporter {
+    pub fn new(config: &IncidentConfig) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.timeout))
+            .build()?;
+        Ok(Self {
+            client,
+            config: config.clone(),
+            open: Mutex::new(HashSet::new()),
+        })
+    }
+
+    /// Stable per node and condition, so repeated triggers collapse into one
+    /// incident and the resolve finds it.
+    fn dedup_key(&self, condition: Condition) -> String {
+        format!(
+            "clashprobe-{}-{}",
+            self.config.node_name,
+            condition.as_str()
+        )
+    }
+
+    async fn trigger(&self, condition: Condition, alive: usize, total: usize) -> Result<()> {
+        let summary = match condition {
+            Condition::LowAlive => format!(
+                "clashprobe {}: only {}/{} proxies alive (below {}%)",
+                self.config.node_name, alive, total, self.config.min_alive_pct
+            ),
+            Condition::AllDead => format!(
+                "clashprobe {}: no proxy is alive ({} probed)",
+                self.config.node_name, total
+            ),
+        };
+        let details = json!({ "alive": alive, "total": total, "node": self.config.node_name });
+
+        let request = match self.config.provider {
+            IncidentProvider::PagerDuty => self.client.post(self.url(PAGERDUTY_URL)).json(&json!({
+                "routing_key": self.config.key,
+                "event_action": "trigger",
+                "dedup_key": self.dedup_key(condition),
+                "payload": {
+                    "summary": summary,
+                    "source": self.config.node_name,
+                    "severity": match condition {
+                        Condition::LowAlive => "error",
+                        Condition::AllDead => "critical",
+                    },
+                    "component": "clashprobe",
+                    "custom_details": details,
+                },
+            })),
+            IncidentProvider::Opsgenie => self
+                .opsgenie(format!("{}/v2/alerts", self.url(OPSGENIE_URL)))
+                .json(&json!({
+                    "message": summary,
+                    "alias": self.dedup_key(condition),
+                    "source": "clashprobe",
+                    "priority": match condition {
+                        Condition::LowAlive => "P2",
+                        Condition::AllDead => "P1",
+                    },
+                    "details": details,
+                })),
+        };
+        request.send().await?.error_for_status()?;
+        Ok(())
+    }
+
+    async fn resolve(&self, condition: Condition) -> Result<()> {
+        let request = match self.config.provider {
+            IncidentProvider::PagerDuty => self.client.post(self.url(PAGERDUTY_URL)).json(&json!({
+                "routing_key": self.config.key,
+                "event_action": "resolve",
+                "dedup_key": self.dedup_key(condition),
+            })),
+            IncidentProvider::Opsgenie => self
+                .opsgenie(format!(
+                    "{}/v2/alerts/{}/close?identifierType=alias",
+                    self.url(OPSGENIE_URL),
+                    urlencoding::encode(&self.dedup_key(condition))
+                ))
+                .json(&json!({ "source": "clashprobe", "note": "Fleet recovered" })),
+        };
+        request.send().await?.error_for_status()?;
+        Ok(())
+    }
+
+    fn url<'a>(&'a self, default: &'a str) -> &'a str {
+        self.config
+            .url
+            .as_deref()
+            .unwrap_or(default)
+            .trim_end_matches('/')
+    }
+
+    fn opsgenie(&self, url: String) -> reqwest::RequestBuilder {
+        self.client
+            .post(url)
+            .header("Authorization", format!("GenieKey {}", self.config.key))
+    }
+}
+
+#[async_trait]
+impl ProbeReporter for IncidentReporter {
+    async fn report(&self, results: &[ProbeResult]) -> Result<()> {
+        let total = results.len();
+        let alive = results.iter().filter(|r| r.alive).count();
+
+        for condition in Condition::ALL {
+            let active = condition.is_active(alive, total, self.config.min_alive_pct);
+            let open = self.open.lock().unwrap().contains(&condition);
+            // Only remember the new state once the provider accepted it, so
+            // a failed call is retried next round.
+            if active && !open {
+                self.trigger(condition, alive, total).await?;
+                self.open.lock().unwrap().insert(condition);
+            } else if !active && open {
+                self.resolve(condition).await?;
+                self.open.lock().unwrap().remove(&condition);
+            }
+        }
+        Ok(())
+    }
+
+    /// Conditions still active were triggered before the restart, so only
+    /// need resolving.
+    async fn restore(&self, results: &[ProbeResult]) {
+        let total = results.len();
+        let alive = results.iter().filter(|r| r.alive).count();
+        *self.open.lock().unwrap() = Condition::ALL
+            .into_iter()
+            .filter(|condition| condition.is_active(alive, total, self.config.min_alive_pct))
+            .collect();
+    }
+
+    fn name(&self) -> &str {
+        "Incident"
+    }
+}
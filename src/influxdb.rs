@@ -1,74 +1,456 @@
-use futures::prelude::*;
-use influxdb2::Client;
-use influxdb2::models::DataPoint;
-use std::time::{SystemTime, UNIX_EPOCH};
-
-use crate::config::Config;
-use crate::probe_result::ProbeResult;
-use crate::reporter::ProbeReporter;
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use influxdb2::models::data_point::DataPointBuilder;
+use influxdb2::models::{DataPoint, WriteDataPoint};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+use tracing::warn;
+
+use crate::config::{DeadDelay, InfluxDbConfig, InfluxVersion};
+use crate::group::GroupStats;
+use crate::probe_result::{ErrorKind, ProbeResult};
+use crate::reporter::{ProbeReporter, RoundInfo, SharedRoundInfo};
+use crate::subscription::SubscriptionInfo;
 use anyhow::Result;
 use async_trait::async_trait;
 
+/// POSTs line protocol to `/api/v2/write` with a token on 2.x, or to
+/// `/write` with optional basic auth on 1.x.
+struct InfluxWriter {
+    client: reqwest::Client,
+    config: InfluxDbConfig,
+    /// Time one write may take, retries included: `main.reporter_timeout`,
+    /// so the engine never cancels a write halfway through its backoff.
+    budget: Duration,
+}
+
+impl InfluxWriter {
+    fn request(&self) -> reqwest::RequestBuilder {
+        let host = self.config.host.trim_end_matches('/');
+        match self.config.version {
+            InfluxVersion::V1 => {
+                let mut request = self
+                    .client
+                    .post(format!("{}/write", host))
+                    .query(&[("db", self.config.database.as_str()), ("precision", "ns")]);
+                if let Some(retention_policy) = &self.config.retention_policy {
+                    request = request.query(&[("rp", retention_policy)]);
+                }
+                if let Some(username) = &self.config.username {
+                    request = request.basic_auth(username, Some(&self.config.password));
+                }
+                request
+            }
+            InfluxVersion::V2 => self
+                .client
+                .post(format!("{}/api/v2/write", host))
+                .query(&[
+                    ("org", self.config.org.as_str()),
+                    ("bucket", self.config.bucket.as_str()),
+                    ("precision", "ns"),
+                ])
+                .header("Authorization", format!("Token {}", self.config.token)),
+        }
+    }
+
+    /// Write `lines`, retrying with doubling backoff up to `max_retries`
+    /// times while another attempt still fits in the budget.
+    async fn write(&self, lines: &[u8]) -> Result<()> {
+        let deadline = Instant::now() + self.budget;
+        let body = if self.config.gzip {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(lines)?;
+            encoder.finish()?
+        } else {
+            lines.to_vec()
+        };
+
+        let mut backoff = Duration::from_secs(self.config.retry_backoff);
+        let mut attempt = 0;
+        loop {
+            let mut request = self.request();
+            if self.config.gzip {
+                request = request.header("Content-Encoding", "gzip");
+            }
+            let outcome = match request.body(body.clone()).send().await {
+                Ok(response) => response.error_for_status().map(|_| ()),
+                Err(e) => Err(e),
+            };
+            match outcome {
+                Ok(()) => return Ok(()),
+                Err(e) if attempt < self.config.max_retries && self.fits(backoff, deadline) => {
+                    attempt += 1;
+                    warn!(
+                        "InfluxDB write failed ({}), retry {}/{} in {}s",
+                        e,
+                        attempt,
+                        self.config.max_retries,
+                        backoff.as_secs()
+                    );
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    /// Whether waiting `backoff` and then a full request still ends before
+    /// `deadline`.
+    fn fits(&self, backoff: Duration, deadline: Instant) -> bool {
+        Instant::now() + backoff + Duration::from_secs(self.config.timeout) < deadline
+    }
+}
+
+/// Line protocol waiting to be written.
+#[derive(Default)]
+struct Batch {
+    lines: Vec<u8>,
+    points: usize,
+    since: Option<Instant>,
+}
+
 pub struct InfluxUploader {
-    client: Client,
-    bucket: String,
+    writer: InfluxWriter,
+    batch: Mutex<Batch>,
     node_name: String,
+    measurement: String,
+    tags: BTreeMap<String, String>,
+    dead_delay: DeadDelay,
+    round_info: SharedRoundInfo,
 }
 
 impl InfluxUploader {
-    pub fn new(config: &Config) -> Self {
-        let client = Client::new(
-            config.influxdb.host.clone(),
-            config.influxdb.org.clone(),
-            config.influxdb.token.clone(),
-        );
-
-        Self {
+    pub fn new(
+        config: &InfluxDbConfig,
+        round_info: SharedRoundInfo,
+        budget: Duration,
+    ) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.timeout))
+            .build()?;
+        let writer = InfluxWriter {
             client,
-            bucket: config.influxdb.bucket.clone(),
-            node_name: config.influxdb.node_name.clone(),
+            config: config.clone(),
+            budget,
+        };
+
+        Ok(Self {
+            writer,
+            batch: Mutex::new(Batch::default()),
+            node_name: config.node_name.clone(),
+            measurement: config.measurement.clone(),
+            tags: config.tags.clone(),
+            dead_delay: config.dead_delay,
+            round_info,
+        })
+    }
+
+    /// A point for `result` carrying the tags every point shares.
+    fn point(&self, measurement: &str, result: &ProbeResult) -> DataPointBuilder {
+        let mut builder = DataPoint::builder(measurement);
+        for (key, value) in &self.tags {
+            builder = builder.tag(key, value);
+        }
+        // Line protocol has no empty tag values.
+        if !result.proxy_id.is_empty() {
+            builder = builder.tag("proxy_id", &result.proxy_id);
+        }
+        if let Some(region) = &result.region {
+            builder = builder.tag("region", region);
         }
+        builder
+            .tag("name", &result.name)
+            .tag("protocol", &result.protocol)
+            .tag("node", result.node.as_deref().unwrap_or(&self.node_name))
     }
 
-    pub async fn upload_results(
+    /// Fleet totals and probe timing for `round`, tagged only by node. Uses
+    /// the round's own timestamp, so replaying a round rewrites the same
+    /// point instead of adding one.
+    fn round_point(&self, round: &RoundInfo) -> Result<DataPoint> {
+        let mut builder = DataPoint::builder(format!("{}_round", self.measurement));
+        for (key, value) in &self.tags {
+            builder = builder.tag(key, value);
+        }
+        let success_rate = if round.total == 0 {
+            0.0
+        } else {
+            round.alive as f64 / round.total as f64
+        };
+        Ok(builder
+            .tag("node", &self.node_name)
+            .field("duration_ms", round.duration_ms as i64)
+            .field("total", round.total as i64)
+            .field("alive", round.alive as i64)
+            .field("dead", (round.total - round.alive) as i64)
+            .field("probed", round.probed as i64)
+            .field("success_rate", success_rate)
+            .field("subscription_entries", round.parse.entries as i64)
+            .field("parsed", round.parse.parsed as i64)
+            .field("parse_skipped", round.parse.skipped() as i64)
+            .field("overruns", round.overruns as i64)
+            .timestamp(round.finished_at.timestamp_nanos_opt().unwrap_or_default())
+            .build()?)
+    }
+
+    /// Aggregates of one `[groups]` entry in `round`, tagged by node and
+    /// group name and timestamped like [`Self::round_point`].
+    fn group_point(&self, round: &RoundInfo, group: &GroupStats) -> Result<DataPoint> {
+        let mut builder = DataPoint::builder(format!("{}_group", self.measurement));
+        for (key, value) in &self.tags {
+            builder = builder.tag(key, value);
+        }
+        builder = builder
+            .tag("node", &self.node_name)
+            .tag("group", &group.name)
+            .field("total", group.total as i64)
+            .field("alive", group.alive as i64)
+            .field("availability_pct", group.availability_pct);
+        if let Some(delay_ms) = group.best_delay_ms {
+            builder = builder.field("best_delay_ms", delay_ms as i64);
+        }
+        Ok(builder
+            .timestamp(round.finished_at.timestamp_nanos_opt().unwrap_or_default())
+            .build()?)
+    }
+
+    fn subscription_point(
         &self,
-        results: &[ProbeResult],
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos() as i64;
+        round: &RoundInfo,
+        subscription: &SubscriptionInfo,
+    ) -> Result<DataPoint> {
+        let mut builder = DataPoint::builder(format!("{}_subscription", self.measurement));
+        for (key, value) in &self.tags {
+            builder = builder.tag(key, value);
+        }
+        builder = builder
+            .tag("node", &self.node_name)
+            .field("upload", subscription.upload as i64)
+            .field("download", subscription.download as i64)
+            .field("total", subscription.total as i64);
+        if let Some(used_pct) = subscription.used_pct() {
+            builder = builder.field("used_pct", used_pct);
+        }
+        if let Some(expire) = subscription.expire {
+            builder = builder
+                .field("expire", expire.timestamp())
+                .field("days_left", (expire - round.finished_at).num_days());
+        }
+        Ok(builder
+            .timestamp(round.finished_at.timestamp_nanos_opt().unwrap_or_default())
+            .build()?)
+    }
 
-        let mut points = Vec::new();
+    /// Direct delay of one test URL in `round`, tagged like the per-target
+    /// points of proxies.
+    fn baseline_point(
+        &self,
+        round: &RoundInfo,
+        target: &str,
+        delay_ms: Option<u64>,
+    ) -> Result<DataPoint> {
+        let mut builder = DataPoint::builder(format!("{}_baseline", self.measurement));
+        for (key, value) in &self.tags {
+            builder = builder.tag(key, value);
+        }
+        builder = builder
+            .tag("node", &self.node_name)
+            .tag("target", target)
+            .field("alive", delay_ms.is_some());
+        if let Some(delay_ms) = self.delay(delay_ms) {
+            builder = builder.field("delay_ms", delay_ms);
+        }
+        Ok(builder
+            .timestamp(round.finished_at.timestamp_nanos_opt().unwrap_or_default())
+            .build()?)
+    }
 
+    /// The `delay_ms` field to write, if any; dead ones follow `dead_delay`.
+    fn delay(&self, delay_ms: Option<u64>) -> Option<i64> {
+        match (delay_ms, self.dead_delay) {
+            (Some(delay_ms), _) => Some(delay_ms as i64),
+            (None, DeadDelay::Omit) => None,
+            (None, DeadDelay::Negative) => Some(-1),
+            (None, DeadDelay::Sentinel) => Some(99999),
+        }
+    }
+
+    pub async fn upload_results(&self, results: &[ProbeResult]) -> Result<()> {
+        // The newest round in the batch, so a replayed round rewrites its own
+        // points instead of adding new ones.
+        let timestamp = match results.iter().map(|r| r.probed_at).max() {
+            Some(probed_at) => probed_at.timestamp_nanos_opt().unwrap_or_default(),
+            None => SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos() as i64,
+        };
+
+        let mut points = Vec::new();
         for result in results {
-            let point = if result.alive {
-                DataPoint::builder("probe")
-                    .tag("name", &result.name)
-                    .tag("protocol", &result.protocol)
-                    .tag("node", &self.node_name)
-                    .field("alive", true)
-                    .field("delay_ms", result.delay_ms.unwrap() as i64)
-                    .timestamp(timestamp)
-                    .build()?
-            } else {
-                DataPoint::builder("probe")
-                    .tag("name", &result.name)
-                    .tag("protocol", &result.protocol)
-                    .tag("node", &self.node_name)
-                    .field("alive", false)
-                    .field("delay_ms", 99999)
-                    .timestamp(timestamp)
-                    .build()?
-            };
-            points.push(point);
+            points.extend(self.result_points(result, timestamp, true)?);
         }
 
-        if !points.is_empty() {
-            self.client
-                .write(&self.bucket, stream::iter(points))
-                .await?;
+        let round = self.round_info.read().unwrap().clone();
+        if let Some(round) = round {
+            points.push(self.round_point(&round)?);
+            for group in &round.groups {
+                points.push(self.group_point(&round, group)?);
+            }
+            if let Some(subscription) = &round.subscription {
+                points.push(self.subscription_point(&round, subscription)?);
+            }
+            for (target, delay_ms) in &round.baseline {
+                points.push(self.baseline_point(&round, target, *delay_ms)?);
+            }
         }
 
+        self.buffer(&points).await
+    }
+
+    /// Buffer the points of one result ahead of its round. They share the
+    /// round's timestamp and carry no `error_kind` tag, so they land in the
+    /// same series as the round's own points and get overwritten by them.
+    pub async fn upload_partial(&self, result: &ProbeResult) -> Result<()> {
+        let timestamp = result.probed_at.timestamp_nanos_opt().unwrap_or_default();
+        let points = self.result_points(result, timestamp, false)?;
+        self.buffer(&points).await
+    }
+
+    /// The proxy's point plus one per test target. Dead proxies get an
+    /// `error_kind` tag when `tag_error` is set.
+    fn result_points(
+        &self,
+        result: &ProbeResult,
+        timestamp: i64,
+        tag_error: bool,
+    ) -> Result<Vec<DataPoint>> {
+        let mut point = self
+            .point(&self.measurement, result)
+            .field("alive", result.alive)
+            .field("loss_pct", result.loss_pct)
+            .field("success_rate", 1.0 - result.loss_pct / 100.0)
+            .field(
+                "consecutive_failures",
+                i64::from(result.consecutive_failures),
+            );
+        if let Some(uptime) = result.uptime_1h {
+            point = point.field("uptime_1h", uptime);
+        }
+        if let Some(uptime) = result.uptime_24h {
+            point = point.field("uptime_24h", uptime);
+        }
+        for (field, value) in [
+            ("p50_ms", result.p50_ms),
+            ("p95_ms", result.p95_ms),
+            ("p99_ms", result.p99_ms),
+            ("first_request_ms", result.first_request_ms),
+            ("second_request_ms", result.second_request_ms),
+            ("tcp_rtt_ms", result.tcp_rtt_ms),
+        ] {
+            if let Some(value) = value {
+                point = point.field(field, value as i64);
+            }
+        }
+        if let Some(phases) = result.phases {
+            point = point
+                .field("handshake_ms", phases.handshake_ms as i64)
+                .field("first_byte_ms", phases.first_byte_ms as i64);
+            if let Some(tls_ms) = phases.tls_ms {
+                point = point.field("tls_ms", tls_ms as i64);
+            }
+        }
+        if let Some(udp_ok) = result.udp_ok {
+            point = point.field("udp_ok", udp_ok);
+        }
+        if let Some(not_after) = result.cert_not_after {
+            let days_left = (not_after - result.probed_at).num_days();
+            point = point.field("cert_days_left", days_left);
+        }
+        if tag_error && !result.alive {
+            point = point.tag(
+                "error_kind",
+                result.error_kind.unwrap_or(ErrorKind::Other).as_str(),
+            );
+        }
+        if let Some(delay_ms) = self.delay(result.delay_ms) {
+            point = point.field("delay_ms", delay_ms);
+        }
+        let mut points = vec![point.timestamp(timestamp).build()?];
+
+        let target_measurement = format!("{}_target", self.measurement);
+        for (target, delay_ms) in &result.targets {
+            let mut point = self
+                .point(&target_measurement, result)
+                .tag("target", target)
+                .field("alive", delay_ms.is_some());
+            if let Some(delay_ms) = self.delay(*delay_ms) {
+                point = point.field("delay_ms", delay_ms);
+            }
+            points.push(point.timestamp(timestamp).build()?);
+        }
+        Ok(points)
+    }
+
+    /// Add `points` to the batch and write it if it is full or old enough.
+    async fn buffer(&self, points: &[DataPoint]) -> Result<()> {
+        let mut batch = self.batch.lock().await;
+        for point in points {
+            point.write_data_point_to(&mut batch.lines)?;
+        }
+        batch.points += points.len();
+        batch.since.get_or_insert_with(Instant::now);
+
+        let config = &self.writer.config;
+        let full = batch.points >= config.batch_size;
+        let stale = batch
+            .since
+            .is_some_and(|since| since.elapsed() >= Duration::from_secs(config.flush_interval));
+        if !full && !stale {
+            return Ok(());
+        }
+        match self.write_batch(&mut batch).await {
+            // Still buffered, so the next flush writes it: reporting an
+            // error would only make the round get replayed into it again.
+            Err(e) if batch.points > 0 => {
+                warn!(
+                    "InfluxDB write failed, keeping {} points for the next flush: {}",
+                    batch.points, e
+                );
+                Ok(())
+            }
+            outcome => outcome,
+        }
+    }
+
+    /// Write whatever is buffered. A failed batch stays buffered for the
+    /// next attempt unless it outgrew `max_buffered_points`; rewriting
+    /// points InfluxDB already has is harmless, since identical series and
+    /// timestamps overwrite each other.
+    async fn write_batch(&self, batch: &mut Batch) -> Result<()> {
+        if batch.points == 0 {
+            return Ok(());
+        }
+
+        if let Err(e) = self.writer.write(&batch.lines).await {
+            if batch.points > self.writer.config.max_buffered_points {
+                warn!(
+                    "InfluxDB buffer over {} points, dropping {} unwritten points",
+                    self.writer.config.max_buffered_points, batch.points
+                );
+                *batch = Batch::default();
+            }
+            return Err(e);
+        }
+        *batch = Batch::default();
         Ok(())
     }
+
+    pub async fn flush(&self) -> Result<()> {
+        let mut batch = self.batch.lock().await;
+        self.write_batch(&mut batch).await
+    }
 }
 
 pub struct InfluxDbReporter {
@@ -76,10 +458,14 @@ pub struct InfluxDbReporter {
 }
 
 impl InfluxDbReporter {
-    pub fn new(config: &Config) -> Self {
-        Self {
-            uploader: InfluxUploader::new(config),
-        }
+    pub fn new(
+        config: &InfluxDbConfig,
+        round_info: SharedRoundInfo,
+        budget: Duration,
+    ) -> Result<Self> {
+        Ok(Self {
+            uploader: InfluxUploader::new(config, round_info, budget)?,
+        })
     }
 }
 
@@ -92,6 +478,24 @@ impl ProbeReporter for InfluxDbReporter {
             .map_err(|e| anyhow::anyhow!("InfluxDB upload failed: {}", e))
     }
 
+    async fn report_partial(&self, result: &ProbeResult) -> Result<()> {
+        self.uploader
+            .upload_partial(result)
+            .await
+            .map_err(|e| anyhow::anyhow!("InfluxDB upload failed: {}", e))
+    }
+
+    async fn flush(&self) -> Result<()> {
+        self.uploader
+            .flush()
+            .await
+            .map_err(|e| anyhow::anyhow!("InfluxDB final flush failed: {}", e))
+    }
+
+    fn is_streaming(&self) -> bool {
+        self.uploader.writer.config.stream
+    }
+
     fn name(&self) -> &str {
         "InfluxDB"
     }
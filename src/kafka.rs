@@ -0,0 +1,94 @@
+use crate::config::KafkaConfig;
+use crate::probe_result::ProbeResult;
+use crate::reporter::ProbeReporter;
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures::future::join_all;
+use rdkafka::ClientConfig;
+use rdkafka::producer::{FutureProducer, FutureRecord, Producer};
+use serde::Serialize;
+use std::time::Duration;
+
+/// One record per proxy, keyed by proxy name so a proxy's history stays in
+/// one partition.
+#[derive(Serialize)]
+struct Record<'a> {
+    #[serde(flatten)]
+    result: &'a ProbeResult,
+    node: &'a str,
+    round_timestamp: DateTime<Utc>,
+}
+
+pub struct KafkaReporter {
+    producer: FutureProducer,
+    topic: String,
+    node_name: String,
+    timeout: Duration,
+}
+
+impl KafkaReporter {
+    pub fn new(config: &KafkaConfig) -> Result<Self> {
+        let mut client = ClientConfig::new();
+        client.set("bootstrap.servers", &config.brokers);
+        for (key, value) in &config.properties {
+            client.set(key, value);
+        }
+
+        Ok(Self {
+            producer: client.create()?,
+            topic: config.topic.clone(),
+            node_name: config.node_name.clone(),
+            timeout: Duration::from_secs(config.timeout),
+        })
+    }
+}
+
+#[async_trait]
+impl ProbeReporter for KafkaReporter {
+    async fn report(&self, results: &[ProbeResult]) -> Result<()> {
+        let round_timestamp = Utc::now();
+        let payloads = results
+            .iter()
+            .map(|result| {
+                serde_json::to_string(&Record {
+                    result,
+                    node: &self.node_name,
+                    round_timestamp,
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let deliveries = results.iter().zip(&payloads).map(|(result, payload)| {
+            self.producer.send(
+                FutureRecord::to(&self.topic)
+                    .key(&result.name)
+                    .payload(payload),
+                self.timeout,
+            )
+        });
+
+        let failed = join_all(deliveries)
+            .await
+            .into_iter()
+            .filter(|delivery| delivery.is_err())
+            .count();
+        if failed > 0 {
+            return Err(anyhow::anyhow!(
+                "{} of {} Kafka records were not delivered",
+                failed,
+                results.len()
+            ));
+        }
+        Ok(())
+    }
+
+    async fn flush(&self) -> Result<()> {
+        self.producer.flush(self.timeout)?;
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "Kafka"
+    }
+}
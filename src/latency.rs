@@ -0,0 +1,48 @@
+use std::collections::VecDeque;
+
+/// The most recent delays of one proxy, for rolling percentiles. Failed
+/// probes have no delay and are not counted.
+pub struct LatencyWindow {
+    capacity: usize,
+    delays: VecDeque<u64>,
+}
+
+impl LatencyWindow {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            delays: VecDeque::new(),
+        }
+    }
+
+    pub fn record(&mut self, delay_ms: u64) {
+        if self.delays.len() == self.capacity {
+            self.delays.pop_front();
+        }
+        self.delays.push_back(delay_ms);
+    }
+
+    /// Change the capacity, dropping the oldest delays that no longer fit.
+    pub fn resize(&mut self, capacity: usize) {
+        self.capacity = capacity.max(1);
+        while self.delays.len() > self.capacity {
+            self.delays.pop_front();
+        }
+    }
+
+    /// Recorded delays, oldest first.
+    pub fn delays(&self) -> impl Iterator<Item = u64> + '_ {
+        self.delays.iter().copied()
+    }
+
+    /// Nearest-rank p50, p95 and p99, or `None` with no delays yet.
+    pub fn percentiles(&self) -> Option<[u64; 3]> {
+        if self.delays.is_empty() {
+            return None;
+        }
+        let mut sorted: Vec<u64> = self.delays.iter().copied().collect();
+        sorted.sort_unstable();
+        let rank = |p: usize| sorted[(sorted.len() * p).div_ceil(100).max(1) - 1];
+        Some([rank(50), rank(95), rank(99)])
+    }
+}
@@ -0,0 +1,119 @@
+//! Protocol-aware health checking for Clash subscriptions.
+//!
+//! Proxies are probed through real protocol handshakes via clash-rs rather
+//! than plain TCP connects. The `clashprobe` binary is a thin CLI over this
+//! crate; embed the same core with [`ProbeEngine`] and your own
+//! [`ProbeReporter`]s.
+//!
+//! Probe every proxy of a subscription once:
+//!
+//! ```no_run
+//! use clashprobe::{Config, ProbeEngine, parse_clash_subscription};
+//!
+//! # async fn example() -> anyhow::Result<()> {
+//! clash_lib::setup_default_crypto_provider();
+//!
+//! let config = Config::default();
+//! let content = clashprobe::subscription::fetch_subscription("https://example.com/sub").await?;
+//! let proxies = parse_clash_subscription(&content)?;
+//!
+//! let engine = ProbeEngine::build(config, proxies).await?;
+//! for result in engine.probe_now(None).await {
+//!     println!("{}: alive={} delay={:?}", result.name, result.alive, result.delay_ms);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Or hand results to a custom reporter on every round:
+//!
+//! ```no_run
+//! use anyhow::Result;
+//! use async_trait::async_trait;
+//! use clashprobe::{ProbeReporter, ProbeResult};
+//!
+//! struct Printer;
+//!
+//! #[async_trait]
+//! impl ProbeReporter for Printer {
+//!     async fn report(&self, results: &[ProbeResult]) -> Result<()> {
+//!         let alive = results.iter().filter(|r| r.alive).count();
+//!         println!("{}/{} alive", alive, results.len());
+//!         Ok(())
+//!     }
+//!
+//!     fn name(&self) -> &str {
+//!         "printer"
+//!     }
+//! }
+//!
+//! # async fn example(mut engine: clashprobe::ProbeEngine) -> Result<()> {
+//! engine.register_reporter(Box::new(Printer));
+//! engine.run(clashprobe::shutdown::listen()).await
+//! # }
+//! ```
+
+pub mod agent;
+mod auth;
+mod cert;
+pub mod cli;
+pub mod clickhouse;
+pub mod config;
+pub mod csv_file;
+pub mod dingtalk;
+pub mod discord;
+pub mod dns;
+pub mod elasticsearch;
+pub mod email;
+pub mod export;
+pub mod feishu;
+pub mod filter;
+pub mod fleet;
+pub mod gotify;
+pub mod graphite;
+pub mod group;
+pub mod heartbeat;
+pub mod history;
+pub mod incident;
+pub mod influxdb;
+pub mod kafka;
+mod latency;
+pub mod loki;
+pub mod matrix;
+mod mihomo;
+pub mod nats;
+pub mod ndjson;
+pub mod otlp;
+pub mod parser;
+pub mod postgres;
+pub mod probe_engine;
+pub mod probe_result;
+pub mod pushover;
+pub mod redis;
+pub mod region;
+mod relay;
+pub mod reload;
+pub mod remote_write;
+pub mod reporter;
+pub mod retry;
+mod scheduler;
+pub mod shutdown;
+pub mod slack;
+mod state;
+pub mod statsd;
+pub mod subscription;
+mod summary;
+pub mod telegram;
+mod tls;
+pub mod tui;
+mod udp;
+mod uptime;
+mod validator;
+pub mod web;
+pub mod wecom;
+
+pub use config::Config;
+pub use parser::{ParsedProxy, parse_clash_subscription};
+pub use probe_engine::ProbeEngine;
+pub use probe_result::{ErrorKind, ProbeResult};
+pub use reporter::ProbeReporter;
@@ -0,0 +1,93 @@
+use crate::config::LokiConfig;
+use crate::probe_result::ProbeResult;
+use crate::reporter::ProbeReporter;
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::Utc;
+use serde_json::{Value, json};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// Pushes one JSON log line per proxy per round to Loki. Lines are split
+/// into streams by node, protocol and alive so LogQL can select on them
+/// without parsing; everything else stays in the line for `| json`.
+pub struct LokiReporter {
+    client: reqwest::Client,
+    config: LokiConfig,
+}
+
+impl LokiReporter {
+    pub fn new(config: &LokiConfig) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.timeout))
+            .build()?;
+        Ok(Self {
+            client,
+            config: config.clone(),
+        })
+    }
+
+    fn body(&self, results: &[ProbeResult]) -> Result<Value> {
+        // Loki wants nanosecond timestamps as strings.
+        let timestamp = Utc::now()
+            .timestamp_nanos_opt()
+            .unwrap_or_default()
+            .to_string();
+
+        let mut streams: BTreeMap<(&str, bool), Vec<Value>> = BTreeMap::new();
+        for result in results {
+            let line = serde_json::to_string(result)?;
+            streams
+                .entry((&result.protocol, result.alive))
+                .or_default()
+                .push(json!([timestamp, line]));
+        }
+
+        let streams: Vec<Value> = streams
+            .into_iter()
+            .map(|((protocol, alive), values)| {
+                let mut labels = self.config.labels.clone();
+                labels.insert("job".to_string(), "clashprobe".to_string());
+                labels.insert("node".to_string(), self.config.node_name.clone());
+                labels.insert("protocol".to_string(), protocol.to_string());
+                labels.insert("alive".to_string(), alive.to_string());
+                json!({ "stream": labels, "values": values })
+            })
+            .collect();
+        Ok(json!({ "streams": streams }))
+    }
+}
+
+#[async_trait]
+impl ProbeReporter for LokiReporter {
+    async fn report(&self, results: &[ProbeResult]) -> Result<()> {
+        if results.is_empty() {
+            return Ok(());
+        }
+
+        let url = format!("{}/loki/api/v1/push", self.config.url.trim_end_matches('/'));
+        let mut request = self.client.post(url).json(&self.body(results)?);
+        if let Some(tenant_id) = &self.config.tenant_id {
+            request = request.header("X-Scope-OrgID", tenant_id);
+        }
+        if let Some(user) = &self.config.basic_auth_user {
+            request = request.basic_auth(user, self.config.basic_auth_password.as_ref());
+        }
+
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let message = response.text().await.unwrap_or_default();
+            return Err(anyhow::anyhow!(
+                "Loki push failed: {} {}",
+                status,
+                message.trim()
+            ));
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "Loki"
+    }
+}
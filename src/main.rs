@@ -1,27 +1,45 @@
-mod config;
-mod influxdb;
-mod parser;
-mod probe_engine;
-mod probe_result;
-mod reporter;
-mod subscription;
-mod web;
-
 use anyhow::Result;
-use clap::Parser;
-use clash_lib::{
-    ProxyManager, app::dns::SystemResolver, app::outbound::manager::OutboundManager,
-    setup_default_crypto_provider,
-};
-use std::sync::Arc;
-use tracing::{error, info};
-
-use config::WorkMode;
-use influxdb::InfluxDbReporter;
-use parser::parse_clash_subscription;
-use probe_engine::ProbeEngine;
-use subscription::fetch_subscription;
-use web::{WebReporter, start_web_server};
+use clap::{Parser, Subcommand, ValueEnum};
+use clash_lib::setup_default_crypto_provider;
+use std::{process::ExitCode, sync::Arc, time::Duration};
+use tracing::{error, info, warn};
+
+use clashprobe::agent::AgentReporter;
+use clashprobe::cli::{self, CliReporter, OutputFormat};
+use clashprobe::clickhouse::ClickHouseReporter;
+use clashprobe::config::{Config, ConfigOverrides, WorkMode};
+use clashprobe::csv_file::CsvFileReporter;
+use clashprobe::dingtalk::DingTalkReporter;
+use clashprobe::discord::DiscordReporter;
+use clashprobe::elasticsearch::ElasticsearchReporter;
+use clashprobe::email::EmailReporter;
+use clashprobe::export;
+use clashprobe::feishu::FeishuReporter;
+use clashprobe::gotify::GotifyReporter;
+use clashprobe::graphite::GraphiteReporter;
+use clashprobe::heartbeat::HeartbeatReporter;
+use clashprobe::incident::IncidentReporter;
+use clashprobe::influxdb::InfluxDbReporter;
+use clashprobe::kafka::KafkaReporter;
+use clashprobe::loki::LokiReporter;
+use clashprobe::matrix::MatrixReporter;
+use clashprobe::nats::NatsReporter;
+use clashprobe::ndjson::NdjsonReporter;
+use clashprobe::otlp::OtlpReporter;
+use clashprobe::parser::{parse_clash_subscription, parse_clash_subscription_with_stats};
+use clashprobe::postgres::PostgresReporter;
+use clashprobe::probe_engine::ProbeEngine;
+use clashprobe::pushover::PushoverReporter;
+use clashprobe::redis::RedisReporter;
+use clashprobe::remote_write::RemoteWriteReporter;
+use clashprobe::slack::SlackReporter;
+use clashprobe::statsd::StatsdReporter;
+use clashprobe::subscription::{fetch_subscription, load_subscription};
+use clashprobe::telegram::TelegramBot;
+use clashprobe::tui::TuiReporter;
+use clashprobe::web::{WebReporter, start_web_server};
+use clashprobe::wecom::WeComReporter;
+use clashprobe::{reload, shutdown};
 
 #[derive(Parser, Debug)]
 #[command(name = "clashprobe")]
@@ -30,26 +48,162 @@ use web::{WebReporter, start_web_server};
 )]
 struct Args {
     /// Config path
-    #[arg(long, default_value = "config.toml")]
+    #[arg(long, default_value = "config.toml", global = true)]
     config: String,
 
-    /// Generate config
-    #[arg(long, default_value = "false")]
-    generate_config: bool,
+    #[command(flatten)]
+    overrides: ConfigOverrides,
+
+    /// Defaults to `serve`
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Probe every proxy once and print the results
+    Probe {
+        #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
+
+        /// Write results to this file instead of stdout
+        #[arg(long)]
+        output: Option<String>,
+
+        /// Exit with 1 unless at least this many proxies are alive
+        #[arg(long, default_value_t = 1)]
+        min_alive: usize,
+
+        /// Exit with 1 unless at least this percentage of proxies is alive
+        #[arg(long, default_value_t = 0.0)]
+        min_alive_pct: f64,
+    },
+    /// Probe continuously and feed the reporters enabled by work_mode
+    Serve,
+    /// Probe once and write the alive proxies, fastest first, as a new
+    /// subscription
+    Export {
+        /// Keep only the fastest N alive proxies
+        #[arg(long)]
+        top: Option<usize>,
+
+        #[arg(long, value_enum, default_value_t = ExportFormat::Clash)]
+        format: ExportFormat,
+
+        /// Output file, stdout if omitted
+        #[arg(long)]
+        out: Option<String>,
+    },
+    /// Check the config, and optionally the subscription, without probing
+    Validate {
+        /// Also fetch and parse the subscription
+        #[arg(long)]
+        subscription: bool,
+    },
+    /// Write a default config to the --config path
+    GenerateConfig,
 }
 
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum ExportFormat {
+    /// Clash YAML with a selector group
+    Clash,
+    /// Base64 URI list
+    Uri,
+}
+
+/// Exit codes: 0 success, 1 health check failed, 2 config or fetch error.
+const EXIT_UNHEALTHY: u8 = 1;
+const EXIT_ERROR: u8 = 2;
+
 #[tokio::main]
-async fn main() -> Result<()> {
-    let args = Args::parse();
+async fn main() -> ExitCode {
+    match run(Args::parse()).await {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            ExitCode::from(EXIT_ERROR)
+        }
+    }
+}
 
-    if args.generate_config {
-        let default_toml = config::Config::generate_default_toml();
-        // Write to config.toml
-        std::fs::write(args.config, default_toml)?;
-        return Ok(());
+async fn run(args: Args) -> Result<ExitCode> {
+    match args.command.unwrap_or(Command::Serve) {
+        Command::GenerateConfig => {
+            std::fs::write(&args.config, Config::generate_default_toml())?;
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Validate { subscription } => {
+            let ok = validate(&args.config, &args.overrides, subscription).await;
+            Ok(if ok {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::from(EXIT_ERROR)
+            })
+        }
+        Command::Probe {
+            format,
+            output,
+            min_alive,
+            min_alive_pct,
+        } => {
+            let config = load_config(&args.config, &args.overrides)?;
+            let Some(engine) = load_engine(&config).await? else {
+                return Err(anyhow::anyhow!("No valid proxies found in subscription"));
+            };
+            let results = engine.probe_now(None).await;
+            write_output(output.as_deref(), &cli::render(&results, format)?)?;
+
+            let alive = results.iter().filter(|r| r.alive).count();
+            let alive_pct = 100.0 * alive as f64 / results.len().max(1) as f64;
+            if alive >= min_alive && alive_pct >= min_alive_pct {
+                Ok(ExitCode::SUCCESS)
+            } else {
+                info!(
+                    "{} proxies alive ({:.1}%), below --min-alive {} / --min-alive-pct {}",
+                    alive, alive_pct, min_alive, min_alive_pct
+                );
+                Ok(ExitCode::from(EXIT_UNHEALTHY))
+            }
+        }
+        Command::Export { top, format, out } => {
+            let config = load_config(&args.config, &args.overrides)?;
+            let Some(engine) = load_engine(&config).await? else {
+                return Err(anyhow::anyhow!("No valid proxies found in subscription"));
+            };
+            let results = engine.probe_now(None).await;
+            let proxy_configs = engine.proxy_configs().read().unwrap().clone();
+            let proxies = export::alive_configs(&results, &proxy_configs, top);
+            let content = match format {
+                ExportFormat::Clash => export::clash_yaml(&proxies)?,
+                ExportFormat::Uri => export::uri_list(&proxies),
+            };
+            write_output(out.as_deref(), &content)?;
+            info!("Exported {} alive proxies", proxies.len());
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Serve => {
+            let config = load_config(&args.config, &args.overrides)?;
+            serve(config, args.config, args.overrides).await?;
+            Ok(ExitCode::SUCCESS)
+        }
     }
+}
 
-    let config = crate::config::Config::load_from_file(args.config.as_str()).unwrap();
+/// Write to `path`, or stdout if there is none.
+fn write_output(path: Option<&str>, content: &str) -> Result<()> {
+    match path {
+        Some(path) => std::fs::write(path, content)?,
+        None => print!("{}", content),
+    }
+    Ok(())
+}
+
+/// Load and validate the config, then set up logging and TLS.
+fn load_config(path: &str, overrides: &ConfigOverrides) -> Result<Config> {
+    let mut config = Config::load_from_file(path)
+        .map_err(|e| anyhow::anyhow!("Failed to load config {}: {}", path, e))?;
+    overrides.apply(&mut config);
 
     // Initialize logging
     let level = if config.main.verbose {
@@ -58,8 +212,18 @@ async fn main() -> Result<()> {
         tracing::Level::INFO
     };
 
+    // Logs go to stderr so probe/export output on stdout stays clean, and
+    // nowhere while the TUI owns the terminal
+    let quiet = config.main.work_mode.contains(WorkMode::TUI);
     tracing_subscriber::fmt()
         .with_max_level(level)
+        .with_writer(move || -> Box<dyn std::io::Write> {
+            if quiet {
+                Box::new(std::io::sink())
+            } else {
+                Box::new(std::io::stderr())
+            }
+        })
         .with_target(false)
         .with_thread_ids(false)
         .with_file(false)
@@ -67,9 +231,19 @@ async fn main() -> Result<()> {
         .compact()
         .init();
 
+    config
+        .validate()
+        .map_err(|e| anyhow::anyhow!("Invalid configuration: {}", e))?;
+
     // Setup crypto provider for TLS
     setup_default_crypto_provider();
 
+    Ok(config)
+}
+
+/// Fetch and parse the subscription and build an engine for it. `None` if
+/// the subscription has no usable proxies.
+async fn load_engine(config: &Config) -> Result<Option<ProbeEngine>> {
     info!("ClashProbe starting...");
     info!(
         "Fetching subscription from: {}",
@@ -77,54 +251,317 @@ async fn main() -> Result<()> {
     );
 
     // Fetch subscription
-    let subscription_content = fetch_subscription(&config.main.subscription_url).await?;
-    info!("Subscription fetched successfully");
+    let subscription = load_subscription(&config.main).await?;
+    info!("Subscription loaded");
 
     // Parse proxies from subscription using proper Clash parsing
-    let proxies = parse_clash_subscription(&subscription_content)?;
-    info!("Parsed {} proxies from subscription", proxies.len());
+    let (proxies, parse_stats) =
+        parse_clash_subscription_with_stats(&subscription.content, &config.tls_verify)?;
+    info!(
+        "Parsed {} proxies from subscription ({} entries skipped)",
+        proxies.len(),
+        parse_stats.skipped()
+    );
 
     if proxies.is_empty() {
         error!("No valid proxies found in subscription");
-        return Ok(());
+        return Ok(None);
+    }
+    if proxies.len() < config.main.min_proxies {
+        warn!(
+            "Subscription has {} proxies, fewer than min_proxies = {}; probing them anyway",
+            proxies.len(),
+            config.main.min_proxies
+        );
     }
 
     // Create outbound handlers from proxy configs using Clash logic
-    let outbound_handlers = OutboundManager::load_plain_outbounds(proxies);
-    info!("Loaded {} outbound handlers", outbound_handlers.len());
+    let mut engine = ProbeEngine::build(config.clone(), proxies).await?;
+    engine.set_parse_stats(parse_stats);
+    engine.set_subscription_info(subscription.info);
+    engine.set_subscription_validators(subscription.validators);
+    info!("Loaded {} outbound handlers", engine.proxy_count());
 
-    // Initialize DNS resolver
-    let dns_resolver = Arc::new(
-        SystemResolver::new(false)
-            .map_err(|e| anyhow::anyhow!("Failed to create DNS resolver: {}", e))?,
-    );
+    Ok(Some(engine))
+}
 
-    // Initialize proxy manager for health checking
-    let proxy_manager = ProxyManager::new(dns_resolver);
+async fn serve(config: Config, config_path: String, overrides: ConfigOverrides) -> Result<()> {
+    let Some(mut engine) = load_engine(&config).await? else {
+        return Ok(());
+    };
 
-    config
-        .main
-        .work_mode
-        .validate()
-        .map_err(|e| anyhow::anyhow!("Invalid work mode configuration: {}", e))?;
+    let (trigger, shutdown) = shutdown::listen_with_trigger();
+    reload::watch_sighup(config_path, overrides, engine.shared_config());
+    let mut web_server = None;
+    let mut tui = None;
+
+    if config.main.work_mode.contains(WorkMode::CLI) {
+        engine.register_reporter(Box::new(CliReporter));
+    }
+
+    if config.main.work_mode.contains(WorkMode::WEB)
+        && let Some(web_config) = &config.web
+    {
+        let commands = engine.command_sender();
+        let ingest = config
+            .controller
+            .as_ref()
+            .filter(|_| config.main.work_mode.contains(WorkMode::CONTROLLER));
+        let (app_state, server) = start_web_server(
+            web_config,
+            commands,
+            engine.proxy_configs(),
+            engine.reporter_stats(),
+            engine.round_info(),
+            engine.subscription_changes(),
+            engine.node_reports(),
+            ingest,
+            config.peers.as_ref(),
+            shutdown.clone(),
+        )
+        .await?;
+        engine.register_reporter(Box::new(WebReporter::new(Arc::new(app_state))));
+        web_server = Some(server);
+    }
+
+    if config.main.work_mode.contains(WorkMode::INFLUXDB)
+        && let Some(influxdb_config) = &config.influxdb
+    {
+        engine.register_reporter(Box::new(InfluxDbReporter::new(
+            influxdb_config,
+            engine.round_info(),
+            Duration::from_secs(config.main.reporter_timeout),
+        )?));
+    }
 
-    let mut engine = ProbeEngine::new(config.clone(), proxy_manager, outbound_handlers);
+    if config.main.work_mode.contains(WorkMode::REMOTE_WRITE)
+        && let Some(remote_write_config) = &config.remote_write
+    {
+        engine.register_reporter(Box::new(RemoteWriteReporter::new(remote_write_config)?));
+    }
 
-    if config.main.work_mode.contains(WorkMode::WEB) {
-        let app_state = Arc::new(start_web_server(config.web.port).await);
-        engine.register_reporter(Box::new(WebReporter::new(app_state)));
+    if config.main.work_mode.contains(WorkMode::OTLP)
+        && let Some(otlp_config) = &config.otlp
+    {
+        engine.register_reporter(Box::new(OtlpReporter::new(otlp_config)?));
     }
 
-    if config.main.work_mode.contains(WorkMode::INFLUXDB) {
-        engine.register_reporter(Box::new(InfluxDbReporter::new(&config)));
+    if config.main.work_mode.contains(WorkMode::GRAPHITE)
+        && let Some(graphite_config) = &config.graphite
+    {
+        engine.register_reporter(Box::new(GraphiteReporter::new(graphite_config)));
     }
 
-    if config.main.work_mode.contains(WorkMode::TELOXIDE) {
-        // TODO: Implement Teloxide reporter
-        error!("Teloxide mode not implemented yet");
+    if config.main.work_mode.contains(WorkMode::STATSD)
+        && let Some(statsd_config) = &config.statsd
+    {
+        engine.register_reporter(Box::new(StatsdReporter::new(statsd_config)));
     }
 
-    engine.run().await?;
+    if config.main.work_mode.contains(WorkMode::KAFKA)
+        && let Some(kafka_config) = &config.kafka
+    {
+        engine.register_reporter(Box::new(KafkaReporter::new(kafka_config)?));
+    }
+
+    if config.main.work_mode.contains(WorkMode::NATS)
+        && let Some(nats_config) = &config.nats
+    {
+        engine.register_reporter(Box::new(NatsReporter::connect(nats_config).await?));
+    }
+
+    if config.main.work_mode.contains(WorkMode::REDIS)
+        && let Some(redis_config) = &config.redis
+    {
+        engine.register_reporter(Box::new(RedisReporter::connect(redis_config).await?));
+    }
+
+    if config.main.work_mode.contains(WorkMode::POSTGRES)
+        && let Some(postgres_config) = &config.postgres
+    {
+        engine.register_reporter(Box::new(PostgresReporter::connect(postgres_config).await?));
+    }
+
+    if config.main.work_mode.contains(WorkMode::CLICKHOUSE)
+        && let Some(clickhouse_config) = &config.clickhouse
+    {
+        engine.register_reporter(Box::new(ClickHouseReporter::new(clickhouse_config)?));
+    }
 
+    if config.main.work_mode.contains(WorkMode::CSV)
+        && let Some(csv_config) = &config.csv
+    {
+        engine.register_reporter(Box::new(CsvFileReporter::new(csv_config)));
+    }
+
+    if config.main.work_mode.contains(WorkMode::NDJSON)
+        && let Some(ndjson_config) = &config.ndjson
+    {
+        engine.register_reporter(Box::new(NdjsonReporter::new(ndjson_config)));
+    }
+
+    if config.main.work_mode.contains(WorkMode::SLACK)
+        && let Some(slack_config) = &config.slack
+    {
+        engine.register_reporter(Box::new(SlackReporter::new(slack_config)?));
+    }
+
+    if config.main.work_mode.contains(WorkMode::DISCORD)
+        && let Some(discord_config) = &config.discord
+    {
+        engine.register_reporter(Box::new(DiscordReporter::new(discord_config)?));
+    }
+
+    if config.main.work_mode.contains(WorkMode::GOTIFY)
+        && let Some(gotify_config) = &config.gotify
+    {
+        engine.register_reporter(Box::new(GotifyReporter::new(gotify_config)?));
+    }
+
+    if config.main.work_mode.contains(WorkMode::PUSHOVER)
+        && let Some(pushover_config) = &config.pushover
+    {
+        engine.register_reporter(Box::new(PushoverReporter::new(pushover_config)?));
+    }
+
+    if config.main.work_mode.contains(WorkMode::EMAIL)
+        && let Some(email_config) = &config.email
+    {
+        engine.register_reporter(Box::new(EmailReporter::new(email_config)?));
+    }
+
+    if config.main.work_mode.contains(WorkMode::INCIDENT)
+        && let Some(incident_config) = &config.incident
+    {
+        engine.register_reporter(Box::new(IncidentReporter::new(incident_config)?));
+    }
+
+    if config.main.work_mode.contains(WorkMode::HEARTBEAT)
+        && let Some(heartbeat_config) = &config.heartbeat
+    {
+        engine.register_reporter(Box::new(HeartbeatReporter::new(heartbeat_config)?));
+    }
+
+    if config.main.work_mode.contains(WorkMode::LOKI)
+        && let Some(loki_config) = &config.loki
+    {
+        engine.register_reporter(Box::new(LokiReporter::new(loki_config)?));
+    }
+
+    if config.main.work_mode.contains(WorkMode::ELASTICSEARCH)
+        && let Some(elasticsearch_config) = &config.elasticsearch
+    {
+        engine.register_reporter(Box::new(
+            ElasticsearchReporter::connect(elasticsearch_config).await?,
+        ));
+    }
+
+    if config.main.work_mode.contains(WorkMode::MATRIX)
+        && let Some(matrix_config) = &config.matrix
+    {
+        engine.register_reporter(Box::new(MatrixReporter::new(matrix_config)?));
+    }
+
+    if config.main.work_mode.contains(WorkMode::FEISHU)
+        && let Some(feishu_config) = &config.feishu
+    {
+        engine.register_reporter(Box::new(FeishuReporter::new(feishu_config)?));
+    }
+
+    if config.main.work_mode.contains(WorkMode::DINGTALK)
+        && let Some(dingtalk_config) = &config.dingtalk
+    {
+        engine.register_reporter(Box::new(DingTalkReporter::new(dingtalk_config)?));
+    }
+
+    if config.main.work_mode.contains(WorkMode::WECOM)
+        && let Some(wecom_config) = &config.wecom
+    {
+        engine.register_reporter(Box::new(WeComReporter::new(wecom_config)?));
+    }
+
+    if config.main.work_mode.contains(WorkMode::AGENT)
+        && let Some(agent_config) = &config.agent
+    {
+        engine.register_reporter(Box::new(AgentReporter::new(agent_config)?));
+    }
+
+    if config.main.work_mode.contains(WorkMode::TUI) {
+        let (reporter, handle) = TuiReporter::start(trigger, shutdown.clone());
+        engine.register_reporter(Box::new(reporter));
+        tui = Some(handle);
+    }
+
+    if config.main.work_mode.contains(WorkMode::TELOXIDE)
+        && let Some(teloxide_config) = &config.teloxide
+    {
+        let commands = engine.command_sender();
+        engine.register_reporter(Box::new(TelegramBot::start(
+            teloxide_config,
+            commands,
+            shutdown.clone(),
+        )));
+    }
+
+    engine.run(shutdown).await?;
+
+    // The TUI must restore the terminal before the process exits.
+    if let Some(tui) = tui {
+        let _ = tui.await;
+    }
+
+    // Open SSE streams keep the graceful shutdown waiting, so don't wait forever.
+    if let Some(server) = web_server {
+        let _ = tokio::time::timeout(Duration::from_secs(5), server).await;
+    }
+
+    info!("ClashProbe stopped");
     Ok(())
 }
+
+/// Print a validation report for the config at `path`, and for its
+/// subscription if asked. Returns whether everything is usable.
+async fn validate(path: &str, overrides: &ConfigOverrides, subscription: bool) -> bool {
+    let mut config = match Config::load_from_file(path) {
+        Ok(config) => config,
+        Err(e) => {
+            println!("✗ {}: failed to load: {}", path, e);
+            return false;
+        }
+    };
+    overrides.apply(&mut config);
+
+    let problems = config.problems();
+    if problems.is_empty() {
+        println!("✓ {}: OK", path);
+    } else {
+        println!("✗ {}: {} problem(s) found", path, problems.len());
+        for problem in &problems {
+            println!("  - {}", problem);
+        }
+    }
+
+    if !subscription {
+        return problems.is_empty();
+    }
+
+    let url = &config.main.subscription_url;
+    let parsed = match fetch_subscription(url).await {
+        Ok(content) => parse_clash_subscription(&content),
+        Err(e) => Err(e),
+    };
+    match parsed {
+        Ok(proxies) if !proxies.is_empty() => {
+            println!("✓ {}: {} proxies", url, proxies.len());
+            problems.is_empty()
+        }
+        Ok(_) => {
+            println!("✗ {}: no valid proxies found", url);
+            false
+        }
+        Err(e) => {
+            println!("✗ {}: {}", url, e);
+            false
+        }
+    }
+}
@@ -0,0 +1,167 @@
+use crate::config::MatrixConfig;
+use crate::probe_result::ProbeResult;
+use crate::reporter::ProbeReporter;
+use crate::summary::{FASTEST, RoundSummary, StateTracker};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::Utc;
+use serde_json::{Value, json};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::sync::Mutex as AsyncMutex;
+
+/// Keeps one "current status" message in a Matrix room up to date by
+/// editing it every round, and posts a separate alert message whenever
+/// proxies die or recover so room members get notified.
+pub struct MatrixReporter {
+    client: reqwest::Client,
+    config: MatrixConfig,
+    state: Mutex<StateTracker>,
+    /// Event ID of the status message, once posted. Held across the edit so
+    /// two rounds never race to post it.
+    status_event: AsyncMutex<Option<String>>,
+    /// Transaction IDs must be unique per access token; prefix with the
+    /// startup time so a restart doesn't reuse them.
+    txn_prefix: i64,
+    txn_counter: AtomicU64,
+}
+
+impl MatrixReporter {
+    pub fn new(config: &MatrixConfig) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.timeout))
+            .build()?;
+        Ok(Self {
+            client,
+            config: config.clone(),
+            state: Mutex::new(StateTracker::default()),
+            status_event: AsyncMutex::new(None),
+            txn_prefix: Utc::now().timestamp_millis(),
+            txn_counter: AtomicU64::new(0),
+        })
+    }
+
+    fn room_url(&self, path: &str) -> String {
+        format!(
+            "{}/_matrix/client/v3/rooms/{}/{}",
+            self.config.homeserver.trim_end_matches('/'),
+            urlencoding::encode(&self.config.room_id),
+            path
+        )
+    }
+
+    /// Send an `m.room.message` event and return its event ID.
+    async fn send(&self, content: &Value) -> Result<String> {
+        let txn = self.txn_counter.fetch_add(1, Ordering::Relaxed);
+        let url = self.room_url(&format!(
+            "send/m.room.message/clashprobe-{}-{}",
+            self.txn_prefix, txn
+        ));
+        let response: Value = self
+            .client
+            .put(url)
+            .bearer_auth(&self.config.access_token)
+            .json(content)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        response["event_id"]
+            .as_str()
+            .map(str::to_string)
+            .context("Matrix send response has no event_id")
+    }
+
+    /// Add `event_id` to the room's pinned events, keeping existing pins.
+    async fn pin(&self, event_id: &str) -> Result<()> {
+        let url = self.room_url("state/m.room.pinned_events");
+        let response = self
+            .client
+            .get(&url)
+            .bearer_auth(&self.config.access_token)
+            .send()
+            .await?;
+        let mut pinned: Vec<Value> = if response.status() == reqwest::StatusCode::NOT_FOUND {
+            Vec::new()
+        } else {
+            let current: Value = response.error_for_status()?.json().await?;
+            current["pinned"].as_array().cloned().unwrap_or_default()
+        };
+        pinned.push(json!(event_id));
+
+        self.client
+            .put(url)
+            .bearer_auth(&self.config.access_token)
+            .json(&json!({ "pinned": pinned }))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    async fn update_status(&self, summary: &RoundSummary) -> Result<()> {
+        let text = summary.status_text(&self.config.node_name);
+        let content = json!({ "msgtype": "m.notice", "body": text });
+
+        let mut status_event = self.status_event.lock().await;
+        match status_event.as_deref() {
+            Some(event_id) => {
+                // Edits are a new event that replaces the original's content;
+                // clients without edit support show the starred fallback.
+                self.send(&json!({
+                    "msgtype": "m.notice",
+                    "body": format!("* {}", text),
+                    "m.new_content": content,
+                    "m.relates_to": { "rel_type": "m.replace", "event_id": event_id },
+                }))
+                .await?;
+            }
+            None => {
+                let event_id = self.send(&content).await?;
+                if self.config.pin_status {
+                    self.pin(&event_id).await?;
+                }
+                *status_event = Some(event_id);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ProbeReporter for MatrixReporter {
+    async fn report(&self, results: &[ProbeResult]) -> Result<()> {
+        let summary = self.state.lock().unwrap().summarize(results, FASTEST);
+        self.update_status(&summary).await?;
+
+        if summary.is_alert() {
+            let body = format!(
+                "clashprobe {}: {}\n{}",
+                self.config.node_name,
+                summary.severity().as_str(),
+                summary.alert_text()
+            );
+            self.send(&json!({ "msgtype": "m.text", "body": body }))
+                .await?;
+        }
+        self.state.lock().unwrap().record(results);
+        Ok(())
+    }
+
+    async fn report_notice(&self, text: &str) -> Result<()> {
+        let body = format!("clashprobe {}: {}", self.config.node_name, text);
+        self.send(&json!({ "msgtype": "m.text", "body": body }))
+            .await?;
+        Ok(())
+    }
+
+    async fn restore(&self, results: &[ProbeResult]) {
+        self.state.lock().unwrap().record(results);
+    }
+
+    fn name(&self) -> &str {
+        "Matrix"
+    }
+}
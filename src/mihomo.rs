@@ -0,0 +1,83 @@
+use crate::config::ExternalControllerConfig;
+use anyhow::Result;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use std::{io, time::Duration};
+
+/// Client for the delay test of a Clash or mihomo external controller.
+pub struct Controller {
+    client: reqwest::Client,
+    base: url::Url,
+    secret: String,
+}
+
+#[derive(Deserialize)]
+struct DelayResponse {
+    #[serde(default)]
+    delay: Option<u64>,
+    #[serde(default)]
+    message: Option<String>,
+}
+
+impl Controller {
+    pub fn new(config: &ExternalControllerConfig) -> Result<Self> {
+        let base = url::Url::parse(&config.url)
+            .map_err(|e| anyhow::anyhow!("Invalid external_controller.url: {}", e))?;
+        if base.cannot_be_a_base() {
+            return Err(anyhow::anyhow!(
+                "external_controller.url '{}' is not an HTTP URL",
+                config.url
+            ));
+        }
+        Ok(Self {
+            client: reqwest::Client::new(),
+            base,
+            secret: config.secret.clone(),
+        })
+    }
+
+    /// Have the instance test proxy `name` against `test_url`, the way its
+    /// own UI does. Errors are `io::Error`s so they classify like dialing
+    /// failures.
+    pub async fn delay(
+        &self,
+        name: &str,
+        test_url: &str,
+        timeout: Duration,
+    ) -> io::Result<Duration> {
+        let mut url = self.base.clone();
+        url.path_segments_mut()
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "controller URL has no path"))?
+            .pop_if_empty()
+            .extend(["proxies", name, "delay"]);
+        url.query_pairs_mut()
+            .append_pair("url", test_url)
+            .append_pair("timeout", &timeout.as_millis().to_string());
+
+        let mut request = self
+            .client
+            .get(url)
+            .timeout(timeout + Duration::from_secs(5));
+        if !self.secret.is_empty() {
+            request = request.bearer_auth(&self.secret);
+        }
+        let response = request.send().await.map_err(io::Error::other)?;
+        let status = response.status();
+        let body: DelayResponse = response.json().await.map_err(io::Error::other)?;
+        match body.delay {
+            Some(delay) if status.is_success() && delay > 0 => Ok(Duration::from_millis(delay)),
+            _ => Err(failure(status, body.message)),
+        }
+    }
+}
+
+/// The controller answers 504 for a timed-out test and 503 for any other
+/// failed one, with the reason in `message`.
+fn failure(status: StatusCode, message: Option<String>) -> io::Error {
+    let message = message.unwrap_or_else(|| format!("controller answered {}", status));
+    if status == StatusCode::GATEWAY_TIMEOUT || status == StatusCode::REQUEST_TIMEOUT {
+        io::Error::new(io::ErrorKind::TimedOut, message)
+    } else {
+        io::Error::other(message)
+    }
+}
@@ -0,0 +1,86 @@
+use crate::config::NatsConfig;
+use crate::probe_result::ProbeResult;
+use crate::reporter::ProbeReporter;
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+#[derive(Serialize)]
+struct RoundMessage<'a> {
+    node: &'a str,
+    timestamp: DateTime<Utc>,
+    results: &'a [ProbeResult],
+}
+
+enum Publisher {
+    Core(async_nats::Client),
+    JetStream(async_nats::jetstream::Context),
+}
+
+/// Publishes every round as one JSON message to a NATS subject.
+pub struct NatsReporter {
+    publisher: Publisher,
+    subject: String,
+    node_name: String,
+}
+
+impl NatsReporter {
+    pub async fn connect(config: &NatsConfig) -> Result<Self> {
+        let mut options = async_nats::ConnectOptions::new();
+        if let Some(token) = &config.token {
+            options = options.token(token.clone());
+        }
+        if let Some(path) = &config.credentials_file {
+            options = options.credentials_file(path).await?;
+        }
+        let client = options.connect(&config.url).await?;
+
+        let publisher = if config.jetstream {
+            Publisher::JetStream(async_nats::jetstream::new(client))
+        } else {
+            Publisher::Core(client)
+        };
+        Ok(Self {
+            publisher,
+            subject: config.subject.clone(),
+            node_name: config.node_name.clone(),
+        })
+    }
+}
+
+#[async_trait]
+impl ProbeReporter for NatsReporter {
+    async fn report(&self, results: &[ProbeResult]) -> Result<()> {
+        let payload = serde_json::to_vec(&RoundMessage {
+            node: &self.node_name,
+            timestamp: Utc::now(),
+            results,
+        })?;
+
+        match &self.publisher {
+            Publisher::Core(client) => {
+                client.publish(self.subject.clone(), payload.into()).await?;
+            }
+            Publisher::JetStream(context) => {
+                // The first await sends, the second waits for the stream's ack.
+                context
+                    .publish(self.subject.clone(), payload.into())
+                    .await?
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn flush(&self) -> Result<()> {
+        if let Publisher::Core(client) = &self.publisher {
+            client.flush().await?;
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "NATS"
+    }
+}
@@ -0,0 +1,140 @@
+use crate::config::NdjsonConfig;
+use crate::probe_result::ProbeResult;
+use crate::reporter::ProbeReporter;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use tokio::fs::{self, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+#[derive(Serialize)]
+struct Line<'a> {
+    #[serde(flatten)]
+    result: &'a ProbeResult,
+    node: &'a str,
+    round_timestamp: DateTime<Utc>,
+}
+
+/// Appends one JSON object per proxy per round to a file. Rotated files are
+/// renamed to `<path>.<UTC timestamp>` (plus `.gz` when compressed), so a
+/// shipper tailing `path` never sees a partially written file move.
+pub struct NdjsonReporter {
+    config: NdjsonConfig,
+    /// When the current file was started, for time-based rotation. Unknown
+    /// until the first round, so a file left over from a previous run counts
+    /// from startup.
+    started_at: Mutex<Option<DateTime<Utc>>>,
+}
+
+impl NdjsonReporter {
+    pub fn new(config: &NdjsonConfig) -> Self {
+        Self {
+            config: config.clone(),
+            started_at: Mutex::new(None),
+        }
+    }
+
+    async fn should_rotate(
+        &self,
+        path: &Path,
+        now: DateTime<Utc>,
+        started_at: DateTime<Utc>,
+    ) -> bool {
+        let Ok(metadata) = fs::metadata(path).await else {
+            return false;
+        };
+        if metadata.len() == 0 {
+            return false;
+        }
+        let too_big = self
+            .config
+            .max_size_mb
+            .is_some_and(|mb| metadata.len() >= mb * 1024 * 1024);
+        let too_old = self
+            .config
+            .rotate_interval
+            .is_some_and(|secs| (now - started_at).num_seconds() >= secs as i64);
+        too_big || too_old
+    }
+
+    async fn rotate(&self, path: &Path, now: DateTime<Utc>) -> Result<()> {
+        let rotated = PathBuf::from(format!(
+            "{}.{}",
+            path.display(),
+            now.format("%Y%m%dT%H%M%SZ")
+        ));
+        fs::rename(path, &rotated)
+            .await
+            .with_context(|| format!("failed to rotate {}", path.display()))?;
+        if self.config.compress {
+            tokio::task::spawn_blocking(move || compress(&rotated)).await??;
+        }
+        Ok(())
+    }
+}
+
+/// Replace `path` with `path.gz`.
+fn compress(path: &Path) -> Result<()> {
+    let gz_path = PathBuf::from(format!("{}.gz", path.display()));
+    let mut input = std::fs::File::open(path)?;
+    let mut encoder = GzEncoder::new(std::fs::File::create(&gz_path)?, Compression::default());
+    std::io::copy(&mut input, &mut encoder)?;
+    encoder.finish()?;
+    std::fs::remove_file(path)?;
+    Ok(())
+}
+
+#[async_trait]
+impl ProbeReporter for NdjsonReporter {
+    async fn report(&self, results: &[ProbeResult]) -> Result<()> {
+        let now = Utc::now();
+        let path = Path::new(&self.config.path);
+        let mut started_at = self.started_at.lock().await;
+
+        if self
+            .should_rotate(path, now, started_at.unwrap_or(now))
+            .await
+        {
+            self.rotate(path, now).await?;
+            *started_at = None;
+        }
+        if let Some(parent) = path.parent()
+            && !parent.as_os_str().is_empty()
+        {
+            fs::create_dir_all(parent).await?;
+        }
+
+        let mut out = Vec::new();
+        for result in results {
+            serde_json::to_writer(
+                &mut out,
+                &Line {
+                    result,
+                    node: &self.config.node_name,
+                    round_timestamp: now,
+                },
+            )?;
+            out.push(b'\n');
+        }
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await
+            .with_context(|| format!("failed to open {}", path.display()))?;
+        file.write_all(&out).await?;
+        file.flush().await?;
+        started_at.get_or_insert(now);
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "NDJSON"
+    }
+}
@@ -0,0 +1,93 @@
+use crate::config::{OtlpConfig, OtlpProtocol};
+use crate::probe_result::ProbeResult;
+use crate::reporter::ProbeReporter;
+use anyhow::Result;
+use async_trait::async_trait;
+use opentelemetry::KeyValue;
+use opentelemetry::metrics::{Gauge, MeterProvider};
+use opentelemetry_otlp::{MetricExporter, Protocol, WithExportConfig};
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::metrics::{PeriodicReader, SdkMeterProvider};
+use std::time::Duration;
+
+/// Records per-proxy gauges that the SDK exports to an OTLP collector on its
+/// own interval.
+pub struct OtlpReporter {
+    provider: SdkMeterProvider,
+    up: Gauge<u64>,
+    delay: Gauge<u64>,
+    loss: Gauge<f64>,
+}
+
+impl OtlpReporter {
+    pub fn new(config: &OtlpConfig) -> Result<Self> {
+        let exporter = match config.protocol {
+            OtlpProtocol::Grpc => MetricExporter::builder()
+                .with_tonic()
+                .with_endpoint(&config.endpoint)
+                .build()?,
+            OtlpProtocol::Http => MetricExporter::builder()
+                .with_http()
+                .with_protocol(Protocol::HttpBinary)
+                .with_endpoint(&config.endpoint)
+                .build()?,
+        };
+        let reader = PeriodicReader::builder(exporter)
+            .with_interval(Duration::from_secs(config.export_interval))
+            .build();
+        let resource = Resource::builder()
+            .with_service_name("clashprobe")
+            .with_attribute(KeyValue::new("clashprobe.node", config.node_name.clone()))
+            .build();
+        let provider = SdkMeterProvider::builder()
+            .with_reader(reader)
+            .with_resource(resource)
+            .build();
+
+        let meter = provider.meter("clashprobe");
+        Ok(Self {
+            up: meter
+                .u64_gauge("clashprobe.proxy.up")
+                .with_description("1 if the proxy passed the last probe")
+                .build(),
+            delay: meter
+                .u64_gauge("clashprobe.proxy.delay")
+                .with_unit("ms")
+                .build(),
+            loss: meter
+                .f64_gauge("clashprobe.proxy.loss")
+                .with_unit("%")
+                .build(),
+            provider,
+        })
+    }
+}
+
+#[async_trait]
+impl ProbeReporter for OtlpReporter {
+    async fn report(&self, results: &[ProbeResult]) -> Result<()> {
+        for result in results {
+            let attributes = [
+                KeyValue::new("proxy", result.name.clone()),
+                KeyValue::new("protocol", result.protocol.clone()),
+                KeyValue::new("server", result.server.clone()),
+            ];
+            self.up.record(result.alive as u64, &attributes);
+            self.loss.record(result.loss_pct, &attributes);
+            if let Some(delay_ms) = result.delay_ms {
+                self.delay.record(delay_ms, &attributes);
+            }
+        }
+        Ok(())
+    }
+
+    async fn flush(&self) -> Result<()> {
+        self.provider
+            .shutdown()
+            .map_err(|e| anyhow::anyhow!("OTLP export failed: {}", e))
+    }
+
+    fn name(&self) -> &str {
+        "OTLP"
+    }
+}
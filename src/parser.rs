@@ -1,60 +1,230 @@
 use anyhow::Result;
-use base64::{Engine, prelude::BASE64_STANDARD};
 use clash_lib::config::internal::proxy::OutboundProxyProtocol;
+use regex::Regex;
+use serde::Serialize;
 use serde_json;
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
 use tracing::{debug, warn};
 use url;
 use urlencoding;
 
-use crate::subscription::is_base64;
+use crate::config::{TlsVerifyConfig, TlsVerifyPolicy};
+use crate::subscription::{DOCUMENT_SEPARATOR, decode_base64};
 
-/// Parse Clash subscription content properly using clash-lib structures
-pub fn parse_clash_subscription(content: &str) -> Result<Vec<OutboundProxyProtocol>> {
-    // Try to decode base64 if needed
-    let decoded_content = if is_base64(content) {
-        match BASE64_STANDARD.decode(content.trim()) {
-            Ok(decoded) => String::from_utf8(decoded)?,
-            Err(_) => content.to_string(),
-        }
-    } else {
-        content.to_string()
+/// Raw Clash proxy config as found in (or converted from) the subscription.
+pub type ProxyConfig = HashMap<String, serde_yaml::Value>;
+
+/// A parsed proxy: the raw config kept for exporting, plus the clash-lib
+/// protocol built from it.
+pub struct ParsedProxy {
+    pub name: String,
+    /// See [`proxy_id`].
+    pub id: String,
+    pub raw: ProxyConfig,
+    pub protocol: OutboundProxyProtocol,
+}
+
+impl ParsedProxy {
+    fn from_config(raw: ProxyConfig) -> Result<Self> {
+        let name = raw
+            .get("name")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow::anyhow!("Proxy config has no name"))?
+            .to_string();
+        let protocol = OutboundProxyProtocol::try_from(raw.clone())
+            .map_err(|e| anyhow::anyhow!("Failed to parse proxy config: {}", e))?;
+        Ok(Self {
+            name,
+            id: proxy_id(&raw),
+            raw,
+            protocol,
+        })
+    }
+}
+
+/// Stable identity of a proxy: a short hash of its type, server, port and
+/// UUID. Providers rename nodes freely ("HK-01 | 2x" becoming "HK-01 | 3x"),
+/// but rarely touch these.
+pub fn proxy_id(raw: &ProxyConfig) -> String {
+    let field = |key: &str| match raw.get(key) {
+        Some(serde_yaml::Value::String(s)) => s.clone(),
+        Some(serde_yaml::Value::Number(n)) => n.to_string(),
+        _ => String::new(),
     };
+    let key = format!(
+        "{}|{}|{}|{}",
+        field("type"),
+        field("server"),
+        field("port"),
+        field("uuid")
+    );
+    Sha256::digest(key.as_bytes())
+        .iter()
+        .take(8)
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Server and port a proxy connects to, as configured.
+pub fn endpoint(raw: &ProxyConfig) -> Option<(String, u16)> {
+    let server = raw.get("server")?.as_str()?.to_string();
+    let port = match raw.get("port")? {
+        serde_yaml::Value::Number(n) => u16::try_from(n.as_u64()?).ok()?,
+        serde_yaml::Value::String(s) => s.parse().ok()?,
+        _ => return None,
+    };
+    Some((server, port))
+}
+
+/// How many subscription entries turned into usable proxies.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct ParseStats {
+    pub entries: usize,
+    pub parsed: usize,
+}
+
+impl ParseStats {
+    pub fn skipped(&self) -> usize {
+        self.entries - self.parsed
+    }
+}
+
+/// The `[tls_verify]` config with its regexes compiled.
+#[derive(Default)]
+struct TlsVerifier {
+    policy: TlsVerifyPolicy,
+    skip: Vec<Regex>,
+    verify: Vec<Regex>,
+}
+
+impl TlsVerifier {
+    fn new(config: &TlsVerifyConfig) -> Result<Self> {
+        let compile = |patterns: &[String]| -> Result<Vec<Regex>> {
+            Ok(patterns
+                .iter()
+                .map(|p| Regex::new(p))
+                .collect::<Result<_, _>>()?)
+        };
+        Ok(Self {
+            policy: config.policy,
+            skip: compile(&config.skip)?,
+            verify: compile(&config.verify)?,
+        })
+    }
+
+    /// Set `skip-cert-verify` on a TLS proxy as configured.
+    fn apply(&self, mut raw: ProxyConfig) -> ProxyConfig {
+        if !is_tls(&raw) {
+            return raw;
+        }
+        let name = raw.get("name").and_then(|v| v.as_str()).unwrap_or_default();
+        let matches = |regexes: &[Regex]| regexes.iter().any(|re| re.is_match(name));
+        let skip = if matches(&self.verify) {
+            Some(false)
+        } else if matches(&self.skip) {
+            Some(true)
+        } else {
+            match self.policy {
+                TlsVerifyPolicy::Subscription => None,
+                TlsVerifyPolicy::Verify => Some(false),
+                TlsVerifyPolicy::Skip => Some(true),
+            }
+        };
+        if let Some(skip) = skip {
+            raw.insert("skip-cert-verify".to_string(), skip.into());
+        }
+        raw
+    }
+}
+
+/// Whether the proxy speaks TLS to its server, so has a certificate to check.
+fn is_tls(raw: &ProxyConfig) -> bool {
+    let kind = raw.get("type").and_then(|v| v.as_str()).unwrap_or_default();
+    raw.get("tls").and_then(|v| v.as_bool()).unwrap_or(false)
+        || matches!(kind, "trojan" | "hysteria" | "hysteria2" | "tuic")
+}
+
+/// Parse Clash subscription content properly using clash-lib structures
+pub fn parse_clash_subscription(content: &str) -> Result<Vec<ParsedProxy>> {
+    parse_clash_subscription_with_stats(content, &TlsVerifyConfig::default())
+        .map(|(proxies, _)| proxies)
+}
+
+/// Like [`parse_clash_subscription`], also counting entries that failed to
+/// parse or use an unsupported protocol, and applying `tls_verify`.
+/// Documents merged from several files are parsed one by one, each in its
+/// own format.
+pub fn parse_clash_subscription_with_stats(
+    content: &str,
+    tls_verify: &TlsVerifyConfig,
+) -> Result<(Vec<ParsedProxy>, ParseStats)> {
+    let verifier = TlsVerifier::new(tls_verify)?;
+    let mut proxies = Vec::new();
+    let mut stats = ParseStats::default();
+    for document in content.split(DOCUMENT_SEPARATOR) {
+        let (parsed, document_stats) = parse_document(document, &verifier)?;
+        proxies.extend(parsed);
+        stats.entries += document_stats.entries;
+        stats.parsed += document_stats.parsed;
+    }
+    Ok((proxies, stats))
+}
+
+/// One Clash YAML config or list of proxy URLs, either maybe in base64.
+fn parse_document(content: &str, verifier: &TlsVerifier) -> Result<(Vec<ParsedProxy>, ParseStats)> {
+    let decoded_content = decode_document(content);
 
     // First try to parse as YAML (Clash config format)
     if let Ok(clash_config) = serde_yaml::from_str::<serde_yaml::Value>(&decoded_content) {
         if let Some(proxies) = clash_config.get("proxies").and_then(|p| p.as_sequence()) {
             let mut outbound_proxies = Vec::new();
             for proxy_value in proxies {
-                if let Ok(proxy) = parse_clash_proxy_from_yaml(proxy_value) {
+                if let Ok(proxy) = parse_clash_proxy_from_yaml(proxy_value)
+                    .map(|raw| verifier.apply(raw))
+                    .and_then(ParsedProxy::from_config)
+                {
                     outbound_proxies.push(proxy);
                 }
             }
             if !outbound_proxies.is_empty() {
-                return Ok(outbound_proxies);
+                let stats = ParseStats {
+                    entries: proxies.len(),
+                    parsed: outbound_proxies.len(),
+                };
+                return Ok((outbound_proxies, stats));
             }
         }
     }
 
     // Fall back to parsing URLs line by line (subscription format)
     let mut proxies = Vec::new();
+    let mut entries = 0;
     for line in decoded_content.lines() {
         let line = line.trim();
         if line.is_empty() || line.starts_with('#') {
             continue;
         }
+        entries += 1;
 
-        if let Ok(proxy) = parse_proxy_url_to_clash_config(line) {
+        if let Ok(proxy) = parse_proxy_url_to_clash_config(line)
+            .map(|raw| verifier.apply(raw))
+            .and_then(ParsedProxy::from_config)
+        {
             proxies.push(proxy);
         } else {
             debug!("Failed to parse proxy URL: {}", line);
         }
     }
 
-    Ok(proxies)
+    let stats = ParseStats {
+        entries,
+        parsed: proxies.len(),
+    };
+    Ok((proxies, stats))
 }
 
-fn parse_clash_proxy_from_yaml(value: &serde_yaml::Value) -> Result<OutboundProxyProtocol> {
+fn parse_clash_proxy_from_yaml(value: &serde_yaml::Value) -> Result<ProxyConfig> {
     // Convert YAML value to a HashMap for easier processing
     let map = value
         .as_mapping()
@@ -67,11 +237,19 @@ fn parse_clash_proxy_from_yaml(value: &serde_yaml::Value) -> Result<OutboundProx
         }
     }
 
-    OutboundProxyProtocol::try_from(config_map)
-        .map_err(|e| anyhow::anyhow!("Failed to parse proxy config: {}", e))
+    Ok(config_map)
+}
+
+/// `content` itself, or what it decodes to if it is base64 of a YAML
+/// config or proxy URLs.
+fn decode_document(content: &str) -> String {
+    decode_base64(content)
+        .and_then(|decoded| String::from_utf8(decoded).ok())
+        .filter(|decoded| decoded.contains("://") || decoded.contains("proxies:"))
+        .unwrap_or_else(|| content.to_string())
 }
 
-fn parse_proxy_url_to_clash_config(url: &str) -> Result<OutboundProxyProtocol> {
+fn parse_proxy_url_to_clash_config(url: &str) -> Result<ProxyConfig> {
     let parsed_url = url::Url::parse(url)?;
 
     let protocol = parsed_url.scheme();
@@ -82,9 +260,8 @@ fn parse_proxy_url_to_clash_config(url: &str) -> Result<OutboundProxyProtocol> {
             .host_str()
             .ok_or_else(|| anyhow::anyhow!("VMess URL missing base64 config"))?;
 
-        let decoded = BASE64_STANDARD
-            .decode(base64_config)
-            .map_err(|e| anyhow::anyhow!("Failed to decode VMess base64: {}", e))?;
+        let decoded = decode_base64(base64_config)
+            .ok_or_else(|| anyhow::anyhow!("Failed to decode VMess base64"))?;
 
         let json_str = String::from_utf8(decoded)
             .map_err(|e| anyhow::anyhow!("VMess config is not valid UTF8: {}", e))?;
@@ -92,10 +269,13 @@ fn parse_proxy_url_to_clash_config(url: &str) -> Result<OutboundProxyProtocol> {
         let vmess_json: serde_json::Value = serde_json::from_str(&json_str)
             .map_err(|e| anyhow::anyhow!("Failed to parse VMess JSON: {}", e))?;
 
+        // Some generators bracket IPv6 addresses here too.
         let server = vmess_json
             .get("add")
             .and_then(|v| v.as_str())
             .ok_or_else(|| anyhow::anyhow!("VMess config missing server address"))?
+            .trim_start_matches('[')
+            .trim_end_matches(']')
             .to_string();
 
         let port = vmess_json
@@ -106,15 +286,12 @@ fn parse_proxy_url_to_clash_config(url: &str) -> Result<OutboundProxyProtocol> {
         let name = vmess_json
             .get("ps")
             .and_then(|v| v.as_str())
-            .unwrap_or(&format!("{}:{}", server, port))
-            .to_string();
+            .map(str::to_string)
+            .unwrap_or_else(|| host_port(&server, port));
 
         (server, port, name, Some(vmess_json))
     } else {
-        let server = parsed_url
-            .host_str()
-            .ok_or_else(|| anyhow::anyhow!("No host in URL"))?
-            .to_string();
+        let server = url_host(&parsed_url).ok_or_else(|| anyhow::anyhow!("No host in URL"))?;
         let port = parsed_url.port().unwrap_or(match protocol {
             "ss" => 8388,
             "trojan" => 443,
@@ -129,7 +306,7 @@ fn parse_proxy_url_to_clash_config(url: &str) -> Result<OutboundProxyProtocol> {
                     .unwrap_or_else(|_| s.into())
                     .to_string()
             })
-            .unwrap_or_else(|| format!("{}:{}", server, port));
+            .unwrap_or_else(|| host_port(&server, port));
         (server, port, name, None)
     };
 
@@ -154,11 +331,9 @@ fn parse_proxy_url_to_clash_config(url: &str) -> Result<OutboundProxyProtocol> {
                     format!("{}:{}", parsed_url.username(), password)
                 } else {
                     // Try to decode base64 user info
-                    match BASE64_STANDARD.decode(parsed_url.username()) {
-                        Ok(decoded) => String::from_utf8(decoded)
-                            .unwrap_or_else(|_| parsed_url.username().to_string()),
-                        Err(_) => parsed_url.username().to_string(),
-                    }
+                    decode_base64(parsed_url.username())
+                        .and_then(|decoded| String::from_utf8(decoded).ok())
+                        .unwrap_or_else(|| parsed_url.username().to_string())
                 };
 
                 if let Some((cipher, password)) = user_info.split_once(':') {
@@ -172,6 +347,13 @@ fn parse_proxy_url_to_clash_config(url: &str) -> Result<OutboundProxyProtocol> {
                     );
                 }
             }
+
+            if let Some((_, spec)) = parsed_url.query_pairs().find(|(key, _)| key == "plugin")
+                && let Some((plugin, opts)) = ss_plugin(&spec)
+            {
+                config.insert("plugin".to_string(), serde_yaml::Value::String(plugin));
+                config.insert("plugin-opts".to_string(), serde_yaml::Value::Mapping(opts));
+            }
         }
         "trojan" => {
             if !parsed_url.username().is_empty() {
@@ -180,6 +362,7 @@ fn parse_proxy_url_to_clash_config(url: &str) -> Result<OutboundProxyProtocol> {
                     serde_yaml::Value::String(parsed_url.username().to_string()),
                 );
             }
+            insert_trojan_query(&parsed_url, &mut config);
         }
         "socks5" => {
             if !parsed_url.username().is_empty() {
@@ -334,10 +517,17 @@ fn parse_proxy_url_to_clash_config(url: &str) -> Result<OutboundProxyProtocol> {
                             }
                         }
 
-                        config.insert(
-                            "skip-cert-verify".to_string(),
-                            serde_yaml::Value::Bool(true),
-                        );
+                        // No longer always on: `[tls_verify]` decides
+                        // unless the link asks for it.
+                        if ["allowInsecure", "skip-cert-verify"]
+                            .iter()
+                            .any(|key| vmess_json.get(key).is_some_and(json_flag))
+                        {
+                            config.insert(
+                                "skip-cert-verify".to_string(),
+                                serde_yaml::Value::Bool(true),
+                            );
+                        }
                     }
                 }
             }
@@ -350,6 +540,114 @@ fn parse_proxy_url_to_clash_config(url: &str) -> Result<OutboundProxyProtocol> {
 
     debug!("Parsed proxy config: {:?}", config);
 
-    OutboundProxyProtocol::try_from(config)
-        .map_err(|e| anyhow::anyhow!("Failed to create proxy config: {}", e))
+    Ok(config)
+}
+
+/// `true`, `1` or `"true"`/`"1"`, as generators variously write switches.
+fn json_flag(value: &serde_json::Value) -> bool {
+    value.as_bool() == Some(true)
+        || value.as_u64() == Some(1)
+        || matches!(value.as_str(), Some("true" | "1"))
+}
+
+/// Host of a proxy link, without the brackets of an IPv6 literal.
+fn url_host(url: &url::Url) -> Option<String> {
+    match url.host()? {
+        url::Host::Domain(domain) => Some(domain.to_string()),
+        url::Host::Ipv4(ip) => Some(ip.to_string()),
+        url::Host::Ipv6(ip) => Some(ip.to_string()),
+    }
+}
+
+/// `host:port`, bracketing IPv6 addresses.
+fn host_port(host: &str, port: u16) -> String {
+    if host.contains(':') {
+        format!("[{}]:{}", host, port)
+    } else {
+        format!("{}:{}", host, port)
+    }
+}
+
+/// TLS and transport settings from the query of a `trojan://` link, e.g.
+/// `?sni=example.com&type=ws&path=/ws&host=example.com&alpn=h2,http/1.1`.
+fn insert_trojan_query(url: &url::Url, config: &mut ProxyConfig) {
+    let query: HashMap<String, String> = url.query_pairs().into_owned().collect();
+    let param = |key: &str| query.get(key).map(String::as_str).filter(|v| !v.is_empty());
+
+    // "peer" is what older clients call it.
+    if let Some(sni) = param("sni").or(param("peer")) {
+        config.insert("sni".to_string(), sni.into());
+    }
+    if matches!(param("allowInsecure"), Some("1" | "true")) {
+        config.insert("skip-cert-verify".to_string(), true.into());
+    }
+    if let Some(alpn) = param("alpn") {
+        let protocols = alpn.split(',').map(|p| p.trim().into()).collect();
+        config.insert("alpn".to_string(), serde_yaml::Value::Sequence(protocols));
+    }
+    match param("type") {
+        Some("ws") => {
+            config.insert("network".to_string(), "ws".into());
+            let opts = ws_opts(param("path"), param("host"));
+            config.insert("ws-opts".to_string(), opts.into());
+        }
+        Some("grpc") => {
+            config.insert("network".to_string(), "grpc".into());
+            let opts = grpc_opts(param("serviceName").or(param("path")));
+            config.insert("grpc-opts".to_string(), opts.into());
+        }
+        _ => {}
+    }
+}
+
+fn ws_opts(path: Option<&str>, host: Option<&str>) -> serde_yaml::Mapping {
+    let mut opts = serde_yaml::Mapping::new();
+    if let Some(path) = path {
+        opts.insert("path".into(), path.into());
+    }
+    if let Some(host) = host {
+        let mut headers = serde_yaml::Mapping::new();
+        headers.insert("Host".into(), host.into());
+        opts.insert("headers".into(), headers.into());
+    }
+    opts
+}
+
+fn grpc_opts(service_name: Option<&str>) -> serde_yaml::Mapping {
+    let mut opts = serde_yaml::Mapping::new();
+    if let Some(service_name) = service_name {
+        opts.insert("grpc-service-name".into(), service_name.into());
+    }
+    opts
+}
+
+/// Clash `plugin` and `plugin-opts` for a SIP002 `plugin` parameter such as
+/// "obfs-local;obfs=http;obfs-host=example.com".
+fn ss_plugin(spec: &str) -> Option<(String, serde_yaml::Mapping)> {
+    let mut parts = spec.split(';').filter(|part| !part.is_empty());
+    let name = parts.next()?;
+    // Clash knows simple-obfs as "obfs", with its own option names.
+    let (plugin, aliases): (&str, &[(&str, &str)]) = match name {
+        "obfs-local" | "simple-obfs" => ("obfs", &[("obfs", "mode"), ("obfs-host", "host")]),
+        other => (other, &[]),
+    };
+
+    let mut opts = serde_yaml::Mapping::new();
+    for part in parts {
+        let (key, value) = match part.split_once('=') {
+            Some((key, value)) => (key, serde_yaml::Value::String(value.to_string())),
+            // Bare options such as "tls" are switches.
+            None => (part, serde_yaml::Value::Bool(true)),
+        };
+        let key = aliases
+            .iter()
+            .find(|(from, _)| *from == key)
+            .map_or(key, |(_, to)| to);
+        opts.insert(key.into(), value);
+    }
+    // v2ray-plugin links leave out the default mode, which clash requires.
+    if plugin == "v2ray-plugin" && !opts.contains_key("mode") {
+        opts.insert("mode".into(), "websocket".into());
+    }
+    Some((plugin.to_string(), opts))
 }
@@ -0,0 +1,92 @@
+use crate::config::PostgresConfig;
+use crate::probe_result::ProbeResult;
+use crate::reporter::ProbeReporter;
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::Utc;
+use sqlx::PgPool;
+use sqlx::postgres::PgPoolOptions;
+
+/// Stores every round in PostgreSQL using the schema in
+/// `migrations/postgres`: one `rounds` row, one `results` row per proxy, and
+/// `proxies` deduplicated across rounds.
+pub struct PostgresReporter {
+    pool: PgPool,
+    node_name: String,
+}
+
+impl PostgresReporter {
+    /// Connect and bring the schema up to date.
+    pub async fn connect(config: &PostgresConfig) -> Result<Self> {
+        let pool = PgPoolOptions::new()
+            .max_connections(config.max_connections)
+            .connect(&config.url)
+            .await?;
+        sqlx::migrate!("./migrations/postgres").run(&pool).await?;
+        Ok(Self {
+            pool,
+            node_name: config.node_name.clone(),
+        })
+    }
+}
+
+#[async_trait]
+impl ProbeReporter for PostgresReporter {
+    async fn report(&self, results: &[ProbeResult]) -> Result<()> {
+        let alive = results.iter().filter(|r| r.alive).count();
+        let mut tx = self.pool.begin().await?;
+
+        let round_id: i64 = sqlx::query_scalar(
+            "INSERT INTO rounds (node, finished_at, total, alive) \
+             VALUES ($1, $2, $3, $4) RETURNING id",
+        )
+        .bind(&self.node_name)
+        .bind(Utc::now())
+        .bind(results.len() as i32)
+        .bind(alive as i32)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        for result in results {
+            // The no-op update makes RETURNING yield the id of existing rows too.
+            let proxy_id: i64 = sqlx::query_scalar(
+                "INSERT INTO proxies (name, protocol, server, port) VALUES ($1, $2, $3, $4) \
+                 ON CONFLICT (name, protocol, server, port) DO UPDATE SET name = EXCLUDED.name \
+                 RETURNING id",
+            )
+            .bind(&result.name)
+            .bind(&result.protocol)
+            .bind(&result.server)
+            .bind(result.port as i32)
+            .fetch_one(&mut *tx)
+            .await?;
+
+            sqlx::query(
+                "INSERT INTO results \
+                 (round_id, proxy_id, alive, delay_ms, loss_pct, error_kind, error) \
+                 VALUES ($1, $2, $3, $4, $5, $6, $7) ON CONFLICT DO NOTHING",
+            )
+            .bind(round_id)
+            .bind(proxy_id)
+            .bind(result.alive)
+            .bind(result.delay_ms.map(|d| d as i32))
+            .bind(result.loss_pct)
+            .bind(result.error_kind.map(|k| k.as_str()))
+            .bind(&result.error)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn flush(&self) -> Result<()> {
+        self.pool.close().await;
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "Postgres"
+    }
+}
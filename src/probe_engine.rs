@@ -1,153 +1,1627 @@
-use crate::config::Config;
-use crate::probe_result::ProbeResult;
-use crate::reporter::ProbeReporter;
+use crate::agent::{self, SharedNodeReports};
+use crate::cert::{self, CertInfo, CertTarget};
+use crate::config::{CertCheckConfig, Config, SharedConfig, WorkMode};
+use crate::dns;
+use crate::export::SharedProxyConfigs;
+use crate::filter;
+use crate::group;
+use crate::mihomo::Controller;
+use crate::parser::{
+    self, ParseStats, ParsedProxy, ProxyConfig, parse_clash_subscription_with_stats,
+};
+use crate::probe_result::{PhaseTimings, ProbeResult};
+use crate::relay::Relay;
+use crate::reporter::{ProbeReporter, RoundInfo, SharedReporterStats, SharedRoundInfo};
+use crate::retry::{Deferred, RetryingReporter};
+use crate::scheduler::AdaptiveScheduler;
+use crate::shutdown::{self, ShutdownSignal};
+use crate::state::{self, Snapshot};
+use crate::subscription::{
+    self, SharedSubscriptionChanges, Subscription, SubscriptionChange, SubscriptionInfo,
+    Validators, fetch_subscription_retrying,
+};
+use crate::udp;
+use crate::validator;
 use anyhow::Result;
-use clash_lib::{ProxyManager, proxy::AnyOutboundHandler};
-use futures::stream::{self, StreamExt};
-use std::{sync::Arc, time::Duration};
-use tokio::time::Instant;
-use tracing::{error, info};
+use chrono::{TimeDelta, Utc};
+use clash_lib::{
+    ProxyManager,
+    app::{dns::ThreadSafeDNSResolver, outbound::manager::OutboundManager},
+    proxy::AnyOutboundHandler,
+};
+use futures::{StreamExt, future::join_all, stream};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::{
+        Arc, Mutex, RwLock,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+use tokio::sync::{Semaphore, mpsc, oneshot};
+use tokio::time::{Instant, Interval, MissedTickBehavior};
+use tracing::{debug, error, info, warn};
+
+/// Samples of one proxy against one test URL.
+type Attempts = Vec<std::io::Result<(Duration, Duration)>>;
+
+/// Requests sent to the probe loop from outside, e.g. the web API. They are
+/// handled between rounds so they never race a scheduled round.
+pub enum EngineCommand {
+    /// Probe now, optionally only the proxy with this name, and reply with
+    /// the fresh results. Does not affect the regular schedule.
+    ProbeNow {
+        proxy: Option<String>,
+        reply: oneshot::Sender<Vec<ProbeResult>>,
+    },
+    /// Stop or restart scheduled rounds. Replies with the new paused state.
+    SetPaused {
+        paused: bool,
+        reply: oneshot::Sender<bool>,
+    },
+}
+
+pub type EngineCommandSender = mpsc::Sender<EngineCommand>;
+
+/// Identity and raw config of a loaded proxy, kept to diff the next
+/// subscription refresh against.
+struct ProxySource {
+    id: String,
+    raw: ProxyConfig,
+}
+
+/// Subscription thresholds currently crossed, so each crossing is only
+/// notified once.
+#[derive(Default)]
+struct SubscriptionAlerts {
+    traffic: bool,
+    expiry: bool,
+    too_few_proxies: bool,
+}
+
+/// Last certificate fetched from one TLS server.
+struct CertState {
+    info: CertInfo,
+    checked_at: Instant,
+    /// Whether its expiry has been notified.
+    expiring: bool,
+}
 
 pub struct ProbeEngine {
-    config: Arc<Config>,
+    config: SharedConfig,
     proxy_manager: Arc<ProxyManager>,
-    outbound_handlers: Arc<Vec<AnyOutboundHandler>>,
+    dns_resolver: ThreadSafeDNSResolver,
+    outbound_handlers: RwLock<Arc<Vec<AnyOutboundHandler>>>,
+    /// Where each handler came from, in the same order. Empty for engines
+    /// built from bare handlers with [`Self::new`].
+    sources: Mutex<Vec<ProxySource>>,
     reporters: Vec<Box<dyn ProbeReporter>>,
+    scheduler: Mutex<AdaptiveScheduler>,
+    commands: Mutex<Option<mpsc::Receiver<EngineCommand>>>,
+    command_sender: Option<EngineCommandSender>,
+    paused: AtomicBool,
+    /// Rounds that ran past the probe interval since startup.
+    overruns: AtomicU64,
+    /// Alive state of every proxy in the last reported round, for
+    /// `only_changed` filters.
+    last_states: Mutex<HashMap<String, bool>>,
+    reporter_stats: SharedReporterStats,
+    parse_stats: Mutex<ParseStats>,
+    subscription_info: Mutex<Option<SubscriptionInfo>>,
+    /// Of the subscription download the current proxies came from.
+    subscription_validators: Mutex<Validators>,
+    subscription_changes: SharedSubscriptionChanges,
+    /// Which subscription thresholds have already been notified.
+    subscription_alerts: Mutex<SubscriptionAlerts>,
+    round_info: SharedRoundInfo,
+    /// Stable IDs of the subscription's proxies, by name.
+    proxy_ids: RwLock<HashMap<String, String>>,
+    /// Server of each proxy as configured, by name, for per-host limits.
+    servers: RwLock<HashMap<String, String>>,
+    /// Server and port of every proxy reached over TCP, by name.
+    tcp_endpoints: RwLock<HashMap<String, (String, u16)>>,
+    /// Where the TLS proxies' certificates are served, by name.
+    cert_targets: RwLock<HashMap<String, CertTarget>>,
+    certs: Mutex<HashMap<CertTarget, CertState>>,
+    proxy_configs: SharedProxyConfigs,
+    /// Rounds pushed by agents, merged into each round with the
+    /// `Controller` work mode.
+    node_reports: SharedNodeReports,
+    /// Measures delays instead of `url_test` when `[external_controller]`
+    /// is configured.
+    controller: Option<Controller>,
 }
 
 impl ProbeEngine {
     pub fn new(
         config: Config,
         proxy_manager: ProxyManager,
+        dns_resolver: ThreadSafeDNSResolver,
         outbound_handlers: Vec<AnyOutboundHandler>,
     ) -> Self {
+        let scheduler = AdaptiveScheduler::new(outbound_handlers.len(), &config.main);
+        let controller = config.external_controller.as_ref().and_then(|controller| {
+            Controller::new(controller)
+                .inspect_err(|e| error!("{}, dialing proxies directly", e))
+                .ok()
+        });
         Self {
-            config: Arc::new(config),
+            config: Arc::new(RwLock::new(Arc::new(config))),
             proxy_manager: Arc::new(proxy_manager),
-            outbound_handlers: Arc::new(outbound_handlers),
+            dns_resolver,
+            outbound_handlers: RwLock::new(Arc::new(outbound_handlers)),
+            sources: Mutex::new(Vec::new()),
             reporters: Vec::new(),
+            scheduler: Mutex::new(scheduler),
+            commands: Mutex::new(None),
+            command_sender: None,
+            paused: AtomicBool::new(false),
+            overruns: AtomicU64::new(0),
+            last_states: Mutex::new(HashMap::new()),
+            reporter_stats: SharedReporterStats::default(),
+            parse_stats: Mutex::new(ParseStats::default()),
+            subscription_info: Mutex::new(None),
+            subscription_validators: Mutex::new(Validators::default()),
+            subscription_changes: SharedSubscriptionChanges::default(),
+            subscription_alerts: Mutex::new(SubscriptionAlerts::default()),
+            round_info: SharedRoundInfo::default(),
+            proxy_ids: RwLock::new(HashMap::new()),
+            servers: RwLock::new(HashMap::new()),
+            tcp_endpoints: RwLock::new(HashMap::new()),
+            cert_targets: RwLock::new(HashMap::new()),
+            certs: Mutex::new(HashMap::new()),
+            proxy_configs: SharedProxyConfigs::default(),
+            node_reports: SharedNodeReports::default(),
+            controller,
+        }
+    }
+
+    /// Build an engine for parsed subscription proxies, resolving proxy
+    /// servers with the `[dns]` settings from `config`.
+    pub async fn build(config: Config, proxies: Vec<ParsedProxy>) -> Result<Self> {
+        let dns_resolver = dns::build_resolver(&config.dns).await?;
+        let proxy_manager = ProxyManager::new(dns_resolver.clone());
+        let engine = Self::new(config, proxy_manager, dns_resolver, Vec::new());
+        engine.load_proxies(proxies);
+        Ok(engine)
+    }
+
+    /// Number of proxies this engine probes.
+    pub fn proxy_count(&self) -> usize {
+        self.handlers().len()
+    }
+
+    fn handlers(&self) -> Arc<Vec<AnyOutboundHandler>> {
+        self.outbound_handlers.read().unwrap().clone()
+    }
+
+    /// Swap in a parsed proxy list. Handlers are only built for proxies
+    /// that are new or whose config changed; the others keep theirs, and
+    /// every proxy still present keeps its scheduler state, matched by ID.
+    fn load_proxies(&self, proxies: Vec<ParsedProxy>) -> SubscriptionChange {
+        let old_handlers = self.handlers();
+        let old_sources = std::mem::take(&mut *self.sources.lock().unwrap());
+        let mut by_id: HashMap<&str, usize> = old_sources
+            .iter()
+            .enumerate()
+            .map(|(index, source)| (source.id.as_str(), index))
+            .collect();
+
+        let mut diff = SubscriptionChange {
+            at: Utc::now(),
+            ..Default::default()
+        };
+        let mut loaded = Vec::with_capacity(proxies.len());
+        let mut to_build = Vec::new();
+        for proxy in proxies {
+            // Claimed once, should two entries share an ID.
+            let previous = by_id.remove(proxy.id.as_str());
+            let handler = match previous {
+                Some(index) if old_sources[index].raw == proxy.raw => {
+                    diff.unchanged += 1;
+                    Some(old_handlers[index].clone())
+                }
+                _ => {
+                    diff.record(
+                        previous.map(|index| old_handlers[index].name()),
+                        &proxy.name,
+                    );
+                    to_build.push(proxy.protocol);
+                    None
+                }
+            };
+            let source = ProxySource {
+                id: proxy.id,
+                raw: proxy.raw,
+            };
+            loaded.push((proxy.name, source, previous, handler));
+        }
+        diff.removed = by_id
+            .values()
+            .map(|&index| old_handlers[index].name().to_string())
+            .collect();
+        diff.removed.sort();
+
+        let mut built: HashMap<String, AnyOutboundHandler> =
+            OutboundManager::load_plain_outbounds(to_build)
+                .into_iter()
+                .map(|handler| (handler.name().to_string(), handler))
+                .collect();
+
+        let mut names = Vec::with_capacity(loaded.len());
+        let mut sources = Vec::with_capacity(loaded.len());
+        let mut previous = Vec::with_capacity(loaded.len());
+        let mut handlers = Vec::with_capacity(loaded.len());
+        for (name, source, index, handler) in loaded {
+            let Some(handler) = handler.or_else(|| built.remove(&name)) else {
+                warn!("No outbound handler could be built for {}", name);
+                continue;
+            };
+            names.push(name);
+            sources.push(source);
+            previous.push(index);
+            handlers.push(handler);
+        }
+
+        let name_refs: Vec<&str> = names.iter().map(String::as_str).collect();
+        self.scheduler.lock().unwrap().remap(&previous, &name_refs);
+        *self.proxy_ids.write().unwrap() = names
+            .iter()
+            .zip(&sources)
+            .map(|(name, source)| (name.clone(), source.id.clone()))
+            .collect();
+        *self.servers.write().unwrap() = names
+            .iter()
+            .zip(&sources)
+            .filter_map(|(name, source)| {
+                let server = source.raw.get("server")?.as_str()?;
+                Some((name.clone(), server.to_string()))
+            })
+            .collect();
+        *self.tcp_endpoints.write().unwrap() = names
+            .iter()
+            .zip(&sources)
+            .filter(|(_, source)| !is_udp(&source.raw))
+            .filter_map(|(name, source)| Some((name.clone(), parser::endpoint(&source.raw)?)))
+            .collect();
+        let cert_targets: HashMap<String, CertTarget> = names
+            .iter()
+            .zip(&sources)
+            .filter_map(|(name, source)| {
+                Some((name.clone(), CertTarget::from_config(&source.raw)?))
+            })
+            .collect();
+        self.certs
+            .lock()
+            .unwrap()
+            .retain(|target, _| cert_targets.values().any(|t| t == target));
+        *self.cert_targets.write().unwrap() = cert_targets;
+        *self.proxy_configs.write().unwrap() = Arc::new(
+            sources
+                .iter()
+                .map(|source| (source.id.clone(), source.raw.clone()))
+                .collect(),
+        );
+        *self.outbound_handlers.write().unwrap() = Arc::new(handlers);
+        *self.sources.lock().unwrap() = sources;
+        diff
+    }
+
+    /// Refetch the subscription and load what changed. The request is
+    /// conditional, so an unchanged subscription isn't parsed again. On any
+    /// failure the current proxies stay.
+    async fn refresh_subscription(&self) {
+        let config = self.config();
+        let validators = self.subscription_validators.lock().unwrap().clone();
+        let subscription = match self.download_subscription(&config, &validators).await {
+            Ok(subscription) => subscription,
+            Err(e) => {
+                warn!(
+                    "Subscription refresh failed, keeping the current proxies: {}",
+                    e
+                );
+                return;
+            }
+        };
+        // A 304 without the header leaves the figures we have.
+        if subscription.info.is_some() || !subscription.not_modified {
+            *self.subscription_info.lock().unwrap() = subscription.info;
+        }
+        if subscription.not_modified {
+            info!(
+                "Subscription not modified, keeping the current {} proxies",
+                self.proxy_count()
+            );
+            return;
+        }
+
+        let (proxies, stats) =
+            match parse_clash_subscription_with_stats(&subscription.content, &config.tls_verify) {
+                Ok(parsed) => parsed,
+                Err(e) => {
+                    warn!(
+                        "Subscription refresh failed, keeping the current proxies: {}",
+                        e
+                    );
+                    return;
+                }
+            };
+
+        if !self.check_proxy_count(&config, proxies.len()).await {
+            return;
         }
+
+        *self.parse_stats.lock().unwrap() = stats;
+        // Only once loaded, so a bad download is fetched again in full.
+        *self.subscription_validators.lock().unwrap() = subscription.validators;
+        let change = self.load_proxies(proxies);
+        if change.is_empty() {
+            info!(
+                "Subscription refreshed, proxy set unchanged ({} proxies)",
+                change.unchanged
+            );
+            return;
+        }
+        let text = change.text();
+        info!(
+            "Subscription refreshed, {} unchanged:\n{}",
+            change.unchanged,
+            text.trim_end()
+        );
+        subscription::push_change(&self.subscription_changes, change);
+        self.notify_notice(&format!("Subscription changed\n{}", text))
+            .await;
+    }
+
+    /// Whether a refreshed subscription has at least `min_proxies`. Falling
+    /// short is notified once, re-armed by the next refresh that has enough.
+    async fn check_proxy_count(&self, config: &Config, count: usize) -> bool {
+        let min = config.main.min_proxies.max(1);
+        let too_few = count < min;
+        let raised = rising(
+            &mut self.subscription_alerts.lock().unwrap().too_few_proxies,
+            too_few,
+        );
+        if !too_few {
+            return true;
+        }
+
+        let text = format!(
+            "Refreshed subscription has {} proxies, fewer than min_proxies = {}; keeping the current {}",
+            count,
+            min,
+            self.proxy_count()
+        );
+        warn!("{}", text);
+        if raised {
+            self.notify_notice(&text).await;
+        }
+        false
+    }
+
+    /// Download the subscription, through the `subscription_via` proxy if
+    /// configured.
+    async fn download_subscription(
+        &self,
+        config: &Config,
+        validators: &Validators,
+    ) -> Result<Subscription> {
+        let relay = match &config.main.subscription_via {
+            Some(name) => Some(self.relay_through(name).await?),
+            None => None,
+        };
+        let proxy = relay.as_ref().map(Relay::url);
+        fetch_subscription_retrying(&config.main, validators, proxy).await
+    }
+
+    async fn relay_through(&self, name: &str) -> Result<Relay> {
+        let handler = self
+            .handlers()
+            .iter()
+            .find(|handler| handler.name() == name)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("subscription_via proxy '{}' is not loaded", name))?;
+        Relay::start(handler, self.dns_resolver.clone())
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to start subscription relay: {}", e))
+    }
+
+    /// Open the command channel, or share it if already open. Commands are
+    /// only served in continuous mode.
+    pub fn command_sender(&mut self) -> EngineCommandSender {
+        self.command_sender
+            .get_or_insert_with(|| {
+                let (sender, receiver) = mpsc::channel(16);
+                *self.commands.get_mut().unwrap() = Some(receiver);
+                sender
+            })
+            .clone()
+    }
+
+    /// Per-reporter delivery counters, e.g. for the web API.
+    pub fn reporter_stats(&self) -> SharedReporterStats {
+        self.reporter_stats.clone()
+    }
+
+    /// Proxy set changes of recent subscription refreshes, e.g. for the
+    /// web API.
+    pub fn subscription_changes(&self) -> SharedSubscriptionChanges {
+        self.subscription_changes.clone()
+    }
+
+    /// Where the web server stores what agents push, for the `Controller`
+    /// work mode.
+    pub fn node_reports(&self) -> SharedNodeReports {
+        self.node_reports.clone()
+    }
+
+    /// Summary of the latest scheduled round, e.g. for metrics reporters.
+    pub fn round_info(&self) -> SharedRoundInfo {
+        self.round_info.clone()
+    }
+
+    /// Record how the subscription parsed, for [`RoundInfo::parse`].
+    pub fn set_parse_stats(&mut self, stats: ParseStats) {
+        *self.parse_stats.get_mut().unwrap() = stats;
+    }
+
+    /// Record the traffic info of the subscription download, for
+    /// [`RoundInfo::subscription`].
+    pub fn set_subscription_info(&mut self, info: Option<SubscriptionInfo>) {
+        *self.subscription_info.get_mut().unwrap() = info;
+    }
+
+    /// Record the `ETag` and `Last-Modified` of the subscription download,
+    /// so the first refresh can already be conditional.
+    pub fn set_subscription_validators(&mut self, validators: Validators) {
+        *self.subscription_validators.get_mut().unwrap() = validators;
     }
 
+    /// Raw configs of the loaded proxies, e.g. for exports. Kept current
+    /// across subscription refreshes.
+    pub fn proxy_configs(&self) -> SharedProxyConfigs {
+        self.proxy_configs.clone()
+    }
+
+    /// Handle used to swap in a reloaded config. Takes effect next round.
+    pub fn shared_config(&self) -> SharedConfig {
+        self.config.clone()
+    }
+
+    fn config(&self) -> Arc<Config> {
+        self.config.read().unwrap().clone()
+    }
+
+    /// Add a reporter for every round. Unless `retry.queue_size` is 0, it
+    /// gets a retry queue for rounds it fails to deliver.
     pub fn register_reporter(&mut self, reporter: Box<dyn ProbeReporter>) -> &mut Self {
+        let retry = self.config().retry.clone();
+        let reporter: Box<dyn ProbeReporter> = if retry.queue_size > 0 {
+            Box::new(RetryingReporter::new(reporter, retry))
+        } else {
+            reporter
+        };
         self.reporters.push(reporter);
         self
     }
 
-    pub async fn run(&self) -> Result<()> {
+    pub async fn run(&self, mut shutdown: ShutdownSignal) -> Result<()> {
         if self.reporters.is_empty() {
             return Err(anyhow::anyhow!("No reporters registered"));
         }
 
         let is_continuous = self.has_continuous_reporters();
+        self.restore_state().await;
 
-        if is_continuous {
-            self.run_continuous().await
+        let result = if is_continuous {
+            self.run_continuous(&mut shutdown).await
         } else {
-            self.run_once().await
+            self.run_once(&mut shutdown).await
+        };
+
+        self.flush_reporters().await;
+        self.save_state().await;
+        result
+    }
+
+    /// Stable ID and name of every loaded proxy, in handler order.
+    fn proxy_keys(&self) -> (Vec<String>, Vec<String>) {
+        let ids = self
+            .sources
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|source| source.id.clone())
+            .collect();
+        let names = self
+            .handlers()
+            .iter()
+            .map(|handler| handler.name().to_string())
+            .collect();
+        (ids, names)
+    }
+
+    /// Take up the state saved in `main.state_file` and hand the results
+    /// it holds to every reporter. A missing or unreadable file just means
+    /// starting afresh.
+    async fn restore_state(&self) {
+        let config = self.config();
+        let Some(path) = &config.main.state_file else {
+            return;
+        };
+        let snapshot = match state::load(path).await {
+            Ok(Some(snapshot)) => snapshot,
+            Ok(None) => return,
+            Err(e) => {
+                warn!("Ignoring state file {}: {}", path, e);
+                return;
+            }
+        };
+
+        let (ids, names) = self.proxy_keys();
+        let names: Vec<&str> = names.iter().map(String::as_str).collect();
+        let mut results = {
+            let mut scheduler = self.scheduler.lock().unwrap();
+            let restored = scheduler.restore(&ids, &names, snapshot.round, snapshot.proxies);
+            info!(
+                "Restored state of {} proxies from {}, saved {}",
+                restored, path, snapshot.saved_at
+            );
+            scheduler.latest()
+        };
+        Self::sort_probe_results(&mut results);
+        *self.last_states.lock().unwrap() =
+            results.iter().map(|r| (r.name.clone(), r.alive)).collect();
+
+        join_all(self.reporters.iter().map(|reporter| {
+            let (config, results) = (&config, &results);
+            async move {
+                if let Err(e) = Self::deliver_restored(reporter.as_ref(), config, results).await {
+                    error!("Reporter '{}' failed to restore: {}", reporter.name(), e);
+                }
+            }
+        }))
+        .await;
+    }
+
+    /// Save every proxy's state to `main.state_file`, if set.
+    async fn save_state(&self) {
+        let config = self.config();
+        let Some(path) = &config.main.state_file else {
+            return;
+        };
+        let (ids, _) = self.proxy_keys();
+        let (round, proxies) = self.scheduler.lock().unwrap().save(&ids);
+        let snapshot = Snapshot {
+            saved_at: Utc::now(),
+            round,
+            proxies,
+        };
+        match state::save(path, &snapshot).await {
+            Ok(()) => info!(
+                "Saved state of {} proxies to {}",
+                snapshot.proxies.len(),
+                path
+            ),
+            Err(e) => error!("Failed to write state file {}: {}", path, e),
         }
     }
 
     async fn test_proxies_with_clash(
-        proxy_manager: &ProxyManager,
+        &self,
         handlers: &[AnyOutboundHandler],
-        test_url: &str,
-        timeout: Duration,
-    ) -> Vec<std::io::Result<(Duration, Duration)>> {
-        let results = stream::iter(handlers)
-            .map(|handler| async {
-                proxy_manager
-                    .url_test(handler.clone(), test_url, Some(timeout))
-                    .await
+        config: &Config,
+        on_done: impl Fn(&AnyOutboundHandler, &[Attempts]),
+    ) -> Vec<Vec<Attempts>> {
+        let targets = config.test_targets();
+        let stagger = Duration::from_millis(config.main.probe_stagger_ms);
+        let global = Semaphore::new(config.main.concurrent.max(1));
+        let per_host = self.host_limits(handlers, config.main.per_host_concurrent);
+
+        // join_all keeps results aligned with handlers.
+        join_all(handlers.iter().map(|handler| {
+            let (targets, on_done, global, per_host) = (&targets, &on_done, &global, &per_host);
+            async move {
+                // Stagger before queueing, so the delay spreads the start
+                // times out instead of idling a slot other proxies could use.
+                tokio::time::sleep(random_delay(stagger)).await;
+                // The host permit first, so proxies queued behind a busy
+                // server don't sit on global slots others could use.
+                let _host = match per_host.get(handler.name()) {
+                    Some(semaphore) => semaphore.acquire().await.ok(),
+                    None => None,
+                };
+                let _permit = global.acquire().await;
+                let per_target = self.probe_targets(handler, targets, config).await;
+                on_done(handler, &per_target);
+                per_target
+            }
+        }))
+        .await
+    }
+
+    /// One semaphore of `limit` permits per proxy server, keyed by the name
+    /// of each handler behind it. Servers are compared as configured, so
+    /// two hostnames of one relay count separately. Empty when `limit` is 0.
+    fn host_limits<'a>(
+        &self,
+        handlers: &'a [AnyOutboundHandler],
+        limit: usize,
+    ) -> HashMap<&'a str, Arc<Semaphore>> {
+        if limit == 0 {
+            return HashMap::new();
+        }
+        let servers = self.servers.read().unwrap();
+        let mut by_server: HashMap<&str, Arc<Semaphore>> = HashMap::new();
+        handlers
+            .iter()
+            .filter_map(|handler| {
+                let server = servers.get(handler.name())?;
+                let semaphore = by_server
+                    .entry(server.as_str())
+                    .or_insert_with(|| Arc::new(Semaphore::new(limit)));
+                Some((handler.name(), semaphore.clone()))
             })
-            .buffer_unordered(10) // Limit concurrency to avoid overwhelming
-            .collect::<Vec<_>>()
-            .await;
+            .collect()
+    }
 
-        results
+    /// `samples` attempts of `handler` against each target, in order.
+    async fn probe_targets(
+        &self,
+        handler: &AnyOutboundHandler,
+        targets: &[&str],
+        config: &Config,
+    ) -> Vec<Attempts> {
+        let samples = config.main.samples.max(1);
+        let mut per_target = Vec::with_capacity(targets.len());
+        for test_url in targets {
+            let mut attempts = Vec::with_capacity(samples);
+            for _ in 0..samples {
+                attempts.push(self.probe_once(handler, config, test_url).await);
+            }
+            self.check_response(handler, config, test_url, &mut attempts)
+                .await;
+            per_target.push(attempts);
+        }
+        per_target
     }
 
-    async fn run_once(&self) -> Result<()> {
+    /// One sample: `url_test` for the delay, or the external controller's
+    /// delay test when one is configured.
+    async fn probe_once(
+        &self,
+        handler: &AnyOutboundHandler,
+        config: &Config,
+        test_url: &str,
+    ) -> std::io::Result<(Duration, Duration)> {
+        let timeout = config.timeout_for(&handler.proto().to_string());
+        if let Some(controller) = &self.controller {
+            let delay = controller.delay(handler.name(), test_url, timeout).await?;
+            return Ok((delay, delay));
+        }
+
+        self.proxy_manager
+            .url_test(handler.clone(), test_url, Some(timeout))
+            .await
+    }
+
+    /// The response check from [`Config::expectation_for`], once per target
+    /// rather than per sample. A failed check fails every sample, so a block
+    /// page answering quickly never counts as alive. Skipped when no sample
+    /// got through, and with an external controller, which only measures.
+    async fn check_response(
+        &self,
+        handler: &AnyOutboundHandler,
+        config: &Config,
+        test_url: &str,
+        attempts: &mut Attempts,
+    ) {
+        if self.controller.is_some() || attempts.iter().all(|a| a.is_err()) {
+            return;
+        }
+        let timeout = config.timeout_for(&handler.proto().to_string());
+        let expect = config.expectation_for(test_url);
+        let checked = validator::validate(
+            handler,
+            self.dns_resolver.clone(),
+            test_url,
+            &expect,
+            timeout,
+        )
+        .await;
+        if let Err(e) = checked {
+            for attempt in attempts.iter_mut() {
+                *attempt = Err(std::io::Error::new(e.kind(), e.to_string()));
+            }
+        }
+    }
+
+    async fn run_once(&self, shutdown: &mut ShutdownSignal) -> Result<()> {
         info!("Starting single probe run");
-        let results = self.execute_probe().await?;
+        let results = tokio::select! {
+            results = self.execute_probe() => results?,
+            _ = shutdown::requested(shutdown) => {
+                info!("Shutdown requested, abandoning probe run");
+                return Ok(());
+            }
+        };
         self.notify_reporters(&results).await?;
         Ok(())
     }
 
-    async fn run_continuous(&self) -> Result<()> {
-        let probe_interval = Duration::from_secs(self.config.main.probe_interval);
+    async fn run_continuous(&self, shutdown: &mut ShutdownSignal) -> Result<()> {
         info!(
             "Starting continuous probe loop with {}s interval",
-            self.config.main.probe_interval
+            self.config().main.probe_interval
         );
 
+        // Rounds start on a fixed cadence. A round that runs past the next
+        // tick makes that tick get skipped rather than run back to back.
+        let mut probe_interval = Duration::from_secs(self.config().main.probe_interval);
+        let mut ticker = Self::ticker(probe_interval);
+        let mut commands = self.commands.lock().unwrap().take();
+        let refresh_period = Duration::from_secs(self.config().main.subscription_refresh);
+        let mut refresh = (!refresh_period.is_zero()).then(|| {
+            let mut ticker =
+                tokio::time::interval_at(Instant::now() + refresh_period, refresh_period);
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+            ticker
+        });
+
         loop {
-            let results = self.execute_probe().await?;
-            self.notify_reporters(&results).await?;
-            tokio::time::sleep(probe_interval).await;
+            tokio::select! {
+                _ = ticker.tick() => {}
+                Some(command) = next_command(&mut commands) => {
+                    self.handle_command(command).await;
+                    continue;
+                }
+                _ = next_tick(&mut refresh) => {
+                    tokio::select! {
+                        _ = self.refresh_subscription() => {}
+                        _ = shutdown::requested(shutdown) => break,
+                    }
+                    continue;
+                }
+                _ = shutdown::requested(shutdown) => break,
+            }
+
+            if self.paused.load(Ordering::Relaxed) {
+                continue;
+            }
+
+            let config = self.config();
+
+            // Spread rounds of many clashprobe instances over a window instead
+            // of having them all hit the provider on the interval boundary.
+            let jitter = random_delay(Duration::from_secs(config.main.probe_jitter));
+            tokio::select! {
+                _ = tokio::time::sleep(jitter) => {}
+                _ = shutdown::requested(shutdown) => break,
+            }
+
+            let round_timeout = Duration::from_secs(config.main.round_timeout);
+            let round_start = Instant::now();
+
+            // Dropping the round future cancels every in-flight probe.
+            let round = tokio::select! {
+                round = tokio::time::timeout(round_timeout, self.execute_probe()) => round,
+                _ = shutdown::requested(shutdown) => break,
+            };
+
+            match round {
+                Ok(Ok(results)) => self.notify_reporters(&results).await?,
+                Ok(Err(e)) => {
+                    self.notify_failure(&e.to_string()).await;
+                    return Err(e);
+                }
+                Err(_) => {
+                    let error = format!(
+                        "Probe round exceeded the {}s round timeout and was abandoned",
+                        config.main.round_timeout
+                    );
+                    warn!("{}", error);
+                    self.notify_failure(&error).await;
+                }
+            }
+
+            if round_start.elapsed() > probe_interval {
+                let overruns = self.record_overrun();
+                warn!(
+                    "Probe round took {:.2}s, longer than the {}s interval; skipping overlapping tick ({} overruns so far)",
+                    round_start.elapsed().as_secs_f64(),
+                    probe_interval.as_secs(),
+                    overruns
+                );
+                // The missed tick would otherwise fire immediately.
+                ticker.reset();
+            }
+
+            let configured = Duration::from_secs(config.main.probe_interval);
+            if configured != probe_interval {
+                info!("Probe interval changed to {}s", config.main.probe_interval);
+                probe_interval = configured;
+                ticker = Self::ticker(probe_interval);
+                ticker.reset();
+            }
         }
+
+        info!("Shutdown requested, stopping probe loop");
+        Ok(())
+    }
+
+    /// Count a round that ran past the interval, in the engine and in the
+    /// latest round info, which was published before the overrun was known.
+    fn record_overrun(&self) -> u64 {
+        let overruns = self.overruns.fetch_add(1, Ordering::Relaxed) + 1;
+        if let Some(info) = self.round_info.write().unwrap().as_mut() {
+            info.overruns = overruns;
+        }
+        overruns
+    }
+
+    fn ticker(period: Duration) -> Interval {
+        let mut ticker = tokio::time::interval(period);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        ticker
     }
 
     async fn execute_probe(&self) -> Result<Vec<ProbeResult>> {
+        let config = self.config();
         let start_time = Instant::now();
-        let timeout = Duration::from_secs(self.config.main.timeout);
 
-        let results = Self::test_proxies_with_clash(
-            &self.proxy_manager,
-            &self.outbound_handlers,
-            &self.config.main.test_url,
-            timeout,
-        )
-        .await;
+        let probed_at = Utc::now();
+        let (due, round_id) = {
+            let mut scheduler = self.scheduler.lock().unwrap();
+            scheduler.reconfigure(&config.main);
+            (scheduler.due(), scheduler.round_id())
+        };
+        let all_handlers = self.handlers();
+        let handlers: Vec<AnyOutboundHandler> = due
+            .iter()
+            .map(|&index| all_handlers[index].clone())
+            .collect();
+
+        // Streaming reporters get each result as it lands. The channel
+        // closes when probing is done and drops `on_done`.
+        let targets = config.test_targets();
+        let streaming = self.reporters.iter().any(|r| r.is_streaming());
+        let (live_sender, live) = mpsc::unbounded_channel();
+        let on_done = {
+            let targets = &targets;
+            move |handler: &AnyOutboundHandler, per_target: &[Attempts]| {
+                if streaming {
+                    let mut result = self.summarize_targets(handler, targets, per_target);
+                    result.probed_at = probed_at;
+                    result.round_id = round_id;
+                    let _ = live_sender.send(result);
+                }
+            }
+        };
+        let probing = self.test_proxies_with_clash(&handlers, &config, on_done);
+        let (results, (), certs, udp, phases, reuse, rtts, baseline) = tokio::join!(
+            probing,
+            self.stream_partials(&config, live),
+            self.check_certificates(&config, &handlers),
+            self.check_udp(&config, &handlers),
+            self.time_phases(&config, &handlers),
+            self.time_reuse(&config, &handlers),
+            self.tcp_pings(&config, &handlers),
+            measure_baseline(&config, &targets)
+        );
 
         let elapsed = start_time.elapsed();
-        let probe_results = self.build_and_sort_probe_results(&results);
+        let mut probe_results = {
+            let mut scheduler = self.scheduler.lock().unwrap();
+            for ((&index, handler), per_target) in due.iter().zip(&handlers).zip(&results) {
+                let mut result = self.summarize_targets(handler, &targets, per_target);
+                result.probed_at = probed_at;
+                result.round_id = round_id;
+                if let Some(info) = certs.get(handler.name()) {
+                    result.cert_not_after = Some(info.not_after);
+                    result.cert_sha256 = Some(info.sha256.clone());
+                }
+                result.udp_ok = udp.get(handler.name()).copied();
+                result.phases = phases.get(handler.name()).copied();
+                result.tcp_rtt_ms = rtts.get(handler.name()).copied();
+                if let Some([first, second]) = reuse.get(handler.name()) {
+                    result.first_request_ms = Some(*first);
+                    result.second_request_ms = Some(*second);
+                }
+                scheduler.record(index, result);
+            }
+            scheduler.finish_round()
+        };
+        probe_results.extend(self.remote_results(&config));
+        Self::sort_probe_results(&mut probe_results);
 
         let alive_count = probe_results.iter().filter(|r| r.alive).count();
         info!(
-            "Probe completed in {:.2}s - {}/{} proxies alive",
+            "Probe completed in {:.2}s - {}/{} proxies alive ({} probed this round)",
             elapsed.as_secs_f64(),
             alive_count,
-            probe_results.len()
+            probe_results.len(),
+            handlers.len()
         );
 
+        *self.round_info.write().unwrap() = Some(RoundInfo {
+            finished_at: Utc::now(),
+            duration_ms: elapsed.as_millis() as u64,
+            total: probe_results.len(),
+            alive: alive_count,
+            probed: handlers.len(),
+            parse: *self.parse_stats.lock().unwrap(),
+            groups: group::aggregate(&config.groups, &probe_results),
+            subscription: *self.subscription_info.lock().unwrap(),
+            baseline,
+            overruns: self.overruns.load(Ordering::Relaxed),
+        });
+
         Ok(probe_results)
     }
 
-    async fn notify_reporters(&self, results: &[ProbeResult]) -> Result<()> {
-        for reporter in &self.reporters {
-            if let Err(e) = reporter.report(results).await {
-                error!("Reporter '{}' failed: {}", reporter.name(), e);
+    /// Fresh rounds of every agent, when this instance is a controller.
+    fn remote_results(&self, config: &Config) -> Vec<ProbeResult> {
+        match &config.controller {
+            Some(controller) if config.main.work_mode.contains(WorkMode::CONTROLLER) => {
+                let stale_after = Duration::from_secs(controller.stale_after);
+                agent::merge(&self.node_reports, stale_after)
             }
+            _ => Vec::new(),
         }
+    }
+
+    async fn handle_command(&self, command: EngineCommand) {
+        match command {
+            EngineCommand::ProbeNow { proxy, reply } => {
+                let results = self.probe_now(proxy.as_deref()).await;
+                let _ = reply.send(results);
+            }
+            EngineCommand::SetPaused { paused, reply } => {
+                if self.paused.swap(paused, Ordering::Relaxed) != paused {
+                    info!("Probing {}", if paused { "paused" } else { "resumed" });
+                }
+                let _ = reply.send(paused);
+            }
+        }
+    }
+
+    /// Out-of-band probe of all proxies, or only those named `proxy`.
+    /// Results go straight back to the caller; scheduler state is untouched.
+    pub async fn probe_now(&self, proxy: Option<&str>) -> Vec<ProbeResult> {
+        let config = self.config();
+        let handlers: Vec<AnyOutboundHandler> = self
+            .handlers()
+            .iter()
+            .filter(|handler| proxy.is_none_or(|name| handler.name() == name))
+            .cloned()
+            .collect();
+        info!("On-demand probe of {} proxies", handlers.len());
+
+        let results = self
+            .test_proxies_with_clash(&handlers, &config, |_, _| {})
+            .await;
+
+        let targets = config.test_targets();
+        let mut probe_results: Vec<ProbeResult> = handlers
+            .iter()
+            .zip(&results)
+            .map(|(handler, per_target)| self.summarize_targets(handler, &targets, per_target))
+            .collect();
+        Self::sort_probe_results(&mut probe_results);
+        probe_results
+    }
+
+    /// Hand `results` to every reporter concurrently, each under its own
+    /// `reporter_timeout`.
+    async fn notify_reporters(&self, results: &[ProbeResult]) -> Result<()> {
+        let config = self.config();
+        let timeout = Duration::from_secs(config.main.reporter_timeout);
+        let changed = {
+            let mut last_states = self.last_states.lock().unwrap();
+            let changed = filter::changed_names(&last_states, results);
+            *last_states = results.iter().map(|r| (r.name.clone(), r.alive)).collect();
+            changed
+        };
+
+        let deliveries = self.reporters.iter().map(|reporter| {
+            let config = &config;
+            let changed = &changed;
+            async move {
+                let start = Instant::now();
+                let delivery = Self::deliver(reporter.as_ref(), config, results, changed);
+                let outcome = tokio::time::timeout(timeout, delivery).await;
+                self.record_delivery(reporter.name(), outcome, start.elapsed(), timeout);
+            }
+        });
+        join_all(deliveries).await;
+        self.check_subscription(&config).await;
         Ok(())
     }
 
-    fn has_continuous_reporters(&self) -> bool {
-        self.reporters.iter().any(|r| r.is_continuous())
+    /// Send a notice when the subscription crosses its traffic or expiry
+    /// threshold. Each crossing is sent once and re-armed when the figure
+    /// drops back, e.g. after the plan is renewed.
+    async fn check_subscription(&self, config: &Config) {
+        let Some(info) = *self.subscription_info.lock().unwrap() else {
+            return;
+        };
+        let used_pct = info.used_pct().filter(|&used| {
+            config
+                .main
+                .subscription_alert_traffic_pct
+                .is_some_and(|limit| used >= limit)
+        });
+        let days_left = info.days_left(Utc::now()).filter(|&days| {
+            config
+                .main
+                .subscription_alert_expire_days
+                .is_some_and(|limit| days <= limit as i64)
+        });
+
+        let mut notices = Vec::new();
+        {
+            let mut alerts = self.subscription_alerts.lock().unwrap();
+            if rising(&mut alerts.traffic, used_pct.is_some()) {
+                notices.push(format!(
+                    "Subscription has used {:.1}% of its traffic ({:.2} of {:.2} GiB)",
+                    used_pct.unwrap_or_default(),
+                    gib(info.used()),
+                    gib(info.total)
+                ));
+            }
+            if rising(&mut alerts.expiry, days_left.is_some()) {
+                notices.push(format!(
+                    "Subscription expires in {} days",
+                    days_left.unwrap_or_default()
+                ));
+            }
+        }
+        for notice in notices {
+            warn!("{}", notice);
+            self.notify_notice(&notice).await;
+        }
     }
 
-    fn build_and_sort_probe_results(
+    /// Certificates of the TLS servers behind `handlers`, by proxy name,
+    /// fetching those not checked within `cert_check.interval`. Empty when
+    /// the check is off.
+    async fn check_certificates(
         &self,
-        results: &[std::io::Result<(Duration, Duration)>],
-    ) -> Vec<ProbeResult> {
-        let mut probe_results: Vec<ProbeResult> = self
-            .outbound_handlers
-            .iter()
-            .zip(results.iter())
-            .map(|(handler, result)| match result {
-                Ok((delay, _)) => ProbeResult::from_success(handler, *delay),
-                Err(e) => ProbeResult::from_error(handler, e),
+        config: &Config,
+        handlers: &[AnyOutboundHandler],
+    ) -> HashMap<String, CertInfo> {
+        if !config.cert_check.enabled {
+            return HashMap::new();
+        }
+        let targets: HashMap<String, CertTarget> = {
+            let all = self.cert_targets.read().unwrap();
+            handlers
+                .iter()
+                .filter_map(|h| Some((h.name().to_string(), all.get(h.name())?.clone())))
+                .collect()
+        };
+        let interval = Duration::from_secs(config.cert_check.interval);
+        let stale: HashSet<CertTarget> = {
+            let certs = self.certs.lock().unwrap();
+            targets
+                .values()
+                .filter(|t| {
+                    certs
+                        .get(t)
+                        .is_none_or(|s| s.checked_at.elapsed() >= interval)
+                })
+                .cloned()
+                .collect()
+        };
+
+        let timeout = Duration::from_secs(config.main.timeout);
+        let fetched: Vec<_> = stream::iter(stale)
+            .map(|target| async move {
+                let info = cert::fetch(&target, self.dns_resolver.clone(), timeout).await;
+                (target, info)
             })
-            .collect();
+            .buffer_unordered(config.main.concurrent.max(1))
+            .collect()
+            .await;
+        for notice in self.record_certificates(&config.cert_check, fetched) {
+            warn!("{}", notice);
+            self.notify_notice(&notice).await;
+        }
 
+        let certs = self.certs.lock().unwrap();
+        targets
+            .into_iter()
+            .filter_map(|(name, target)| Some((name, certs.get(&target)?.info.clone())))
+            .collect()
+    }
+
+    /// Whether each of `handlers` relayed a DNS query over UDP, by proxy
+    /// name. Empty when `[udp_check]` is off.
+    async fn check_udp(
+        &self,
+        config: &Config,
+        handlers: &[AnyOutboundHandler],
+    ) -> HashMap<String, bool> {
+        if !config.udp_check.enabled {
+            return HashMap::new();
+        }
+        stream::iter(handlers)
+            .map(|handler| async move {
+                let timeout = config.timeout_for(&handler.proto().to_string());
+                let outcome = udp::check(
+                    handler,
+                    self.dns_resolver.clone(),
+                    &config.udp_check,
+                    timeout,
+                )
+                .await;
+                if let Err(e) = &outcome {
+                    debug!("UDP check of {} failed: {}", handler.name(), e);
+                }
+                (handler.name().to_string(), outcome.is_ok())
+            })
+            .buffer_unordered(config.main.concurrent.max(1))
+            .collect()
+            .await
+    }
+
+    /// Phase timings of one request through each of `handlers`, by proxy
+    /// name, leaving out proxies the request failed through. Empty unless
+    /// `main.phase_timing` is set.
+    async fn time_phases(
+        &self,
+        config: &Config,
+        handlers: &[AnyOutboundHandler],
+    ) -> HashMap<String, PhaseTimings> {
+        if !config.main.phase_timing {
+            return HashMap::new();
+        }
+        self.through_each(config, handlers, validator::measure_phases)
+            .await
+    }
+
+    /// First and second request delays over one connection through each of
+    /// `handlers`, by proxy name. Empty unless `main.reuse_check` is set.
+    async fn time_reuse(
+        &self,
+        config: &Config,
+        handlers: &[AnyOutboundHandler],
+    ) -> HashMap<String, [u64; 2]> {
+        if !config.main.reuse_check {
+            return HashMap::new();
+        }
+        self.through_each(config, handlers, validator::measure_reuse)
+            .await
+    }
+
+    /// Time of a bare TCP connect to the server of each of `handlers`, by
+    /// proxy name, leaving out proxies over UDP and failed connects. Empty
+    /// unless `main.tcp_ping` is set.
+    async fn tcp_pings(
+        &self,
+        config: &Config,
+        handlers: &[AnyOutboundHandler],
+    ) -> HashMap<String, u64> {
+        if !config.main.tcp_ping {
+            return HashMap::new();
+        }
+        let endpoints: Vec<(String, (String, u16))> = {
+            let all = self.tcp_endpoints.read().unwrap();
+            handlers
+                .iter()
+                .filter_map(|h| Some((h.name().to_string(), all.get(h.name())?.clone())))
+                .collect()
+        };
+        let timeout = Duration::from_secs(config.main.timeout);
+        stream::iter(endpoints)
+            .map(|(name, (server, port))| async move {
+                match tcp_connect_time(&self.dns_resolver, &server, port, timeout).await {
+                    Ok(rtt) => Some((name, rtt.as_millis() as u64)),
+                    Err(e) => {
+                        debug!("TCP ping of {} failed: {}", name, e);
+                        None
+                    }
+                }
+            })
+            .buffer_unordered(config.main.concurrent.max(1))
+            .filter_map(std::future::ready)
+            .collect()
+            .await
+    }
+
+    /// Run `measure` against the first test URL, the one that decides
+    /// whether a proxy is alive, through each of `handlers` in turn,
+    /// `main.concurrent` at a time. Failures are left out.
+    async fn through_each<'a, T, F>(
+        &self,
+        config: &'a Config,
+        handlers: &'a [AnyOutboundHandler],
+        measure: impl Fn(&'a AnyOutboundHandler, ThreadSafeDNSResolver, &'a str, Duration) -> F,
+    ) -> HashMap<String, T>
+    where
+        F: Future<Output = std::io::Result<T>>,
+    {
+        let url = config.test_targets()[0];
+        let measure = &measure;
+        stream::iter(handlers)
+            .map(|handler| async move {
+                let timeout = config.timeout_for(&handler.proto().to_string());
+                match measure(handler, self.dns_resolver.clone(), url, timeout).await {
+                    Ok(value) => Some((handler.name().to_string(), value)),
+                    Err(e) => {
+                        debug!("Request through {} failed: {}", handler.name(), e);
+                        None
+                    }
+                }
+            })
+            .buffer_unordered(config.main.concurrent.max(1))
+            .filter_map(std::future::ready)
+            .collect()
+            .await
+    }
+
+    /// Store freshly fetched certificates, returning the notices they call
+    /// for. A failed fetch keeps the last certificate and is retried next
+    /// round.
+    fn record_certificates(
+        &self,
+        config: &CertCheckConfig,
+        fetched: Vec<(CertTarget, Result<CertInfo>)>,
+    ) -> Vec<String> {
+        let now = Utc::now();
+        let window = TimeDelta::days(config.alert_days as i64);
+        let mut certs = self.certs.lock().unwrap();
+        let mut notices = Vec::new();
+        for (target, info) in fetched {
+            let info = match info {
+                Ok(info) => info,
+                Err(e) => {
+                    debug!("Certificate check of {} failed: {}", target, e);
+                    continue;
+                }
+            };
+            let previous = certs.remove(&target);
+            if let Some(old) = &previous
+                && old.info.sha256 != info.sha256
+                && !info.renews(&old.info, now, window)
+            {
+                notices.push(format!(
+                    "Certificate of {} changed unexpectedly: {} is now {}",
+                    target, old.info.sha256, info.sha256
+                ));
+            }
+            let mut expiring = previous.is_some_and(|s| s.expiring);
+            if rising(&mut expiring, info.not_after - now <= window) {
+                notices.push(format!(
+                    "Certificate of {} expires on {}",
+                    target,
+                    info.not_after.format("%Y-%m-%d")
+                ));
+            }
+            let checked_at = Instant::now();
+            certs.insert(
+                target,
+                CertState {
+                    info,
+                    checked_at,
+                    expiring,
+                },
+            );
+        }
+        notices
+    }
+
+    async fn notify_notice(&self, text: &str) {
+        let timeout = Duration::from_secs(self.config().main.reporter_timeout);
+        join_all(self.reporters.iter().map(|reporter| async move {
+            match tokio::time::timeout(timeout, reporter.report_notice(text)).await {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => error!("Reporter '{}' failed: {}", reporter.name(), e),
+                Err(_) => error!("Reporter '{}' timed out", reporter.name()),
+            }
+        }))
+        .await;
+    }
+
+    /// Hand results arriving on `live` to the streaming reporters until the
+    /// channel closes, each through its filter and under `reporter_timeout`.
+    /// Failures are only logged; the round's `report` supersedes them.
+    async fn stream_partials(
+        &self,
+        config: &Config,
+        mut live: mpsc::UnboundedReceiver<ProbeResult>,
+    ) {
+        let timeout = Duration::from_secs(config.main.reporter_timeout);
+        while let Some(result) = live.recv().await {
+            let result = &result;
+            let changed = &filter::changed_names(
+                &self.last_states.lock().unwrap(),
+                std::slice::from_ref(result),
+            );
+            let streaming = self.reporters.iter().filter(|r| r.is_streaming());
+            join_all(streaming.map(|reporter| async move {
+                let delivery = Self::deliver_partial(reporter.as_ref(), config, result, changed);
+                match tokio::time::timeout(timeout, delivery).await {
+                    Ok(Ok(())) => {}
+                    Ok(Err(e)) => warn!(
+                        "Reporter '{}' failed on a partial result: {}",
+                        reporter.name(),
+                        e
+                    ),
+                    Err(_) => warn!(
+                        "Reporter '{}' timed out on a partial result",
+                        reporter.name()
+                    ),
+                }
+            }))
+            .await;
+        }
+    }
+
+    /// [`Self::deliver`] for a single result of the running round.
+    async fn deliver_partial(
+        reporter: &dyn ProbeReporter,
+        config: &Config,
+        result: &ProbeResult,
+        changed: &HashSet<String>,
+    ) -> Result<()> {
+        let Some(result_filter) = config.filter_for(reporter.name()) else {
+            return reporter.report_partial(result).await;
+        };
+        for result in filter::apply(result_filter, std::slice::from_ref(result), changed)? {
+            reporter.report_partial(&result).await?;
+        }
+        Ok(())
+    }
+
+    /// Report `results` to one reporter, through its filter if configured.
+    async fn deliver(
+        reporter: &dyn ProbeReporter,
+        config: &Config,
+        results: &[ProbeResult],
+        changed: &HashSet<String>,
+    ) -> Result<()> {
+        match config.filter_for(reporter.name()) {
+            Some(result_filter) => {
+                let filtered = filter::apply(result_filter, results, changed)?;
+                reporter.report(&filtered).await
+            }
+            None => reporter.report(results).await,
+        }
+    }
+
+    /// Hand restored `results` to one reporter, through its filter as if
+    /// nothing had changed.
+    async fn deliver_restored(
+        reporter: &dyn ProbeReporter,
+        config: &Config,
+        results: &[ProbeResult],
+    ) -> Result<()> {
+        match config.filter_for(reporter.name()) {
+            Some(result_filter) => {
+                let filtered = filter::apply(result_filter, results, &HashSet::new())?;
+                reporter.restore(&filtered).await;
+            }
+            None => reporter.restore(results).await,
+        }
+        Ok(())
+    }
+
+    fn record_delivery(
+        &self,
+        name: &str,
+        outcome: Result<Result<()>, tokio::time::error::Elapsed>,
+        elapsed: Duration,
+        timeout: Duration,
+    ) {
+        let mut stats = self.reporter_stats.write().unwrap();
+        let stats = stats.entry(name.to_string()).or_default();
+        stats.last_duration_ms = Some(elapsed.as_millis() as u64);
+        match outcome {
+            Ok(Ok(())) => stats.successes += 1,
+            Ok(Err(e)) if e.is::<Deferred>() => stats.deferred += 1,
+            Ok(Err(e)) => {
+                error!("Reporter '{}' failed: {}", name, e);
+                stats.failures += 1;
+                stats.last_error = Some(e.to_string());
+            }
+            Err(_) => {
+                error!("Reporter '{}' timed out after {}s", name, timeout.as_secs());
+                stats.timeouts += 1;
+                stats.last_error = Some(format!("timed out after {}s", timeout.as_secs()));
+            }
+        }
+    }
+
+    async fn notify_failure(&self, error: &str) {
+        let timeout = Duration::from_secs(self.config().main.reporter_timeout);
+        join_all(self.reporters.iter().map(|reporter| async move {
+            match tokio::time::timeout(timeout, reporter.report_failure(error)).await {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => error!("Reporter '{}' failed: {}", reporter.name(), e),
+                Err(_) => error!("Reporter '{}' timed out", reporter.name()),
+            }
+        }))
+        .await;
+    }
+
+    async fn flush_reporters(&self) {
+        let timeout = Duration::from_secs(self.config().main.reporter_timeout);
+        join_all(self.reporters.iter().map(|reporter| async move {
+            match tokio::time::timeout(timeout, reporter.flush()).await {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => error!("Reporter '{}' failed to flush: {}", reporter.name(), e),
+                Err(_) => error!("Reporter '{}' timed out while flushing", reporter.name()),
+            }
+        }))
+        .await;
+    }
+
+    fn has_continuous_reporters(&self) -> bool {
+        self.reporters.iter().any(|r| r.is_continuous())
+    }
+
+    fn sort_probe_results(probe_results: &mut [ProbeResult]) {
         probe_results.sort_by(|a, b| match (a.alive, b.alive) {
             (true, false) => std::cmp::Ordering::Less,
             (false, true) => std::cmp::Ordering::Greater,
             (true, true) => a.delay_ms.cmp(&b.delay_ms),
             (false, false) => a.name.cmp(&b.name),
         });
+    }
 
-        probe_results
+    /// The first test URL decides the proxy's status; every target also gets
+    /// its own averaged delay in `ProbeResult::targets`.
+    fn summarize_targets(
+        &self,
+        handler: &AnyOutboundHandler,
+        targets: &[&str],
+        per_target: &[Attempts],
+    ) -> ProbeResult {
+        let mut result = Self::summarize_attempts(handler, &per_target[0]);
+        if let Some(id) = self.proxy_ids.read().unwrap().get(handler.name()) {
+            result.proxy_id = id.clone();
+        }
+        result.targets = targets
+            .iter()
+            .zip(per_target)
+            .map(|(url, attempts)| (url.to_string(), average_delay_ms(attempts)))
+            .collect();
+        result
+    }
+
+    /// Fold the per-round samples of one proxy into a single result.
+    /// The delay is averaged over successful attempts only.
+    fn summarize_attempts(
+        handler: &AnyOutboundHandler,
+        attempts: &[std::io::Result<(Duration, Duration)>],
+    ) -> ProbeResult {
+        let delays = successful_delays(attempts);
+        if delays.is_empty() {
+            let error = attempts
+                .iter()
+                .rev()
+                .find_map(|r| r.as_ref().err())
+                .expect("a round with no successful sample has an error");
+            return ProbeResult::from_error(handler, error);
+        }
+
+        let lost = attempts.len() - delays.len();
+        let loss_pct = lost as f64 / attempts.len() as f64 * 100.0;
+        ProbeResult::from_success(handler, mean(&delays), loss_pct)
+    }
+}
+
+fn successful_delays(attempts: &[std::io::Result<(Duration, Duration)>]) -> Vec<Duration> {
+    attempts
+        .iter()
+        .filter_map(|r| r.as_ref().ok().map(|(delay, _)| *delay))
+        .collect()
+}
+
+fn mean(delays: &[Duration]) -> Duration {
+    delays.iter().sum::<Duration>() / delays.len() as u32
+}
+
+fn average_delay_ms(attempts: &[std::io::Result<(Duration, Duration)>]) -> Option<u64> {
+    let delays = successful_delays(attempts);
+    (!delays.is_empty()).then(|| mean(&delays).as_millis() as u64)
+}
+
+async fn next_command(
+    commands: &mut Option<mpsc::Receiver<EngineCommand>>,
+) -> Option<EngineCommand> {
+    match commands {
+        Some(receiver) => receiver.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+async fn next_tick(ticker: &mut Option<Interval>) {
+    match ticker {
+        Some(ticker) => {
+            ticker.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// Whether a proxy of this config talks to its server over UDP only, so
+/// has no TCP port to ping.
+fn is_udp(raw: &ProxyConfig) -> bool {
+    let kind = raw.get("type").and_then(|v| v.as_str()).unwrap_or_default();
+    matches!(kind, "hysteria" | "hysteria2" | "tuic" | "wireguard")
+}
+
+/// Time to open a TCP connection to `server:port`, name lookup excluded.
+async fn tcp_connect_time(
+    resolver: &ThreadSafeDNSResolver,
+    server: &str,
+    port: u16,
+    timeout: Duration,
+) -> Result<Duration> {
+    let ip = dns::resolve_ip(resolver, server).await?;
+    let start = Instant::now();
+    tokio::time::timeout(timeout, tokio::net::TcpStream::connect((ip, port)))
+        .await
+        .map_err(|_| anyhow::anyhow!("timed out after {}s", timeout.as_secs()))??;
+    Ok(start.elapsed())
+}
+
+/// Delay of each test URL over the direct path, as a reference for the
+/// proxies' delays. Empty unless `main.direct_baseline` is set.
+async fn measure_baseline(config: &Config, targets: &[&str]) -> BTreeMap<String, Option<u64>> {
+    if !config.main.direct_baseline {
+        return BTreeMap::new();
+    }
+    let client = match reqwest::Client::builder()
+        .no_proxy()
+        .timeout(Duration::from_secs(config.main.timeout))
+        .build()
+    {
+        Ok(client) => client,
+        Err(e) => {
+            warn!("Failed to build the direct baseline client: {}", e);
+            return BTreeMap::new();
+        }
+    };
+    join_all(targets.iter().map(|url| {
+        let client = &client;
+        async move { (url.to_string(), direct_delay(client, url).await) }
+    }))
+    .await
+    .into_iter()
+    .collect()
+}
+
+/// Time to the response headers of a fresh request to `url`, like
+/// `url_test` measures through a proxy.
+async fn direct_delay(client: &reqwest::Client, url: &str) -> Option<u64> {
+    let start = Instant::now();
+    match client.get(url).send().await {
+        Ok(_) => Some(start.elapsed().as_millis() as u64),
+        Err(e) => {
+            debug!("Direct request to {} failed: {}", url, e);
+            None
+        }
+    }
+}
+
+/// Uniformly random delay in `[0, max]`.
+fn random_delay(max: Duration) -> Duration {
+    if max.is_zero() {
+        return Duration::ZERO;
     }
+    Duration::from_millis(rand::random_range(0..=max.as_millis() as u64))
+}
+
+/// Set `flag` to `now`, true only if it was just raised.
+fn rising(flag: &mut bool, now: bool) -> bool {
+    let raised = now && !*flag;
+    *flag = now;
+    raised
+}
+
+fn gib(bytes: u64) -> f64 {
+    bytes as f64 / (1u64 << 30) as f64
 }
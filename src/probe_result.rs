@@ -1,29 +1,236 @@
+use crate::region;
+use chrono::{DateTime, Utc};
 use clash_lib::proxy::AnyOutboundHandler;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer, ser::Error as _};
+use std::collections::BTreeMap;
+use std::sync::Arc;
 use std::time::Duration;
 
+/// Serialized through the impls below, which honour `fields`.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(remote = "Self")]
 pub struct ProbeResult {
     pub name: String,
+    /// Hash of server, port, protocol and UUID that survives renames; empty
+    /// when the subscription entry isn't known.
+    #[serde(default)]
+    pub proxy_id: String,
+    /// When the round that produced this result started. Every proxy probed
+    /// in the same round shares it.
+    #[serde(default)]
+    pub probed_at: DateTime<Utc>,
+    /// Scheduled round that produced this result, counting from 1; 0 for
+    /// on-demand probes.
+    #[serde(default)]
+    pub round_id: u64,
+    #[serde(default)]
     pub server: String,
+    #[serde(default)]
     pub port: u16,
+    #[serde(default)]
     pub protocol: String,
+    /// ISO 3166 code guessed from the proxy name, e.g. "HK".
+    #[serde(default)]
+    pub region: Option<String>,
     pub alive: bool,
     pub delay_ms: Option<u64>,
+    #[serde(default)]
+    pub loss_pct: f64,
     pub error: Option<String>,
+    pub error_kind: Option<ErrorKind>,
+    /// Average delay per test URL; `None` when every sample failed.
+    #[serde(default)]
+    pub targets: BTreeMap<String, Option<u64>>,
+    /// Percentage of probes that found the proxy alive over the past hour
+    /// and day; `None` until it has been probed in that window.
+    #[serde(default)]
+    pub uptime_1h: Option<f64>,
+    #[serde(default)]
+    pub uptime_24h: Option<f64>,
+    /// Failed probes in a row, before flap damping.
+    #[serde(default)]
+    pub consecutive_failures: u32,
+    #[serde(default)]
+    pub last_alive_at: Option<DateTime<Utc>>,
+    /// Delay percentiles over the last `latency_window` successful probes.
+    #[serde(default)]
+    pub p50_ms: Option<u64>,
+    #[serde(default)]
+    pub p95_ms: Option<u64>,
+    #[serde(default)]
+    pub p99_ms: Option<u64>,
+    /// Expiry and SHA-256 fingerprint of the server's TLS certificate, when
+    /// `[cert_check]` is on and the proxy uses TLS.
+    #[serde(default)]
+    pub cert_not_after: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub cert_sha256: Option<String>,
+    /// Whether a DNS query through the proxy's UDP relay was answered;
+    /// `None` when `[udp_check]` is off.
+    #[serde(default)]
+    pub udp_ok: Option<bool>,
+    /// Where the time of one request to the first test URL went, with
+    /// `main.phase_timing` on.
+    #[serde(default)]
+    pub phases: Option<PhaseTimings>,
+    /// Two requests to the first test URL over one connection, with
+    /// `main.reuse_check` on: the first includes connecting, the second
+    /// only the request. A second request nearly as slow as the first
+    /// points at poor session reuse or multiplexing.
+    #[serde(default)]
+    pub first_request_ms: Option<u64>,
+    #[serde(default)]
+    pub second_request_ms: Option<u64>,
+    /// Time of a plain TCP connect to the proxy server, with
+    /// `main.tcp_ping` on; `None` for proxies over UDP.
+    #[serde(default)]
+    pub tcp_rtt_ms: Option<u64>,
+    /// Agent that probed this proxy, for results a controller merged in;
+    /// `None` for proxies probed here.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub node: Option<String>,
+    /// Fields a reporter's filter selected; the others are left out when
+    /// serialized. `None` serializes every field.
+    #[serde(skip)]
+    pub fields: Option<Arc<[String]>>,
+}
+
+/// Fields serialized whatever a filter selects.
+const ALWAYS_SERIALIZED: &[&str] = &["name", "alive", "probed_at", "round_id"];
+
+impl Serialize for ProbeResult {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let Some(fields) = &self.fields else {
+            return ProbeResult::serialize(self, serializer);
+        };
+        let value = ProbeResult::serialize(self, serde_json::value::Serializer)
+            .map_err(S::Error::custom)?;
+        let serde_json::Value::Object(mut map) = value else {
+            return Err(S::Error::custom("probe result is not an object"));
+        };
+        map.retain(|key, _| {
+            ALWAYS_SERIALIZED.contains(&key.as_str()) || fields.iter().any(|f| f == key)
+        });
+        map.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ProbeResult {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        ProbeResult::deserialize(deserializer)
+    }
+}
+
+/// Phases of one request through a proxy. The proxy's own connect to the
+/// test host is not visible from here: it falls into the TLS handshake for
+/// HTTPS URLs and into `first_byte_ms` otherwise.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PhaseTimings {
+    /// Connecting to the proxy server and its protocol handshake.
+    pub handshake_ms: u64,
+    /// TLS handshake with the test host through the proxy; `None` for
+    /// plain HTTP.
+    pub tls_ms: Option<u64>,
+    /// From the request being sent to the first byte of the response.
+    pub first_byte_ms: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    Timeout,
+    ConnectionRefused,
+    DnsFailure,
+    TlsError,
+    /// The server's certificate failed verification, see `[tls_verify]`.
+    CertificateError,
+    ProtocolError,
+    Other,
+}
+
+impl ErrorKind {
+    /// Classify an io::Error coming out of `url_test`. clash-lib flattens most
+    /// failures into `ErrorKind::Other`, so fall back to the message text.
+    pub fn classify(error: &std::io::Error) -> Self {
+        use std::io::ErrorKind as Io;
+
+        match error.kind() {
+            Io::TimedOut => return ErrorKind::Timeout,
+            Io::ConnectionRefused | Io::ConnectionReset | Io::ConnectionAborted => {
+                return ErrorKind::ConnectionRefused;
+            }
+            _ => {}
+        }
+
+        let msg = error.to_string().to_lowercase();
+        if msg.contains("timed out") || msg.contains("timeout") || msg.contains("deadline") {
+            ErrorKind::Timeout
+        } else if msg.contains("refused") || msg.contains("reset by peer") {
+            ErrorKind::ConnectionRefused
+        } else if msg.contains("dns") || msg.contains("resolve") || msg.contains("lookup") {
+            ErrorKind::DnsFailure
+        } else if msg.contains("certificate") || msg.contains("unknownissuer") {
+            ErrorKind::CertificateError
+        } else if msg.contains("tls") || msg.contains("handshake") {
+            ErrorKind::TlsError
+        } else if msg.contains("protocol")
+            || msg.contains("invalid")
+            || msg.contains("unexpected")
+            || msg.contains("eof")
+        {
+            ErrorKind::ProtocolError
+        } else {
+            ErrorKind::Other
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorKind::Timeout => "timeout",
+            ErrorKind::ConnectionRefused => "connection_refused",
+            ErrorKind::DnsFailure => "dns_failure",
+            ErrorKind::TlsError => "tls_error",
+            ErrorKind::CertificateError => "certificate_error",
+            ErrorKind::ProtocolError => "protocol_error",
+            ErrorKind::Other => "other",
+        }
+    }
 }
 
 impl ProbeResult {
-    pub fn from_success(handler: &AnyOutboundHandler, delay: Duration) -> Self {
+    pub fn from_success(handler: &AnyOutboundHandler, delay: Duration, loss_pct: f64) -> Self {
         let (server, port) = extract_server_and_port(handler);
         ProbeResult {
             name: handler.name().to_string(),
+            proxy_id: String::new(),
+            probed_at: Utc::now(),
+            round_id: 0,
             server,
             port,
             protocol: format!("{}", handler.proto()),
+            region: region::from_name(handler.name()),
             alive: true,
             delay_ms: Some(delay.as_millis() as u64),
+            loss_pct,
             error: None,
+            error_kind: None,
+            targets: BTreeMap::new(),
+            uptime_1h: None,
+            uptime_24h: None,
+            consecutive_failures: 0,
+            last_alive_at: None,
+            p50_ms: None,
+            p95_ms: None,
+            p99_ms: None,
+            cert_not_after: None,
+            cert_sha256: None,
+            udp_ok: None,
+            phases: None,
+            first_request_ms: None,
+            second_request_ms: None,
+            tcp_rtt_ms: None,
+            node: None,
+            fields: None,
         }
     }
 
@@ -31,12 +238,35 @@ impl ProbeResult {
         let (server, port) = extract_server_and_port(handler);
         ProbeResult {
             name: handler.name().to_string(),
+            proxy_id: String::new(),
+            probed_at: Utc::now(),
+            round_id: 0,
             server,
             port,
             protocol: format!("{}", handler.proto()),
+            region: region::from_name(handler.name()),
             alive: false,
             delay_ms: None,
+            loss_pct: 100.0,
             error: Some(error.to_string()),
+            error_kind: Some(ErrorKind::classify(error)),
+            targets: BTreeMap::new(),
+            uptime_1h: None,
+            uptime_24h: None,
+            consecutive_failures: 0,
+            last_alive_at: None,
+            p50_ms: None,
+            p95_ms: None,
+            p99_ms: None,
+            cert_not_after: None,
+            cert_sha256: None,
+            udp_ok: None,
+            phases: None,
+            first_request_ms: None,
+            second_request_ms: None,
+            tcp_rtt_ms: None,
+            node: None,
+            fields: None,
         }
     }
 }
@@ -0,0 +1,95 @@
+use crate::config::PushoverConfig;
+use crate::probe_result::ProbeResult;
+use crate::reporter::ProbeReporter;
+use crate::summary::{AlertThrottle, RoundSummary, StateTracker};
+use anyhow::Result;
+use async_trait::async_trait;
+use std::sync::Mutex;
+use std::time::Duration;
+
+const API_URL: &str = "https://api.pushover.net/1/messages.json";
+/// Emergency (priority 2) messages repeat every `RETRY` seconds until
+/// acknowledged or `EXPIRE` seconds pass.
+const EMERGENCY_RETRY: &str = "60";
+const EMERGENCY_EXPIRE: &str = "3600";
+
+/// Pushes an alert through Pushover whenever proxies die or recover,
+/// prioritised by severity.
+pub struct PushoverReporter {
+    client: reqwest::Client,
+    config: PushoverConfig,
+    state: Mutex<StateTracker>,
+    throttle: Mutex<AlertThrottle>,
+}
+
+impl PushoverReporter {
+    pub fn new(config: &PushoverConfig) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.timeout))
+            .build()?;
+        Ok(Self {
+            client,
+            config: config.clone(),
+            state: Mutex::new(StateTracker::default()),
+            throttle: Mutex::new(AlertThrottle::new(Duration::from_secs(config.min_interval))),
+        })
+    }
+
+    /// Push `summary` as an alert.
+    async fn send(&self, summary: &RoundSummary) -> Result<()> {
+        let severity = summary.severity();
+
+        let priority = severity.priority(&self.config.priorities);
+        let title = format!(
+            "clashprobe {}: {}",
+            self.config.node_name,
+            severity.as_str()
+        );
+        let priority_str = priority.to_string();
+        let message = summary.alert_text();
+        let mut form = vec![
+            ("token", self.config.token.as_str()),
+            ("user", self.config.user_key.as_str()),
+            ("title", title.as_str()),
+            ("message", message.as_str()),
+            ("priority", priority_str.as_str()),
+        ];
+        if let Some(device) = &self.config.device {
+            form.push(("device", device));
+        }
+        if priority == 2 {
+            form.push(("retry", EMERGENCY_RETRY));
+            form.push(("expire", EMERGENCY_EXPIRE));
+        }
+
+        self.client
+            .post(API_URL)
+            .form(&form)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ProbeReporter for PushoverReporter {
+    async fn report(&self, results: &[ProbeResult]) -> Result<()> {
+        let summary = self.state.lock().unwrap().summarize(results, 0);
+        let severity = summary.severity();
+        if summary.is_alert() && self.throttle.lock().unwrap().allows(severity) {
+            self.send(&summary).await?;
+            self.throttle.lock().unwrap().sent();
+        }
+        self.state.lock().unwrap().record(results);
+        Ok(())
+    }
+
+    async fn restore(&self, results: &[ProbeResult]) {
+        self.state.lock().unwrap().record(results);
+    }
+
+    fn name(&self) -> &str {
+        "Pushover"
+    }
+}
@@ -0,0 +1,109 @@
+use crate::config::RedisConfig;
+use crate::probe_result::ProbeResult;
+use crate::reporter::ProbeReporter;
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::Utc;
+use redis::aio::ConnectionManager;
+use serde_json::json;
+
+/// Proxies listed as `best` in the round summary.
+const SUMMARY_BEST: usize = 5;
+
+/// Keeps the latest state of every proxy in Redis and announces each round
+/// on a pub/sub channel. Per node, under `<key_prefix>:<node_name>`:
+///
+/// - `...:proxy:<name>`: hash with the latest result
+/// - `...:latency`: sorted set of alive proxies scored by delay, so
+///   `ZRANGE ... 0 0` is the current fastest proxy
+pub struct RedisReporter {
+    connection: ConnectionManager,
+    config: RedisConfig,
+}
+
+impl RedisReporter {
+    pub async fn connect(config: &RedisConfig) -> Result<Self> {
+        let connection = redis::Client::open(config.url.as_str())?
+            .get_connection_manager()
+            .await?;
+        Ok(Self {
+            connection,
+            config: config.clone(),
+        })
+    }
+
+    fn key(&self, suffix: &str) -> String {
+        format!(
+            "{}:{}:{}",
+            self.config.key_prefix, self.config.node_name, suffix
+        )
+    }
+}
+
+#[async_trait]
+impl ProbeReporter for RedisReporter {
+    async fn report(&self, results: &[ProbeResult]) -> Result<()> {
+        let ttl = self.config.ttl as i64;
+        let updated_at = Utc::now().to_rfc3339();
+        let latency_key = self.key("latency");
+
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        for result in results {
+            let key = self.key(&format!("proxy:{}", result.name));
+            let fields = [
+                ("alive", result.alive.to_string()),
+                (
+                    "delay_ms",
+                    result.delay_ms.map(|d| d.to_string()).unwrap_or_default(),
+                ),
+                ("loss_pct", result.loss_pct.to_string()),
+                ("protocol", result.protocol.clone()),
+                ("server", result.server.clone()),
+                ("port", result.port.to_string()),
+                ("error", result.error.clone().unwrap_or_default()),
+                (
+                    "error_kind",
+                    result
+                        .error_kind
+                        .map(|k| k.as_str().to_string())
+                        .unwrap_or_default(),
+                ),
+                ("updated_at", updated_at.clone()),
+            ];
+            pipe.del(&key).ignore();
+            pipe.hset_multiple(&key, &fields).ignore();
+            pipe.expire(&key, ttl).ignore();
+        }
+
+        let alive: Vec<(u64, &str)> = results
+            .iter()
+            .filter(|r| r.alive)
+            .filter_map(|r| r.delay_ms.map(|d| (d, r.name.as_str())))
+            .collect();
+        pipe.del(&latency_key).ignore();
+        if !alive.is_empty() {
+            pipe.zadd_multiple(&latency_key, &alive).ignore();
+            pipe.expire(&latency_key, ttl).ignore();
+        }
+
+        let mut best = alive.clone();
+        best.sort();
+        let summary = json!({
+            "node": self.config.node_name,
+            "timestamp": updated_at,
+            "total": results.len(),
+            "alive": alive.len(),
+            "best": best.iter().take(SUMMARY_BEST).map(|(_, name)| name).collect::<Vec<_>>(),
+        });
+        pipe.publish(&self.config.channel, summary.to_string())
+            .ignore();
+
+        pipe.query_async::<()>(&mut self.connection.clone()).await?;
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "Redis"
+    }
+}
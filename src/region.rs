@@ -0,0 +1,136 @@
+/// Country and city names, lower case, checked in order.
+const KEYWORDS: &[(&str, &[&str])] = &[
+    ("HK", &["香港", "hong kong", "hongkong"]),
+    ("MO", &["澳门", "澳門", "macau", "macao"]),
+    ("TW", &["台湾", "台灣", "臺灣", "taiwan", "台北", "taipei"]),
+    (
+        "JP",
+        &["日本", "japan", "东京", "東京", "tokyo", "大阪", "osaka"],
+    ),
+    (
+        "KR",
+        &["韩国", "韓國", "korea", "首尔", "首爾", "seoul", "春川"],
+    ),
+    ("SG", &["新加坡", "狮城", "獅城", "singapore"]),
+    (
+        "US",
+        &[
+            "美国",
+            "美國",
+            "united states",
+            "洛杉矶",
+            "los angeles",
+            "硅谷",
+            "san jose",
+            "西雅图",
+            "seattle",
+            "芝加哥",
+            "chicago",
+            "纽约",
+            "new york",
+        ],
+    ),
+    ("GB", &["英国", "英國", "united kingdom", "伦敦", "london"]),
+    ("DE", &["德国", "德國", "germany", "法兰克福", "frankfurt"]),
+    ("FR", &["法国", "法國", "france", "巴黎", "paris"]),
+    (
+        "NL",
+        &["荷兰", "荷蘭", "netherlands", "阿姆斯特丹", "amsterdam"],
+    ),
+    ("RU", &["俄罗斯", "俄羅斯", "russia", "莫斯科", "moscow"]),
+    ("AU", &["澳大利亚", "澳洲", "australia", "悉尼", "sydney"]),
+    ("CA", &["加拿大", "canada", "多伦多", "toronto"]),
+    (
+        "TR",
+        &["土耳其", "turkey", "türkiye", "伊斯坦布尔", "istanbul"],
+    ),
+    (
+        "MY",
+        &["马来西亚", "馬來西亞", "malaysia", "吉隆坡", "kuala lumpur"],
+    ),
+    ("TH", &["泰国", "泰國", "thailand", "曼谷", "bangkok"]),
+    ("VN", &["越南", "vietnam"]),
+    ("PH", &["菲律宾", "菲律賓", "philippines"]),
+    // Before India, which "印度尼西亚" contains.
+    ("ID", &["印尼", "印度尼西亚", "indonesia"]),
+    ("IN", &["印度", "india", "孟买", "mumbai"]),
+    ("AR", &["阿根廷", "argentina"]),
+    ("BR", &["巴西", "brazil"]),
+    (
+        "CN",
+        &[
+            "中国", "中國", "china", "上海", "shanghai", "北京", "beijing",
+        ],
+    ),
+];
+
+/// Bare codes recognised as a separate word, e.g. "HK-01" or "[JP] 02".
+/// Upper case only, so ordinary words don't match.
+const CODES: &[(&str, &str)] = &[
+    ("HK", "HK"),
+    ("MO", "MO"),
+    ("TW", "TW"),
+    ("JP", "JP"),
+    ("KR", "KR"),
+    ("SG", "SG"),
+    ("US", "US"),
+    ("USA", "US"),
+    ("UK", "GB"),
+    ("GB", "GB"),
+    ("DE", "DE"),
+    ("FR", "FR"),
+    ("NL", "NL"),
+    ("RU", "RU"),
+    ("IN", "IN"),
+    ("AU", "AU"),
+    ("CA", "CA"),
+    ("TR", "TR"),
+    ("MY", "MY"),
+    ("TH", "TH"),
+    ("VN", "VN"),
+    ("PH", "PH"),
+    ("AR", "AR"),
+    ("BR", "BR"),
+    ("CN", "CN"),
+];
+
+/// ISO 3166 code of the region a proxy name points at: a flag emoji first,
+/// then a country or city name, then a bare code like "HK".
+pub fn from_name(name: &str) -> Option<String> {
+    if let Some(code) = flag(name) {
+        return Some(code);
+    }
+
+    let lower = name.to_lowercase();
+    if let Some((code, _)) = KEYWORDS
+        .iter()
+        .find(|(_, words)| words.iter().any(|word| lower.contains(word)))
+    {
+        return Some(code.to_string());
+    }
+
+    name.split(|c: char| !c.is_ascii_alphanumeric())
+        // "HK01" names the same region as "HK 01".
+        .map(|word| word.trim_end_matches(|c: char| c.is_ascii_digit()))
+        .find_map(|word| CODES.iter().find(|(token, _)| *token == word))
+        .map(|(_, code)| code.to_string())
+}
+
+/// Letters of the first flag emoji in `name`, a pair of regional indicator
+/// symbols. 🇺🇳 and other non-country pairs come through as-is.
+fn flag(name: &str) -> Option<String> {
+    const FIRST: u32 = 0x1F1E6;
+    let indicator = |c: char| (FIRST..FIRST + 26).contains(&(c as u32));
+
+    let chars: Vec<char> = name.chars().collect();
+    chars.windows(2).find_map(|pair| {
+        if !(indicator(pair[0]) && indicator(pair[1])) {
+            return None;
+        }
+        Some(
+            pair.iter()
+                .map(|&c| char::from(b'A' + (c as u32 - FIRST) as u8))
+                .collect(),
+        )
+    })
+}
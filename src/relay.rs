@@ -0,0 +1,136 @@
+use clash_lib::{
+    Session, app::dns::ThreadSafeDNSResolver, proxy::AnyOutboundHandler, session::SocksAddr,
+};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::{JoinHandle, JoinSet};
+use tracing::debug;
+
+/// A SOCKS5 server on loopback that tunnels every connection through one
+/// outbound handler, so plain HTTP clients can go through a subscription
+/// proxy. Stops, with every open tunnel, when dropped.
+pub struct Relay {
+    url: String,
+    task: JoinHandle<()>,
+}
+
+impl Relay {
+    pub async fn start(
+        handler: AnyOutboundHandler,
+        resolver: ThreadSafeDNSResolver,
+    ) -> io::Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await?;
+        // socks5h, so the client leaves name resolution to the proxy.
+        let url = format!("socks5h://{}", listener.local_addr()?);
+        let task = tokio::spawn(serve(listener, handler, resolver));
+        Ok(Self { url, task })
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+}
+
+impl Drop for Relay {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn serve(
+    listener: TcpListener,
+    handler: AnyOutboundHandler,
+    resolver: ThreadSafeDNSResolver,
+) {
+    // Dropped with this task on abort, which aborts the tunnels too.
+    let mut tunnels = JoinSet::new();
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                debug!("Relay stopped accepting: {}", e);
+                return;
+            }
+        };
+        let (handler, resolver) = (handler.clone(), resolver.clone());
+        tunnels.spawn(async move {
+            if let Err(e) = tunnel(stream, handler, resolver).await {
+                debug!("Relay tunnel closed: {}", e);
+            }
+        });
+    }
+}
+
+async fn tunnel(
+    mut client: TcpStream,
+    handler: AnyOutboundHandler,
+    resolver: ThreadSafeDNSResolver,
+) -> io::Result<()> {
+    let sess = Session {
+        destination: handshake(&mut client).await?,
+        ..Default::default()
+    };
+    let mut remote = handler.connect_stream(&sess, resolver).await?;
+    // Succeeded, bound to 0.0.0.0:0.
+    client.write_all(&[5, 0, 0, 1, 0, 0, 0, 0, 0, 0]).await?;
+    tokio::io::copy_bidirectional(&mut client, &mut remote).await?;
+    Ok(())
+}
+
+/// Read a SOCKS5 greeting, accepting it without auth, and a CONNECT
+/// request. Returns its destination.
+async fn handshake(stream: &mut TcpStream) -> io::Result<SocksAddr> {
+    let mut greeting = [0u8; 2];
+    stream.read_exact(&mut greeting).await?;
+    let mut methods = vec![0u8; greeting[1] as usize];
+    stream.read_exact(&mut methods).await?;
+    stream.write_all(&[5, 0]).await?;
+
+    // Version, command, reserved, address type.
+    let mut request = [0u8; 4];
+    stream.read_exact(&mut request).await?;
+    if request[1] != 1 {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "only CONNECT is relayed",
+        ));
+    }
+    let host = match request[3] {
+        1 => {
+            let mut ip = [0u8; 4];
+            stream.read_exact(&mut ip).await?;
+            Host::Ip(Ipv4Addr::from(ip).into())
+        }
+        3 => {
+            let mut name = vec![0u8; stream.read_u8().await? as usize];
+            stream.read_exact(&mut name).await?;
+            Host::Domain(
+                String::from_utf8(name)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+            )
+        }
+        4 => {
+            let mut ip = [0u8; 16];
+            stream.read_exact(&mut ip).await?;
+            Host::Ip(Ipv6Addr::from(ip).into())
+        }
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "unknown SOCKS5 address type",
+            ));
+        }
+    };
+    let port = stream.read_u16().await?;
+    Ok(match host {
+        Host::Ip(ip) => SocksAddr::Ip(SocketAddr::new(ip, port)),
+        Host::Domain(name) => SocksAddr::Domain(name, port),
+    })
+}
+
+enum Host {
+    Ip(IpAddr),
+    Domain(String),
+}
@@ -0,0 +1,47 @@
+use crate::config::{Config, ConfigOverrides, SharedConfig};
+use std::sync::Arc;
+use tracing::{error, info};
+
+/// Re-read the config file on SIGHUP. The new config replaces the live one
+/// only if it parses and validates; otherwise the old one stays in effect.
+#[cfg(unix)]
+pub fn watch_sighup(path: String, overrides: ConfigOverrides, config: SharedConfig) {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    let mut sighup = match signal(SignalKind::hangup()) {
+        Ok(sighup) => sighup,
+        Err(e) => {
+            error!("Failed to install SIGHUP handler: {}", e);
+            return;
+        }
+    };
+
+    tokio::spawn(async move {
+        while sighup.recv().await.is_some() {
+            info!("SIGHUP received, reloading config from {}", path);
+            reload(&path, &overrides, &config);
+        }
+    });
+}
+
+#[cfg(not(unix))]
+pub fn watch_sighup(_path: String, _overrides: ConfigOverrides, _config: SharedConfig) {}
+
+fn reload(path: &str, overrides: &ConfigOverrides, config: &SharedConfig) {
+    let mut new_config = match Config::load_from_file(path) {
+        Ok(new_config) => new_config,
+        Err(e) => {
+            error!("Config reload failed, keeping current config: {}", e);
+            return;
+        }
+    };
+    overrides.apply(&mut new_config);
+
+    if let Err(e) = new_config.validate() {
+        error!("Reloaded config is invalid, keeping current config: {}", e);
+        return;
+    }
+
+    *config.write().unwrap() = Arc::new(new_config);
+    info!("Config reloaded");
+}
@@ -0,0 +1,138 @@
+use crate::config::RemoteWriteConfig;
+use crate::probe_result::ProbeResult;
+use crate::reporter::ProbeReporter;
+use anyhow::Result;
+use async_trait::async_trait;
+use prost::Message;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// Prometheus remote_write 1.0 messages (prometheus/prompb), just the fields
+// we send.
+
+#[derive(Clone, PartialEq, Message)]
+struct WriteRequest {
+    #[prost(message, repeated, tag = "1")]
+    timeseries: Vec<TimeSeries>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct TimeSeries {
+    #[prost(message, repeated, tag = "1")]
+    labels: Vec<Label>,
+    #[prost(message, repeated, tag = "2")]
+    samples: Vec<Sample>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct Label {
+    #[prost(string, tag = "1")]
+    name: String,
+    #[prost(string, tag = "2")]
+    value: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct Sample {
+    #[prost(double, tag = "1")]
+    value: f64,
+    #[prost(int64, tag = "2")]
+    timestamp: i64,
+}
+
+/// Pushes every round to a Prometheus remote_write endpoint as
+/// `clashprobe_up`, `clashprobe_delay_ms` and `clashprobe_loss_pct`.
+pub struct RemoteWriteReporter {
+    client: reqwest::Client,
+    config: RemoteWriteConfig,
+}
+
+impl RemoteWriteReporter {
+    pub fn new(config: &RemoteWriteConfig) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.timeout))
+            .build()?;
+        Ok(Self {
+            client,
+            config: config.clone(),
+        })
+    }
+
+    fn series(&self, metric: &str, result: &ProbeResult, value: f64, timestamp: i64) -> TimeSeries {
+        let mut labels: Vec<Label> = [
+            ("__name__", metric),
+            ("proxy", result.name.as_str()),
+            ("protocol", result.protocol.as_str()),
+            ("server", result.server.as_str()),
+        ]
+        .into_iter()
+        .chain(
+            self.config
+                .external_labels
+                .iter()
+                .map(|(name, value)| (name.as_str(), value.as_str())),
+        )
+        .map(|(name, value)| Label {
+            name: name.to_string(),
+            value: value.to_string(),
+        })
+        .collect();
+        // Receivers require labels sorted by name.
+        labels.sort_by(|a, b| a.name.cmp(&b.name));
+
+        TimeSeries {
+            labels,
+            samples: vec![Sample { value, timestamp }],
+        }
+    }
+
+    fn write_request(&self, results: &[ProbeResult]) -> Result<WriteRequest> {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
+        let mut timeseries = Vec::with_capacity(results.len() * 3);
+        for result in results {
+            let up = if result.alive { 1.0 } else { 0.0 };
+            timeseries.push(self.series("clashprobe_up", result, up, timestamp));
+            timeseries.push(self.series("clashprobe_loss_pct", result, result.loss_pct, timestamp));
+            if let Some(delay_ms) = result.delay_ms {
+                timeseries.push(self.series(
+                    "clashprobe_delay_ms",
+                    result,
+                    delay_ms as f64,
+                    timestamp,
+                ));
+            }
+        }
+        Ok(WriteRequest { timeseries })
+    }
+}
+
+#[async_trait]
+impl ProbeReporter for RemoteWriteReporter {
+    async fn report(&self, results: &[ProbeResult]) -> Result<()> {
+        let body = self.write_request(results)?.encode_to_vec();
+        let body = snap::raw::Encoder::new().compress_vec(&body)?;
+
+        let mut request = self
+            .client
+            .post(&self.config.url)
+            .header("Content-Encoding", "snappy")
+            .header("Content-Type", "application/x-protobuf")
+            .header("X-Prometheus-Remote-Write-Version", "0.1.0")
+            .body(body);
+        if let Some(token) = &self.config.bearer_token {
+            request = request.bearer_auth(token);
+        } else if let Some(user) = &self.config.basic_auth_user {
+            request = request.basic_auth(user, self.config.basic_auth_password.as_ref());
+        }
+
+        request
+            .send()
+            .await?
+            .error_for_status()
+            .map_err(|e| anyhow::anyhow!("remote_write push failed: {}", e))?;
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "RemoteWrite"
+    }
+}
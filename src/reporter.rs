@@ -1,14 +1,96 @@
+use crate::group::GroupStats;
+use crate::parser::ParseStats;
 use crate::probe_result::ProbeResult;
+use crate::subscription::SubscriptionInfo;
 use anyhow::Result;
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+
+/// Delivery counters per reporter name, updated by the engine every round.
+pub type SharedReporterStats = Arc<RwLock<BTreeMap<String, ReporterStats>>>;
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ReporterStats {
+    pub successes: u64,
+    pub failures: u64,
+    pub timeouts: u64,
+    /// Rounds queued for retry during backoff without trying the sink.
+    pub deferred: u64,
+    pub last_error: Option<String>,
+    pub last_duration_ms: Option<u64>,
+}
+
+/// The latest completed round, updated by the engine before reporters run.
+pub type SharedRoundInfo = Arc<RwLock<Option<RoundInfo>>>;
+
+/// Fleet-level facts about one round, independent of reporter filters.
+#[derive(Debug, Clone, Serialize)]
+pub struct RoundInfo {
+    pub finished_at: DateTime<Utc>,
+    pub duration_ms: u64,
+    /// Proxies with a known state, including ones not due this round.
+    pub total: usize,
+    pub alive: usize,
+    /// Proxies actually probed this round.
+    pub probed: usize,
+    /// How the subscription parsed at startup.
+    pub parse: ParseStats,
+    /// One entry per configured `[groups]` table.
+    pub groups: Vec<GroupStats>,
+    /// Traffic and expiry from the last subscription download, if the
+    /// provider reports them.
+    pub subscription: Option<SubscriptionInfo>,
+    /// Delay of each test URL fetched without a proxy, with
+    /// `main.direct_baseline` on; `None` when the fetch failed.
+    pub baseline: BTreeMap<String, Option<u64>>,
+    /// Rounds since startup that ran past the probe interval, this one
+    /// included once it has.
+    pub overruns: u64,
+}
 
 #[async_trait]
 pub trait ProbeReporter: Send + Sync {
     async fn report(&self, results: &[ProbeResult]) -> Result<()>;
 
+    /// Called instead of `report` when a round failed or timed out.
+    async fn report_failure(&self, _error: &str) -> Result<()> {
+        Ok(())
+    }
+
+    /// Push out anything still buffered. Called once before shutdown.
+    async fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// One proxy's result as soon as it is probed, ahead of the round's
+    /// `report`. Only called when [`Self::is_streaming`] is true. The result
+    /// is raw: the round's `report` may still differ once flapping damping
+    /// and uptime figures are applied.
+    async fn report_partial(&self, _result: &ProbeResult) -> Result<()> {
+        Ok(())
+    }
+
+    /// Results of the last round before a restart, from `main.state_file`,
+    /// handed over once before the first round. Nothing should be sent:
+    /// they only seed what the next round is compared against.
+    async fn restore(&self, _results: &[ProbeResult]) {}
+
+    /// A one-off message for people, e.g. that the subscription is about
+    /// to expire. Reporters without a chat-like sink ignore it.
+    async fn report_notice(&self, _text: &str) -> Result<()> {
+        Ok(())
+    }
+
     fn is_continuous(&self) -> bool {
         true
     }
 
+    fn is_streaming(&self) -> bool {
+        false
+    }
+
     fn name(&self) -> &str;
 }
@@ -0,0 +1,167 @@
+use crate::config::RetryConfig;
+use crate::probe_result::ProbeResult;
+use crate::reporter::ProbeReporter;
+use anyhow::Result;
+use async_trait::async_trait;
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+/// Wraps a reporter so rounds it fails to deliver are queued in memory and
+/// replayed, oldest first, once the sink accepts writes again. While backing
+/// off, new rounds go straight to the queue instead of hammering the sink.
+pub struct RetryingReporter {
+    inner: Box<dyn ProbeReporter>,
+    config: RetryConfig,
+    state: Mutex<RetryState>,
+}
+
+/// Error [`RetryingReporter::report`] returns for a round queued during
+/// backoff without trying the sink: not delivered, but not failed either.
+#[derive(Debug)]
+pub struct Deferred;
+
+impl fmt::Display for Deferred {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("queued while backing off")
+    }
+}
+
+impl std::error::Error for Deferred {}
+
+struct RetryState {
+    queue: VecDeque<Vec<ProbeResult>>,
+    backoff: Duration,
+    retry_at: Option<Instant>,
+}
+
+impl RetryingReporter {
+    pub fn new(inner: Box<dyn ProbeReporter>, config: RetryConfig) -> Self {
+        Self {
+            inner,
+            state: Mutex::new(RetryState {
+                queue: VecDeque::new(),
+                backoff: Duration::from_secs(config.initial_backoff),
+                retry_at: None,
+            }),
+            config,
+        }
+    }
+
+    fn enqueue(&self, state: &mut RetryState, results: &[ProbeResult]) {
+        if state.queue.len() >= self.config.queue_size {
+            state.queue.pop_front();
+            warn!(
+                "Reporter '{}' retry queue is full, dropping the oldest round",
+                self.inner.name()
+            );
+        }
+        state.queue.push_back(results.to_vec());
+    }
+
+    fn back_off(&self, state: &mut RetryState) {
+        state.retry_at = Some(Instant::now() + state.backoff);
+        warn!(
+            "Reporter '{}' will retry in {}s with {} rounds queued",
+            self.inner.name(),
+            state.backoff.as_secs(),
+            state.queue.len()
+        );
+        state.backoff = (state.backoff * 2).min(Duration::from_secs(self.config.max_backoff));
+    }
+
+    /// Deliver queued rounds oldest first, stopping at the first failure. A
+    /// round leaves the queue only once delivered, so an interrupted replay
+    /// loses nothing.
+    async fn replay(&self, state: &mut RetryState) -> Result<usize> {
+        let mut replayed = 0;
+        while let Some(batch) = state.queue.front() {
+            self.inner.report(batch).await?;
+            state.queue.pop_front();
+            replayed += 1;
+        }
+        Ok(replayed)
+    }
+}
+
+#[async_trait]
+impl ProbeReporter for RetryingReporter {
+    /// The round joins the queue before anything is sent and leaves it once
+    /// delivered, so a report cut short partway loses nothing.
+    async fn report(&self, results: &[ProbeResult]) -> Result<()> {
+        let mut state = self.state.lock().await;
+        self.enqueue(&mut state, results);
+        if state.retry_at.is_some_and(|at| Instant::now() < at) {
+            return Err(Deferred.into());
+        }
+
+        match self.replay(&mut state).await {
+            Ok(replayed) => {
+                if replayed > 1 {
+                    info!(
+                        "Reporter '{}' recovered, replayed {} queued rounds",
+                        self.inner.name(),
+                        replayed - 1
+                    );
+                }
+                state.backoff = Duration::from_secs(self.config.initial_backoff);
+                state.retry_at = None;
+                Ok(())
+            }
+            Err(e) => {
+                self.back_off(&mut state);
+                Err(e)
+            }
+        }
+    }
+
+    async fn report_failure(&self, error: &str) -> Result<()> {
+        self.inner.report_failure(error).await
+    }
+
+    /// One last attempt at the queue, ignoring the backoff, before the
+    /// inner reporter flushes.
+    async fn flush(&self) -> Result<()> {
+        let mut state = self.state.lock().await;
+        let replayed = self.replay(&mut state).await;
+        let flushed = self.inner.flush().await;
+        if let Err(e) = replayed {
+            warn!(
+                "Reporter '{}' dropped {} queued rounds at shutdown",
+                self.inner.name(),
+                state.queue.len()
+            );
+            return Err(e);
+        }
+        flushed
+    }
+
+    /// Passed straight through: partial results are superseded by the
+    /// round's `report`, so they are never queued.
+    async fn report_partial(&self, result: &ProbeResult) -> Result<()> {
+        self.inner.report_partial(result).await
+    }
+
+    async fn report_notice(&self, text: &str) -> Result<()> {
+        self.inner.report_notice(text).await
+    }
+
+    async fn restore(&self, results: &[ProbeResult]) {
+        self.inner.restore(results).await
+    }
+
+    fn is_continuous(&self) -> bool {
+        self.inner.is_continuous()
+    }
+
+    fn is_streaming(&self) -> bool {
+        self.inner.is_streaming()
+    }
+
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+}
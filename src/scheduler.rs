@@ -0,0 +1,259 @@
+use crate::config::MainConfig;
+use crate::latency::LatencyWindow;
+use crate::probe_result::ProbeResult;
+use crate::region;
+use crate::uptime::UptimeWindow;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Per-proxy round scheduler. Alive proxies are probed every round; dead ones
+/// back off exponentially (1, 2, 4, ... rounds) up to `max_backoff_rounds`.
+///
+/// It also damps flapping: the reported result only flips between alive and
+/// dead after enough consecutive observations of the new state. Uptime and
+/// failure streaks are counted from the raw observations.
+pub struct AdaptiveScheduler {
+    round: u64,
+    max_backoff_rounds: u64,
+    failures_to_dead: u32,
+    successes_to_alive: u32,
+    latency_window: usize,
+    slots: Vec<Slot>,
+}
+
+struct Slot {
+    consecutive_failures: u32,
+    consecutive_successes: u32,
+    next_round: u64,
+    last: Option<ProbeResult>,
+    uptime: UptimeWindow,
+    last_alive_at: Option<DateTime<Utc>>,
+    latency: LatencyWindow,
+}
+
+/// A slot as saved across restarts.
+#[derive(Serialize, Deserialize)]
+pub struct SavedSlot {
+    consecutive_failures: u32,
+    consecutive_successes: u32,
+    /// Rounds until it is due again.
+    backoff: u64,
+    last: Option<ProbeResult>,
+    uptime: UptimeWindow,
+    last_alive_at: Option<DateTime<Utc>>,
+    delays: Vec<u64>,
+}
+
+impl Slot {
+    fn new(latency_window: usize) -> Self {
+        Self {
+            consecutive_failures: 0,
+            consecutive_successes: 0,
+            next_round: 0,
+            last: None,
+            uptime: UptimeWindow::default(),
+            last_alive_at: None,
+            latency: LatencyWindow::new(latency_window),
+        }
+    }
+
+    /// Keep the last result under the proxy's current name, should the
+    /// provider have renamed it.
+    fn rename(&mut self, name: &str) {
+        if let Some(last) = &mut self.last
+            && last.name != name
+        {
+            last.name = name.to_string();
+            last.region = region::from_name(name);
+        }
+    }
+}
+
+impl AdaptiveScheduler {
+    pub fn new(proxy_count: usize, config: &MainConfig) -> Self {
+        Self {
+            round: 0,
+            max_backoff_rounds: config.max_backoff_rounds.max(1),
+            failures_to_dead: config.consecutive_failures_to_dead.max(1),
+            successes_to_alive: config.consecutive_successes_to_alive.max(1),
+            latency_window: config.latency_window,
+            slots: (0..proxy_count)
+                .map(|_| Slot::new(config.latency_window))
+                .collect(),
+        }
+    }
+
+    /// Apply settings from a reloaded config. Proxies backing off longer
+    /// than a lowered `max_backoff_rounds` are brought forward, and latency
+    /// windows shrunk below their delays drop the oldest.
+    pub fn reconfigure(&mut self, config: &MainConfig) {
+        self.max_backoff_rounds = config.max_backoff_rounds.max(1);
+        self.failures_to_dead = config.consecutive_failures_to_dead.max(1);
+        self.successes_to_alive = config.consecutive_successes_to_alive.max(1);
+        self.latency_window = config.latency_window;
+        let latest_due = self.round + self.max_backoff_rounds;
+        for slot in &mut self.slots {
+            slot.next_round = slot.next_round.min(latest_due);
+            slot.latency.resize(config.latency_window);
+        }
+    }
+
+    /// ID of the current round, as stamped on its results.
+    pub fn round_id(&self) -> u64 {
+        self.round + 1
+    }
+
+    /// Carry state over to a refreshed proxy list. `previous[i]` is the old
+    /// index of proxy `i`, if it was in the old list, and `names[i]` its
+    /// current name; a proxy renamed by the provider keeps its history.
+    pub fn remap(&mut self, previous: &[Option<usize>], names: &[&str]) {
+        let mut old: Vec<Option<Slot>> = std::mem::take(&mut self.slots)
+            .into_iter()
+            .map(Some)
+            .collect();
+        self.slots = previous
+            .iter()
+            .zip(names)
+            .map(|(index, name)| {
+                let Some(mut slot) = index.and_then(|index| old[index].take()) else {
+                    return Slot::new(self.latency_window);
+                };
+                slot.rename(name);
+                slot
+            })
+            .collect();
+    }
+
+    /// State of every proxy, keyed by `ids[i]` for proxy `i`, and the
+    /// current round.
+    pub fn save(&self, ids: &[String]) -> (u64, HashMap<String, SavedSlot>) {
+        let slots = ids
+            .iter()
+            .zip(&self.slots)
+            .map(|(id, slot)| {
+                let saved = SavedSlot {
+                    consecutive_failures: slot.consecutive_failures,
+                    consecutive_successes: slot.consecutive_successes,
+                    backoff: slot.next_round.saturating_sub(self.round),
+                    last: slot.last.clone(),
+                    uptime: slot.uptime.clone(),
+                    last_alive_at: slot.last_alive_at,
+                    delays: slot.latency.delays().collect(),
+                };
+                (id.clone(), saved)
+            })
+            .collect();
+        (self.round, slots)
+    }
+
+    /// Take up state saved by [`Self::save`] for every proxy whose ID is in
+    /// `saved`; the others start afresh. `ids` and `names` are as in
+    /// [`Self::remap`]. Returns how many proxies got their state back.
+    pub fn restore(
+        &mut self,
+        ids: &[String],
+        names: &[&str],
+        round: u64,
+        mut saved: HashMap<String, SavedSlot>,
+    ) -> usize {
+        self.round = round;
+        let mut restored = 0;
+        for ((id, name), slot) in ids.iter().zip(names).zip(&mut self.slots) {
+            let Some(saved) = saved.remove(id) else {
+                continue;
+            };
+            let mut latency = LatencyWindow::new(self.latency_window);
+            for delay_ms in saved.delays {
+                latency.record(delay_ms);
+            }
+            *slot = Slot {
+                consecutive_failures: saved.consecutive_failures,
+                consecutive_successes: saved.consecutive_successes,
+                next_round: round + saved.backoff.min(self.max_backoff_rounds),
+                last: saved.last,
+                uptime: saved.uptime,
+                last_alive_at: saved.last_alive_at,
+                latency,
+            };
+            slot.rename(name);
+            restored += 1;
+        }
+        restored
+    }
+
+    /// Indices of the proxies that should be probed in the current round.
+    pub fn due(&self) -> Vec<usize> {
+        self.slots
+            .iter()
+            .enumerate()
+            .filter(|(_, slot)| slot.next_round <= self.round)
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    pub fn record(&mut self, index: usize, result: ProbeResult) {
+        let now = Utc::now();
+        let slot = &mut self.slots[index];
+        slot.uptime.record(now, result.alive);
+        if let Some(delay_ms) = result.delay_ms {
+            slot.latency.record(delay_ms);
+        }
+        if result.alive {
+            slot.last_alive_at = Some(now);
+            slot.consecutive_failures = 0;
+            slot.consecutive_successes = slot.consecutive_successes.saturating_add(1);
+            slot.next_round = self.round + 1;
+        } else {
+            slot.consecutive_successes = 0;
+            slot.consecutive_failures = slot.consecutive_failures.saturating_add(1);
+            let backoff = 1u64
+                .checked_shl(slot.consecutive_failures - 1)
+                .unwrap_or(u64::MAX)
+                .min(self.max_backoff_rounds);
+            slot.next_round = self.round + backoff;
+        }
+
+        // The first observation sets the state; later ones must hold long
+        // enough before the reported state flips.
+        let (streak, needed) = if result.alive {
+            (slot.consecutive_successes, self.successes_to_alive)
+        } else {
+            (slot.consecutive_failures, self.failures_to_dead)
+        };
+        let flips = slot
+            .last
+            .as_ref()
+            .is_some_and(|last| last.alive != result.alive);
+        if !flips || streak >= needed {
+            slot.last = Some(result);
+        }
+    }
+
+    /// [`Self::latest`], then move on to the next round.
+    pub fn finish_round(&mut self) -> Vec<ProbeResult> {
+        self.round += 1;
+        self.latest()
+    }
+
+    /// Latest known result of every proxy probed at least once, with its
+    /// current uptime figures.
+    pub fn latest(&self) -> Vec<ProbeResult> {
+        let now = Utc::now();
+        self.slots
+            .iter()
+            .filter_map(|slot| {
+                let mut result = slot.last.clone()?;
+                result.uptime_1h = slot.uptime.uptime_pct(now, 1);
+                result.uptime_24h = slot.uptime.uptime_pct(now, 24);
+                result.consecutive_failures = slot.consecutive_failures;
+                result.last_alive_at = slot.last_alive_at;
+                let percentiles = slot.latency.percentiles();
+                result.p50_ms = percentiles.map(|[p50, _, _]| p50);
+                result.p95_ms = percentiles.map(|[_, p95, _]| p95);
+                result.p99_ms = percentiles.map(|[_, _, p99]| p99);
+                Some(result)
+            })
+            .collect()
+    }
+}
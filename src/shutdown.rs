@@ -0,0 +1,61 @@
+use std::sync::Arc;
+use tokio::sync::watch;
+use tracing::{error, info};
+
+pub type ShutdownSignal = watch::Receiver<bool>;
+
+/// Lets in-process components, e.g. the TUI, request shutdown themselves.
+pub type ShutdownTrigger = Arc<watch::Sender<bool>>;
+
+/// Spawn a task that flips the returned signal on SIGINT or SIGTERM.
+pub fn listen() -> ShutdownSignal {
+    listen_with_trigger().1
+}
+
+/// Like [`listen`], but also hand out a trigger for the same signal.
+pub fn listen_with_trigger() -> (ShutdownTrigger, ShutdownSignal) {
+    let (sender, receiver) = watch::channel(false);
+    let sender = Arc::new(sender);
+    let trigger = sender.clone();
+
+    tokio::spawn(async move {
+        wait_for_signal().await;
+        info!("Shutdown signal received");
+        let _ = sender.send(true);
+        // Keep the sender alive so receivers never see a closed channel.
+        std::future::pending::<()>().await;
+    });
+
+    (trigger, receiver)
+}
+
+/// Resolve once shutdown has been requested.
+pub async fn requested(signal: &mut ShutdownSignal) {
+    if signal.wait_for(|stop| *stop).await.is_err() {
+        std::future::pending::<()>().await;
+    }
+}
+
+#[cfg(unix)]
+async fn wait_for_signal() {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    let mut sigterm = match signal(SignalKind::terminate()) {
+        Ok(sigterm) => sigterm,
+        Err(e) => {
+            error!("Failed to install SIGTERM handler: {}", e);
+            let _ = tokio::signal::ctrl_c().await;
+            return;
+        }
+    };
+
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = sigterm.recv() => {}
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_signal() {
+    let _ = tokio::signal::ctrl_c().await;
+}
@@ -0,0 +1,137 @@
+use crate::config::SlackConfig;
+use crate::probe_result::ProbeResult;
+use crate::reporter::ProbeReporter;
+use crate::summary::{FASTEST, RoundSummary, StateTracker};
+use anyhow::Result;
+use async_trait::async_trait;
+use serde_json::{Value, json};
+use std::fmt::Write as _;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Names listed under "Newly dead"/"Recovered" before collapsing the rest,
+/// keeping blocks under Slack's 3000 character text limit.
+const MAX_LISTED: usize = 20;
+
+/// Posts a Block Kit summary of every round to a Slack incoming webhook.
+pub struct SlackReporter {
+    client: reqwest::Client,
+    config: SlackConfig,
+    state: Mutex<StateTracker>,
+}
+
+impl SlackReporter {
+    pub fn new(config: &SlackConfig) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.timeout))
+            .build()?;
+        Ok(Self {
+            client,
+            config: config.clone(),
+            state: Mutex::new(StateTracker::default()),
+        })
+    }
+
+    fn message(&self, summary: &RoundSummary) -> Value {
+        let counts = format!(
+            "*Alive:* {}/{}    *Dead:* {}",
+            summary.alive,
+            summary.total,
+            summary.dead()
+        );
+        let mut blocks = vec![
+            json!({
+                "type": "header",
+                "text": {
+                    "type": "plain_text",
+                    "text": format!("clashprobe · {}", self.config.node_name),
+                },
+            }),
+            section(&counts),
+        ];
+
+        if !summary.fastest.is_empty() {
+            let mut text = String::from("*Fastest*");
+            for (i, (name, delay)) in summary.fastest.iter().enumerate() {
+                let _ = write!(text, "\n{}. {} — {} ms", i + 1, escape(name), delay);
+            }
+            blocks.push(section(&text));
+        }
+        if !summary.newly_dead.is_empty() {
+            blocks.push(section(&name_list(
+                ":red_circle: *Newly dead*",
+                &summary.newly_dead,
+            )));
+        }
+        if !summary.recovered.is_empty() {
+            blocks.push(section(&name_list(
+                ":large_green_circle: *Recovered*",
+                &summary.recovered,
+            )));
+        }
+
+        let fallback = format!(
+            "clashprobe {}: {}/{} alive",
+            self.config.node_name, summary.alive, summary.total
+        );
+        json!({ "text": fallback, "blocks": blocks })
+    }
+}
+
+fn section(text: &str) -> Value {
+    json!({ "type": "section", "text": { "type": "mrkdwn", "text": text } })
+}
+
+fn name_list(title: &str, names: &[String]) -> String {
+    let mut text = title.to_string();
+    for name in names.iter().take(MAX_LISTED) {
+        let _ = write!(text, "\n• {}", escape(name));
+    }
+    if names.len() > MAX_LISTED {
+        let _ = write!(text, "\n…and {} more", names.len() - MAX_LISTED);
+    }
+    text
+}
+
+/// Slack mrkdwn only needs `&`, `<` and `>` escaped.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[async_trait]
+impl ProbeReporter for SlackReporter {
+    async fn report(&self, results: &[ProbeResult]) -> Result<()> {
+        let summary = self.state.lock().unwrap().summarize(results, FASTEST);
+        if !self.config.only_on_change || summary.changed() {
+            self.client
+                .post(&self.config.webhook_url)
+                .json(&self.message(&summary))
+                .send()
+                .await?
+                .error_for_status()?;
+        }
+        self.state.lock().unwrap().record(results);
+        Ok(())
+    }
+
+    async fn report_notice(&self, text: &str) -> Result<()> {
+        let text = format!("clashprobe · {}: {}", self.config.node_name, text);
+        self.client
+            .post(&self.config.webhook_url)
+            .json(&json!({ "text": text }))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    async fn restore(&self, results: &[ProbeResult]) {
+        self.state.lock().unwrap().record(results);
+    }
+
+    fn name(&self) -> &str {
+        "Slack"
+    }
+}
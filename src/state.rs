@@ -0,0 +1,34 @@
+use crate::scheduler::SavedSlot;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// What `main.state_file` holds: the scheduler's view of every proxy, so
+/// results, uptime and flap damping survive a restart.
+#[derive(Serialize, Deserialize)]
+pub struct Snapshot {
+    pub saved_at: DateTime<Utc>,
+    /// Rounds finished so far, so round IDs keep counting up.
+    pub round: u64,
+    /// By stable proxy ID, so renamed proxies keep their state.
+    pub proxies: HashMap<String, SavedSlot>,
+}
+
+/// The snapshot in `path`, or `None` if there is no file yet.
+pub async fn load(path: &str) -> Result<Option<Snapshot>> {
+    let content = match tokio::fs::read(path).await {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    Ok(Some(serde_json::from_slice(&content)?))
+}
+
+/// Write through a temporary file so a crash never leaves half a snapshot.
+pub async fn save(path: &str, snapshot: &Snapshot) -> Result<()> {
+    let tmp = format!("{}.tmp", path);
+    tokio::fs::write(&tmp, serde_json::to_vec(snapshot)?).await?;
+    tokio::fs::rename(&tmp, path).await?;
+    Ok(())
+}
@@ -0,0 +1,164 @@
+use crate::config::{StatsdConfig, StatsdFlavor};
+use crate::graphite::sanitize;
+use crate::probe_result::{ErrorKind, ProbeResult};
+use crate::reporter::ProbeReporter;
+use anyhow::Result;
+use async_trait::async_trait;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+
+/// Keep datagrams under a typical MTU so agents don't see truncated packets.
+const MAX_PACKET_BYTES: usize = 1432;
+
+/// Sends per-proxy gauges (`alive`, `delay_ms`, `loss_pct`) and a failure
+/// counter by error kind to a StatsD or DogStatsD agent.
+pub struct StatsdReporter {
+    config: StatsdConfig,
+    last_sent: Mutex<Option<Instant>>,
+}
+
+impl StatsdReporter {
+    pub fn new(config: &StatsdConfig) -> Self {
+        Self {
+            config: config.clone(),
+            last_sent: Mutex::new(None),
+        }
+    }
+
+    /// Whether `sample_interval` has passed since the last send; marks now as
+    /// sent if so.
+    fn due(&self) -> bool {
+        let interval = Duration::from_secs(self.config.sample_interval);
+        let mut last_sent = self.last_sent.lock().unwrap();
+        if last_sent.is_some_and(|at| at.elapsed() < interval) {
+            return false;
+        }
+        *last_sent = Some(Instant::now());
+        true
+    }
+
+    fn lines(&self, results: &[ProbeResult]) -> Vec<String> {
+        let mut lines = Vec::new();
+        let mut failures: BTreeMap<&str, u64> = BTreeMap::new();
+
+        for result in results {
+            let mut gauge = |metric: &str, value: String| {
+                lines.push(self.metric(metric, &value, "g", Some(result)));
+            };
+            gauge("alive", (result.alive as u8).to_string());
+            gauge("loss_pct", result.loss_pct.to_string());
+            if let Some(delay_ms) = result.delay_ms {
+                gauge("delay_ms", delay_ms.to_string());
+            }
+            if !result.alive {
+                let kind = result.error_kind.unwrap_or(ErrorKind::Other).as_str();
+                *failures.entry(kind).or_default() += 1;
+            }
+        }
+
+        for (kind, count) in failures {
+            lines.push(match self.config.flavor {
+                StatsdFlavor::Statsd => {
+                    self.metric(&format!("failures.{}", kind), &count.to_string(), "c", None)
+                }
+                StatsdFlavor::Dogstatsd => format!(
+                    "{}.failures:{}|c{}",
+                    self.config.prefix,
+                    count,
+                    self.tags(&[("error_kind", kind)])
+                ),
+            });
+        }
+        lines
+    }
+
+    fn metric(&self, metric: &str, value: &str, kind: &str, proxy: Option<&ProbeResult>) -> String {
+        match (self.config.flavor, proxy) {
+            (StatsdFlavor::Statsd, Some(result)) => format!(
+                "{}.proxy.{}.{}:{}|{}",
+                self.config.prefix,
+                sanitize(&result.name),
+                metric,
+                value,
+                kind
+            ),
+            (StatsdFlavor::Dogstatsd, Some(result)) => format!(
+                "{}.proxy.{}:{}|{}{}",
+                self.config.prefix,
+                metric,
+                value,
+                kind,
+                self.tags(&[
+                    ("proxy", result.name.as_str()),
+                    ("protocol", result.protocol.as_str()),
+                    ("server", result.server.as_str()),
+                ])
+            ),
+            (_, None) => format!("{}.{}:{}|{}", self.config.prefix, metric, value, kind),
+        }
+    }
+
+    /// DogStatsD tag suffix: `|#key:value,...` with the configured tags.
+    fn tags(&self, extra: &[(&str, &str)]) -> String {
+        let tags: Vec<String> = self
+            .config
+            .tags
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .chain(extra.iter().copied())
+            .map(|(k, v)| format!("{}:{}", k, v.replace([',', '|', '#'], "_")))
+            .collect();
+        format!("|#{}", tags.join(","))
+    }
+}
+
+/// Pack newline-separated lines into datagrams of at most
+/// `MAX_PACKET_BYTES`.
+fn packets(lines: &[String]) -> Vec<String> {
+    let mut packets = Vec::new();
+    let mut current = String::new();
+    for line in lines {
+        if !current.is_empty() && current.len() + 1 + line.len() > MAX_PACKET_BYTES {
+            packets.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push('\n');
+        }
+        current.push_str(line);
+    }
+    if !current.is_empty() {
+        packets.push(current);
+    }
+    packets
+}
+
+#[async_trait]
+impl ProbeReporter for StatsdReporter {
+    async fn report(&self, results: &[ProbeResult]) -> Result<()> {
+        if !self.due() {
+            return Ok(());
+        }
+
+        let address = tokio::net::lookup_host((self.config.host.as_str(), self.config.port))
+            .await?
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("StatsD host {} did not resolve", self.config.host))?;
+        let local = if address.is_ipv4() {
+            "0.0.0.0:0"
+        } else {
+            "[::]:0"
+        };
+        let socket = UdpSocket::bind(local).await?;
+        socket.connect(address).await?;
+        for packet in packets(&self.lines(results)) {
+            socket.send(packet.as_bytes()).await?;
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "StatsD"
+    }
+}
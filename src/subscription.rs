@@ -1,17 +1,361 @@
+use crate::config::MainConfig;
 use anyhow::Result;
+use base64::engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig};
+use base64::{Engine, alphabet, prelude::BASE64_STANDARD};
+use chrono::{DateTime, Utc};
+use flate2::read::GzDecoder;
 use reqwest;
+use reqwest::StatusCode;
+use reqwest::header::{
+    ETAG, HeaderMap, HeaderName, HeaderValue, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED,
+};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::io::Read;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::io::AsyncReadExt;
+use tracing::warn;
+
+/// Time allowed for one download by [`fetch_subscription`].
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// First pause between download attempts; doubles after each.
+const RETRY_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Source that reads the subscription from standard input.
+pub const STDIN: &str = "-";
+
+/// Refreshes that changed the proxy set, kept for the web API.
+const CHANGE_HISTORY: usize = 20;
+
+/// Names listed per kind of change in [`SubscriptionChange::text`].
+const MAX_LISTED: usize = 20;
+
+/// Subscriptions and links come padded or not, and with sloppy encoders.
+const LENIENT_BASE64: GeneralPurposeConfig = GeneralPurposeConfig::new()
+    .with_decode_padding_mode(DecodePaddingMode::Indifferent)
+    .with_decode_allow_trailing_bits(true);
+
+/// Standard alphabet first, then URL-safe (`-` and `_`).
+const BASE64_ENGINES: [GeneralPurpose; 2] = [
+    GeneralPurpose::new(&alphabet::STANDARD, LENIENT_BASE64),
+    GeneralPurpose::new(&alphabet::URL_SAFE, LENIENT_BASE64),
+];
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/// Between the files of a `dir://` source, which the parser splits on.
+pub const DOCUMENT_SEPARATOR: &str = "\n---\n";
+
+/// A downloaded subscription.
+#[derive(Default)]
+pub struct Subscription {
+    pub content: String,
+    /// From the `subscription-userinfo` header, if the provider sent one.
+    pub info: Option<SubscriptionInfo>,
+    /// To send back on the next download of the same URL.
+    pub validators: Validators,
+    /// The provider answered a conditional download with 304. `content`
+    /// is empty; the previous copy is still current.
+    pub not_modified: bool,
+}
+
+/// `ETag` and `Last-Modified` of a download, for a conditional refresh.
+#[derive(Debug, Clone, Default)]
+pub struct Validators {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+impl Validators {
+    fn from_headers(headers: &HeaderMap) -> Self {
+        let header = |name: HeaderName| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+        Self {
+            etag: header(ETAG),
+            last_modified: header(LAST_MODIFIED),
+        }
+    }
+}
+
+/// Recent [`SubscriptionChange`]s, newest first.
+pub type SharedSubscriptionChanges = Arc<RwLock<VecDeque<SubscriptionChange>>>;
+
+/// How a refreshed proxy list differs from the previous one. Proxies are
+/// matched by [`crate::parser::proxy_id`], so a renamed node isn't counted
+/// as one removed and one added.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SubscriptionChange {
+    pub at: DateTime<Utc>,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub renamed: Vec<Rename>,
+    /// Same name, different config, e.g. a new password.
+    pub changed: Vec<String>,
+    pub unchanged: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Rename {
+    pub from: String,
+    pub to: String,
+}
+
+impl SubscriptionChange {
+    /// Note a proxy that needs a new handler: new, or loaded before as
+    /// `old_name`.
+    pub(crate) fn record(&mut self, old_name: Option<&str>, name: &str) {
+        match old_name {
+            None => self.added.push(name.to_string()),
+            Some(from) if from != name => self.renamed.push(Rename {
+                from: from.to_string(),
+                to: name.to_string(),
+            }),
+            Some(_) => self.changed.push(name.to_string()),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.renamed.is_empty()
+            && self.changed.is_empty()
+    }
+
+    /// One line per kind of change, naming the proxies.
+    pub fn text(&self) -> String {
+        let renamed: Vec<String> = self
+            .renamed
+            .iter()
+            .map(|rename| format!("{} → {}", rename.from, rename.to))
+            .collect();
+        let mut text = String::new();
+        for (title, names) in [
+            ("Added", &self.added),
+            ("Removed", &self.removed),
+            ("Renamed", &renamed),
+            ("Changed", &self.changed),
+        ] {
+            if !names.is_empty() {
+                let _ = writeln!(text, "{} ({}): {}", title, names.len(), name_list(names));
+            }
+        }
+        text
+    }
+}
+
+/// Up to [`MAX_LISTED`] names, then how many more.
+fn name_list(names: &[String]) -> String {
+    let mut list = names[..names.len().min(MAX_LISTED)].join(", ");
+    if names.len() > MAX_LISTED {
+        let _ = write!(list, " and {} more", names.len() - MAX_LISTED);
+    }
+    list
+}
+
+/// Add `change` to `changes`, dropping the oldest beyond the history size.
+pub fn push_change(changes: &SharedSubscriptionChanges, change: SubscriptionChange) {
+    let mut changes = changes.write().unwrap();
+    changes.push_front(change);
+    changes.truncate(CHANGE_HISTORY);
+}
+
+/// Traffic and expiry of the plan as reported by the provider in the
+/// `subscription-userinfo` response header. Traffic is in bytes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct SubscriptionInfo {
+    pub upload: u64,
+    pub download: u64,
+    /// 0 when the plan has no traffic cap.
+    pub total: u64,
+    pub expire: Option<DateTime<Utc>>,
+}
+
+impl SubscriptionInfo {
+    /// Parse `upload=1; download=2; total=3; expire=1700000000`. Unknown
+    /// keys are skipped; `None` if none of the known ones is there.
+    pub fn parse(header: &str) -> Option<Self> {
+        let mut info = Self::default();
+        let mut found = false;
+        for pair in header.split(';') {
+            let Some((key, value)) = pair.split_once('=') else {
+                continue;
+            };
+            // Some providers send floats such as "1.073741824E11".
+            let Ok(value) = value.trim().parse::<f64>() else {
+                continue;
+            };
+            let value = value as u64;
+            match key.trim() {
+                "upload" => info.upload = value,
+                "download" => info.download = value,
+                "total" => info.total = value,
+                // 0 means the plan never expires.
+                "expire" => {
+                    info.expire = DateTime::from_timestamp(value as i64, 0).filter(|_| value > 0)
+                }
+                _ => continue,
+            }
+            found = true;
+        }
+        found.then_some(info)
+    }
+
+    pub fn used(&self) -> u64 {
+        self.upload + self.download
+    }
+
+    /// Share of the traffic cap used, or `None` without a cap.
+    pub fn used_pct(&self) -> Option<f64> {
+        (self.total > 0).then(|| self.used() as f64 * 100.0 / self.total as f64)
+    }
+
+    /// Whole days until the plan expires, negative once it has.
+    pub fn days_left(&self, now: DateTime<Utc>) -> Option<i64> {
+        self.expire.map(|expire| (expire - now).num_days())
+    }
+}
 
 /// Fetch subscription content from URL or file
 pub async fn fetch_subscription(url: &str) -> Result<String> {
-    // Handle file:// URLs for local testing
-    if url.starts_with("file://") {
-        let file_path = url.strip_prefix("file://").unwrap();
-        let content = tokio::fs::read_to_string(file_path).await?;
-        return Ok(content);
+    let client = reqwest::Client::builder()
+        .timeout(DEFAULT_TIMEOUT)
+        .build()?;
+    Ok(fetch(url, &client, &Validators::default()).await?.content)
+}
+
+/// HTTP client for the subscription with the `[main]` timeout, User-Agent,
+/// extra headers and proxy. `proxy` overrides `subscription_proxy`.
+fn client(main: &MainConfig, proxy: Option<&str>) -> Result<reqwest::Client> {
+    let mut headers = HeaderMap::new();
+    for (name, value) in &main.subscription_headers {
+        headers.insert(HeaderName::try_from(name)?, HeaderValue::try_from(value)?);
+    }
+    let mut builder = reqwest::Client::builder()
+        .timeout(Duration::from_secs(main.subscription_timeout))
+        .default_headers(headers);
+    if let Some(user_agent) = &main.subscription_user_agent {
+        builder = builder.user_agent(user_agent);
     }
+    if let Some(proxy) = proxy.or(main.subscription_proxy.as_deref()) {
+        builder = builder.proxy(reqwest::Proxy::all(proxy)?);
+    }
+    Ok(builder.build()?)
+}
+
+/// Fetch the subscription with the `[main]` timeout and retries. A
+/// successful download is also saved to `subscription_cache`, if set.
+/// With `validators` from a previous download the request is conditional
+/// and may come back [`Subscription::not_modified`]. `proxy` overrides
+/// `subscription_proxy`.
+pub async fn fetch_subscription_retrying(
+    main: &MainConfig,
+    validators: &Validators,
+    proxy: Option<&str>,
+) -> Result<Subscription> {
+    let client = client(main, proxy)?;
+    let mut backoff = RETRY_BACKOFF;
+    let mut attempt = 0;
+    let subscription = loop {
+        match fetch(&main.subscription_url, &client, validators).await {
+            Ok(subscription) => break subscription,
+            Err(e) if attempt < main.subscription_retries => {
+                attempt += 1;
+                warn!(
+                    "Subscription fetch failed ({}), retry {}/{} in {}s",
+                    e,
+                    attempt,
+                    main.subscription_retries,
+                    backoff.as_secs()
+                );
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+            Err(e) => return Err(e),
+        }
+    };
 
-    let client = reqwest::Client::new();
-    let response = client.get(url).send().await?;
+    if let Some(path) = &main.subscription_cache
+        && !subscription.not_modified
+        && let Err(e) = save_cache(path, &subscription.content).await
+    {
+        warn!("Failed to write subscription cache {}: {}", path, e);
+    }
+    Ok(subscription)
+}
+
+/// [`fetch_subscription_retrying`], falling back to the cached copy when
+/// every attempt fails. The cached copy carries no traffic info.
+pub async fn load_subscription(main: &MainConfig) -> Result<Subscription> {
+    let error = match fetch_subscription_retrying(main, &Validators::default(), None).await {
+        Ok(subscription) => return Ok(subscription),
+        Err(e) => e,
+    };
+    let Some(path) = &main.subscription_cache else {
+        return Err(error);
+    };
+    match tokio::fs::read_to_string(path).await {
+        Ok(content) => {
+            warn!(
+                "Subscription fetch failed ({}), using the cached copy in {}",
+                error, path
+            );
+            Ok(Subscription {
+                content,
+                ..Default::default()
+            })
+        }
+        Err(e) => Err(anyhow::anyhow!(
+            "{} (no usable cache at {}: {})",
+            error,
+            path,
+            e
+        )),
+    }
+}
+
+async fn fetch(
+    url: &str,
+    client: &reqwest::Client,
+    validators: &Validators,
+) -> Result<Subscription> {
+    if let Some(content) = read_local(url).await? {
+        return Ok(Subscription {
+            content,
+            ..Default::default()
+        });
+    }
+
+    let mut request = client.get(url);
+    if let Some(etag) = &validators.etag {
+        request = request.header(IF_NONE_MATCH, etag);
+    }
+    if let Some(last_modified) = &validators.last_modified {
+        request = request.header(IF_MODIFIED_SINCE, last_modified);
+    }
+    let response = request.send().await?;
+
+    // A 304 still carries fresh traffic figures from most providers.
+    let info = response
+        .headers()
+        .get("subscription-userinfo")
+        .and_then(|value| value.to_str().ok())
+        .and_then(SubscriptionInfo::parse);
+    if response.status() == StatusCode::NOT_MODIFIED {
+        return Ok(Subscription {
+            info,
+            validators: validators.clone(),
+            not_modified: true,
+            ..Default::default()
+        });
+    }
 
     if !response.status().is_success() {
         return Err(anyhow::anyhow!(
@@ -20,12 +364,106 @@ pub async fn fetch_subscription(url: &str) -> Result<String> {
         ));
     }
 
-    let content = response.text().await?;
-    Ok(content)
+    let validators = Validators::from_headers(response.headers());
+    let content = decode(&response.bytes().await?)?;
+    Ok(Subscription {
+        content,
+        info,
+        validators,
+        not_modified: false,
+    })
+}
+
+/// Content of a local source: [`STDIN`], `file://` for one file, a
+/// `data:` URI, or `dir://` for every file matching a glob such as
+/// `/etc/nodes/*.yaml`, or in a directory. `None` for remote URLs.
+async fn read_local(url: &str) -> Result<Option<String>> {
+    if url == STDIN {
+        let mut body = Vec::new();
+        tokio::io::stdin().read_to_end(&mut body).await?;
+        return decode(&body).map(Some);
+    }
+    if let Some(path) = url.strip_prefix("file://") {
+        return decode(&tokio::fs::read(path).await?).map(Some);
+    }
+    if let Some(data) = url.strip_prefix("data:") {
+        return decode(&data_uri(data)?).map(Some);
+    }
+    match url.strip_prefix("dir://") {
+        Some(pattern) => read_files(pattern).await.map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Every file matching `pattern`, in path order, one document each.
+async fn read_files(pattern: &str) -> Result<String> {
+    let pattern = if std::path::Path::new(pattern).is_dir() {
+        format!("{}/*", pattern.trim_end_matches('/'))
+    } else {
+        pattern.to_string()
+    };
+    let mut documents = Vec::new();
+    for path in glob::glob(&pattern)? {
+        let path = path?;
+        if path.is_file() {
+            documents.push(decode(&tokio::fs::read(&path).await?)?.trim().to_string());
+        }
+    }
+    if documents.is_empty() {
+        return Err(anyhow::anyhow!("No subscription files match {}", pattern));
+    }
+    Ok(documents.join(DOCUMENT_SEPARATOR))
+}
+
+/// Text of a subscription body, decompressed first if it starts with a
+/// gzip or zstd magic number: some providers compress without sending
+/// `Content-Encoding`.
+fn decode(body: &[u8]) -> Result<String> {
+    let body = if body.starts_with(GZIP_MAGIC) {
+        let mut decompressed = Vec::new();
+        GzDecoder::new(body).read_to_end(&mut decompressed)?;
+        decompressed
+    } else if body.starts_with(ZSTD_MAGIC) {
+        zstd::decode_all(body)?
+    } else {
+        body.to_vec()
+    };
+    Ok(String::from_utf8_lossy(&body).into_owned())
+}
+
+/// Payload of a `data:` URI after the scheme: base64 if the media type
+/// ends in `;base64`, percent-encoded otherwise.
+fn data_uri(data: &str) -> Result<Vec<u8>> {
+    let (media_type, payload) = data
+        .split_once(',')
+        .ok_or_else(|| anyhow::anyhow!("data: URI has no ','"))?;
+    if media_type.ends_with(";base64") {
+        return Ok(BASE64_STANDARD.decode(payload)?);
+    }
+    Ok(urlencoding::decode_binary(payload.as_bytes()).into_owned())
+}
+
+/// Write through a temporary file so a crash never leaves half a cache.
+async fn save_cache(path: &str, content: &str) -> Result<()> {
+    let tmp = format!("{}.tmp", path);
+    tokio::fs::write(&tmp, content).await?;
+    tokio::fs::rename(&tmp, path).await?;
+    Ok(())
+}
+
+/// `s` decoded as base64 in the standard or URL-safe alphabet, padded or
+/// not, ignoring line breaks. `None` if it isn't base64. Short plain text
+/// can decode too, so callers should check the result makes sense.
+pub fn decode_base64(s: &str) -> Option<Vec<u8>> {
+    let compact: String = s.split_ascii_whitespace().collect();
+    if compact.is_empty() {
+        return None;
+    }
+    BASE64_ENGINES
+        .iter()
+        .find_map(|engine| engine.decode(&compact).ok())
 }
 
 pub fn is_base64(s: &str) -> bool {
-    s.chars()
-        .all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '/' || c == '=')
-        && s.len() % 4 == 0
+    decode_base64(s).is_some()
 }
@@ -0,0 +1,222 @@
+use crate::config::AlertPriorities;
+use crate::probe_result::ProbeResult;
+use chrono::Utc;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::time::{Duration, Instant};
+
+/// Names listed in plain-text alerts before collapsing the rest.
+const MAX_LISTED: usize = 20;
+
+/// Proxies listed under "Fastest" in chat summaries.
+pub const FASTEST: usize = 5;
+
+/// How bad a round looks, for picking colours and who to notify.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Nothing died since the previous round.
+    Info,
+    /// Some proxies died since the previous round.
+    Warning,
+    /// No proxy is alive.
+    Critical,
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Critical => "critical",
+        }
+    }
+
+    pub fn priority(&self, priorities: &AlertPriorities) -> i32 {
+        match self {
+            Severity::Info => priorities.info,
+            Severity::Warning => priorities.warning,
+            Severity::Critical => priorities.critical,
+        }
+    }
+}
+
+/// A round condensed for chat notifications.
+pub struct RoundSummary {
+    pub total: usize,
+    pub alive: usize,
+    /// Alive proxies with a measured delay, fastest first.
+    pub fastest: Vec<(String, u64)>,
+    /// Alive last round, dead now.
+    pub newly_dead: Vec<String>,
+    /// Dead last round, alive now.
+    pub recovered: Vec<String>,
+    /// No previous round to compare against.
+    pub first_round: bool,
+}
+
+impl RoundSummary {
+    pub fn dead(&self) -> usize {
+        self.total - self.alive
+    }
+
+    pub fn severity(&self) -> Severity {
+        if self.total > 0 && self.alive == 0 {
+            Severity::Critical
+        } else if !self.newly_dead.is_empty() {
+            Severity::Warning
+        } else {
+            Severity::Info
+        }
+    }
+
+    /// Whether this round is worth alerting on. The first round has nothing
+    /// to compare against, so only a total outage counts there.
+    pub fn is_alert(&self) -> bool {
+        (!self.first_round && self.changed()) || self.severity() == Severity::Critical
+    }
+
+    /// Plain-text alert body for services without rich formatting.
+    pub fn alert_text(&self) -> String {
+        let mut text = String::new();
+        if self.severity() == Severity::Critical {
+            text.push_str("No proxy is alive.\n");
+        }
+        for (title, names) in [
+            ("Newly dead", &self.newly_dead),
+            ("Recovered", &self.recovered),
+        ] {
+            if names.is_empty() {
+                continue;
+            }
+            let listed: Vec<&str> = names.iter().take(MAX_LISTED).map(String::as_str).collect();
+            let _ = write!(text, "{}: {}", title, listed.join(", "));
+            if names.len() > MAX_LISTED {
+                let _ = write!(text, " and {} more", names.len() - MAX_LISTED);
+            }
+            text.push('\n');
+        }
+        let _ = write!(text, "Alive: {}/{}", self.alive, self.total);
+        text
+    }
+
+    /// Plain-text round summary for chat bots: counts, fastest proxies and
+    /// any state changes.
+    pub fn chat_text(&self, node_name: &str) -> String {
+        let mut text = format!("clashprobe {} [{}]\n", node_name, self.severity().as_str());
+        if !self.fastest.is_empty() {
+            text.push_str("Fastest:\n");
+            for (i, (name, delay)) in self.fastest.iter().enumerate() {
+                let _ = writeln!(text, "{}. {} — {} ms", i + 1, name, delay);
+            }
+        }
+        text.push_str(&self.alert_text());
+        text
+    }
+
+    /// Plain-text fleet status for a message that is edited in place.
+    pub fn status_text(&self, node_name: &str) -> String {
+        let mut text = format!(
+            "clashprobe {} — {}/{} alive",
+            node_name, self.alive, self.total
+        );
+        if !self.fastest.is_empty() {
+            text.push_str("\nFastest:");
+            for (i, (name, delay)) in self.fastest.iter().enumerate() {
+                let _ = write!(text, "\n{}. {} — {} ms", i + 1, name, delay);
+            }
+        }
+        let _ = write!(
+            text,
+            "\nUpdated {}",
+            Utc::now().format("%Y-%m-%d %H:%M:%S UTC")
+        );
+        text
+    }
+
+    /// Whether any proxy changed state since the previous round. The first
+    /// round always counts as a change.
+    pub fn changed(&self) -> bool {
+        self.first_round || !self.newly_dead.is_empty() || !self.recovered.is_empty()
+    }
+}
+
+/// Remembers whether each proxy was alive in the previous round.
+#[derive(Default)]
+pub struct StateTracker {
+    previous: Option<HashMap<String, bool>>,
+}
+
+impl StateTracker {
+    /// Take `results` as the previous round. Reporters call this once the
+    /// round's notification is out, so a failed send is summarised again.
+    pub fn record(&mut self, results: &[ProbeResult]) {
+        self.previous = Some(results.iter().map(|r| (r.name.clone(), r.alive)).collect());
+    }
+
+    /// Summarise `results` against the previous round, keeping the `top`
+    /// fastest proxies.
+    pub fn summarize(&self, results: &[ProbeResult], top: usize) -> RoundSummary {
+        let mut fastest: Vec<(String, u64)> = results
+            .iter()
+            .filter(|r| r.alive)
+            .filter_map(|r| r.delay_ms.map(|d| (r.name.clone(), d)))
+            .collect();
+        fastest.sort_by_key(|(_, delay)| *delay);
+        fastest.truncate(top);
+
+        let (newly_dead, recovered) = match &self.previous {
+            Some(previous) => {
+                let newly_dead = results
+                    .iter()
+                    .filter(|r| !r.alive && previous.get(&r.name) == Some(&true))
+                    .map(|r| r.name.clone())
+                    .collect();
+                let recovered = results
+                    .iter()
+                    .filter(|r| r.alive && previous.get(&r.name) == Some(&false))
+                    .map(|r| r.name.clone())
+                    .collect();
+                (newly_dead, recovered)
+            }
+            None => (Vec::new(), Vec::new()),
+        };
+
+        RoundSummary {
+            total: results.len(),
+            alive: results.iter().filter(|r| r.alive).count(),
+            fastest,
+            newly_dead,
+            recovered,
+            first_round: self.previous.is_none(),
+        }
+    }
+}
+
+/// Rate limit for alert notifications: drops alerts sent within
+/// `min_interval` of the previous one, except critical ones.
+pub struct AlertThrottle {
+    min_interval: Duration,
+    last_sent: Option<Instant>,
+}
+
+impl AlertThrottle {
+    pub fn new(min_interval: Duration) -> Self {
+        Self {
+            min_interval,
+            last_sent: None,
+        }
+    }
+
+    /// Whether an alert of `severity` may go out now.
+    pub fn allows(&self, severity: Severity) -> bool {
+        let throttled = self
+            .last_sent
+            .is_some_and(|last| last.elapsed() < self.min_interval);
+        !throttled || severity == Severity::Critical
+    }
+
+    /// Count an alert as sent, once it actually went out.
+    pub fn sent(&mut self) {
+        self.last_sent = Some(Instant::now());
+    }
+}
@@ -0,0 +1,393 @@
+use crate::config::TeloxideConfig;
+use crate::probe_engine::{EngineCommand, EngineCommandSender};
+use crate::probe_result::ProbeResult;
+use crate::reporter::ProbeReporter;
+use crate::shutdown::{self, ShutdownSignal};
+use crate::summary::{AlertThrottle, RoundSummary, StateTracker};
+use anyhow::Result;
+use async_trait::async_trait;
+use frankenstein::AsyncTelegramApi;
+use frankenstein::client_reqwest::Bot;
+use frankenstein::methods::{
+    EditMessageTextParams, GetUpdatesParams, PinChatMessageParams, SendMessageParams,
+};
+use frankenstein::updates::{AllowedUpdate, UpdateContent};
+use std::fmt::Write as _;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex as AsyncMutex, oneshot, watch};
+use tracing::{debug, info, warn};
+
+/// Seconds a `getUpdates` long poll waits for new messages.
+const POLL_TIMEOUT: u32 = 25;
+
+/// Pause after a failed poll before trying again.
+const POLL_RETRY: Duration = Duration::from_secs(5);
+
+/// Telegram rejects messages longer than this many characters.
+const MAX_MESSAGE_CHARS: usize = 4096;
+
+const HELP: &str = "/status - alive count and fastest proxy\n\
+                    /top [n] - fastest proxies\n\
+                    /dead - proxies that are down\n\
+                    /probe - probe every proxy now";
+
+/// Answers commands from allow-listed chats with the latest round, and can
+/// ask the engine for an on-demand probe. With `status_chat_id` set it also
+/// keeps a pinned status message there up to date and posts alerts when
+/// proxies die or recover.
+pub struct TelegramBot {
+    sender: watch::Sender<Vec<ProbeResult>>,
+    bot: Bot,
+    config: TeloxideConfig,
+    state: Mutex<NotifyState>,
+    /// Message ID of the pinned status message, once posted. Held across
+    /// the edit so two rounds never race to post it.
+    status_message: AsyncMutex<Option<i32>>,
+}
+
+struct NotifyState {
+    tracker: StateTracker,
+    throttle: AlertThrottle,
+    last_edit: Option<Instant>,
+    /// Whether the previous round was already under `min_alive_pct`, so
+    /// the drop is only alerted once.
+    below_min_alive: bool,
+}
+
+impl TelegramBot {
+    /// Start long-polling for commands until shutdown.
+    pub fn start(
+        config: &TeloxideConfig,
+        commands: EngineCommandSender,
+        shutdown: ShutdownSignal,
+    ) -> Self {
+        let (sender, results) = watch::channel(Vec::new());
+        let handler = CommandHandler {
+            bot: Bot::new(&config.token),
+            config: config.clone(),
+            commands,
+            results,
+        };
+        tokio::spawn(handler.run(shutdown));
+        Self {
+            sender,
+            bot: Bot::new(&config.token),
+            config: config.clone(),
+            state: Mutex::new(NotifyState {
+                tracker: StateTracker::default(),
+                throttle: AlertThrottle::new(Duration::from_secs(config.min_interval)),
+                last_edit: None,
+                below_min_alive: false,
+            }),
+            status_message: AsyncMutex::new(None),
+        }
+    }
+
+    /// Whether fewer than `min_alive_pct` of the proxies are alive.
+    fn below_min_alive(&self, alive: usize, total: usize) -> bool {
+        let alive_pct = if total == 0 {
+            100.0
+        } else {
+            alive as f64 * 100.0 / total as f64
+        };
+        self.config.min_alive_pct.is_some_and(|min| alive_pct < min)
+    }
+
+    /// Edit the status message, or post and pin a new one if there is none
+    /// yet or it was deleted.
+    async fn update_status(&self, chat_id: i64, text: &str) -> Result<()> {
+        let mut status_message = self.status_message.lock().await;
+        if let Some(message_id) = *status_message {
+            let params = EditMessageTextParams::builder()
+                .chat_id(chat_id)
+                .message_id(message_id)
+                .text(truncate(text))
+                .build();
+            match self.bot.edit_message_text(&params).await {
+                Ok(_) => return Ok(()),
+                Err(e) if e.to_string().contains("message to edit not found") => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        let params = SendMessageParams::builder()
+            .chat_id(chat_id)
+            .text(truncate(text))
+            .disable_notification(true)
+            .build();
+        let message_id = self.bot.send_message(&params).await?.result.message_id;
+        let params = PinChatMessageParams::builder()
+            .chat_id(chat_id)
+            .message_id(message_id)
+            .disable_notification(true)
+            .build();
+        self.bot.pin_chat_message(&params).await?;
+        *status_message = Some(message_id);
+        Ok(())
+    }
+
+    /// Send an alert for `summary`, noting when the fleet just fell under
+    /// `min_alive_pct`.
+    async fn send_alert(&self, chat_id: i64, summary: &RoundSummary, dropped: bool) -> Result<()> {
+        let mut text = format!(
+            "clashprobe {} [{}]\n",
+            self.config.node_name,
+            summary.severity().as_str()
+        );
+        if dropped {
+            let _ = writeln!(
+                text,
+                "Fewer than {}% of proxies are alive.",
+                self.config.min_alive_pct.unwrap_or_default()
+            );
+        }
+        text.push_str(&summary.alert_text());
+        let params = SendMessageParams::builder()
+            .chat_id(chat_id)
+            .text(truncate(&text))
+            .build();
+        self.bot.send_message(&params).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ProbeReporter for TelegramBot {
+    async fn report(&self, results: &[ProbeResult]) -> Result<()> {
+        self.sender.send_replace(results.to_vec());
+        let Some(chat_id) = self.config.status_chat_id else {
+            return Ok(());
+        };
+
+        let (summary, below, edit, alert) = {
+            let state = self.state.lock().unwrap();
+            let summary = state.tracker.summarize(results, self.config.top);
+
+            let below = self.below_min_alive(summary.alive, summary.total);
+            let dropped = below && !state.below_min_alive;
+            let edit = state
+                .last_edit
+                .is_none_or(|at| at.elapsed() >= Duration::from_secs(self.config.edit_interval));
+            let alert =
+                (summary.is_alert() || dropped) && state.throttle.allows(summary.severity());
+            (summary, below, edit, alert.then_some(dropped))
+        };
+
+        // State only moves on once its message is out, so a failed round is
+        // summarised and sent again on retry.
+        if edit {
+            self.update_status(chat_id, &summary.status_text(&self.config.node_name))
+                .await?;
+            self.state.lock().unwrap().last_edit = Some(Instant::now());
+        }
+        if let Some(dropped) = alert {
+            self.send_alert(chat_id, &summary, dropped).await?;
+            self.state.lock().unwrap().throttle.sent();
+        }
+        let mut state = self.state.lock().unwrap();
+        state.tracker.record(results);
+        state.below_min_alive = below;
+        Ok(())
+    }
+
+    async fn restore(&self, results: &[ProbeResult]) {
+        self.sender.send_replace(results.to_vec());
+        let alive = results.iter().filter(|r| r.alive).count();
+        let below = self.below_min_alive(alive, results.len());
+        let mut state = self.state.lock().unwrap();
+        state.tracker.record(results);
+        state.below_min_alive = below;
+    }
+
+    async fn report_notice(&self, text: &str) -> Result<()> {
+        let Some(chat_id) = self.config.status_chat_id else {
+            return Ok(());
+        };
+        let text = format!("clashprobe {}\n{}", self.config.node_name, text);
+        let params = SendMessageParams::builder()
+            .chat_id(chat_id)
+            .text(truncate(&text))
+            .build();
+        self.bot.send_message(&params).await?;
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "Teloxide"
+    }
+}
+
+struct CommandHandler {
+    bot: Bot,
+    config: TeloxideConfig,
+    commands: EngineCommandSender,
+    results: watch::Receiver<Vec<ProbeResult>>,
+}
+
+impl CommandHandler {
+    async fn run(self, mut shutdown: ShutdownSignal) {
+        info!("Telegram bot listening for commands");
+        let mut offset: i64 = 0;
+        loop {
+            let params = GetUpdatesParams::builder()
+                .offset(offset)
+                .timeout(POLL_TIMEOUT)
+                .allowed_updates(vec![AllowedUpdate::Message])
+                .build();
+            let updates = tokio::select! {
+                updates = self.bot.get_updates(&params) => updates,
+                _ = shutdown::requested(&mut shutdown) => break,
+            };
+
+            let updates = match updates {
+                Ok(response) => response.result,
+                Err(e) => {
+                    warn!("Telegram getUpdates failed: {}", e);
+                    tokio::select! {
+                        _ = tokio::time::sleep(POLL_RETRY) => continue,
+                        _ = shutdown::requested(&mut shutdown) => break,
+                    }
+                }
+            };
+
+            for update in updates {
+                // Acknowledges this update on the next poll.
+                offset = i64::from(update.update_id) + 1;
+                if let UpdateContent::Message(message) = update.content
+                    && let Some(text) = &message.text
+                {
+                    self.handle(message.chat.id, text).await;
+                }
+            }
+        }
+        info!("Telegram bot stopped");
+    }
+
+    async fn handle(&self, chat_id: i64, text: &str) {
+        if !self.config.allowed_chat_ids.contains(&chat_id) {
+            debug!("Ignoring Telegram message from chat {}", chat_id);
+            return;
+        }
+
+        let mut words = text.split_whitespace();
+        // In groups commands may be addressed as `/top@some_bot`.
+        let command = words
+            .next()
+            .and_then(|word| word.split('@').next())
+            .unwrap_or_default();
+        // Cloned so the watch borrow isn't held across the awaits below.
+        let results = self.results.borrow().clone();
+        let reply = match command {
+            "/status" => self.status(&results),
+            "/top" => {
+                let count = words
+                    .next()
+                    .and_then(|n| n.parse().ok())
+                    .unwrap_or(self.config.top);
+                top(&results, count)
+            }
+            "/dead" => dead(&results),
+            "/probe" => {
+                self.send(chat_id, "Probing all proxies…").await;
+                self.probe().await
+            }
+            "/start" | "/help" => HELP.to_string(),
+            _ => return,
+        };
+        self.send(chat_id, &reply).await;
+    }
+
+    async fn send(&self, chat_id: i64, text: &str) {
+        let params = SendMessageParams::builder()
+            .chat_id(chat_id)
+            .text(truncate(text))
+            .build();
+        if let Err(e) = self.bot.send_message(&params).await {
+            warn!("Failed to send Telegram message: {}", e);
+        }
+    }
+
+    fn status(&self, results: &[ProbeResult]) -> String {
+        if results.is_empty() {
+            return "No probe round has finished yet.".to_string();
+        }
+        let alive = results.iter().filter(|r| r.alive).count();
+        let mut text = format!(
+            "clashprobe {}: {}/{} alive",
+            self.config.node_name,
+            alive,
+            results.len()
+        );
+        if let Some((name, delay)) = fastest(results).first() {
+            let _ = write!(text, "\nFastest: {} — {} ms", name, delay);
+        }
+        text
+    }
+
+    async fn probe(&self) -> String {
+        let (reply, receiver) = oneshot::channel();
+        let sent = self
+            .commands
+            .send(EngineCommand::ProbeNow { proxy: None, reply })
+            .await;
+        match (sent, receiver.await) {
+            (Ok(()), Ok(results)) => {
+                let mut text = self.status(&results);
+                text.push('\n');
+                text.push_str(&dead(&results));
+                text
+            }
+            _ => "The probe engine is not running.".to_string(),
+        }
+    }
+}
+
+/// Alive proxies with a measured delay, fastest first.
+fn fastest(results: &[ProbeResult]) -> Vec<(&str, u64)> {
+    let mut fastest: Vec<(&str, u64)> = results
+        .iter()
+        .filter(|r| r.alive)
+        .filter_map(|r| r.delay_ms.map(|d| (r.name.as_str(), d)))
+        .collect();
+    fastest.sort_by_key(|(_, delay)| *delay);
+    fastest
+}
+
+fn top(results: &[ProbeResult], count: usize) -> String {
+    let fastest = fastest(results);
+    if fastest.is_empty() {
+        return "No proxy is alive.".to_string();
+    }
+    let mut text = String::new();
+    for (i, (name, delay)) in fastest.iter().take(count).enumerate() {
+        let _ = writeln!(text, "{}. {} — {} ms", i + 1, name, delay);
+    }
+    text
+}
+
+fn dead(results: &[ProbeResult]) -> String {
+    let dead: Vec<&ProbeResult> = results.iter().filter(|r| !r.alive).collect();
+    if dead.is_empty() {
+        return "Every proxy is alive.".to_string();
+    }
+    let mut text = format!("{} dead:\n", dead.len());
+    for result in dead {
+        let reason = result
+            .error_kind
+            .map(|kind| kind.as_str())
+            .unwrap_or("down");
+        let _ = writeln!(text, "{} ({})", result.name, reason);
+    }
+    text
+}
+
+/// Cut `text` to what fits in one message.
+fn truncate(text: &str) -> String {
+    if text.chars().count() <= MAX_MESSAGE_CHARS {
+        return text.to_string();
+    }
+    let mut cut: String = text.chars().take(MAX_MESSAGE_CHARS - 1).collect();
+    cut.push('…');
+    cut
+}
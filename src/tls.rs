@@ -0,0 +1,70 @@
+use anyhow::{Context, Result};
+use std::{fs::File, io::BufReader, net::SocketAddr, sync::Arc, time::Duration};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::{TlsAcceptor, rustls::ServerConfig, server::TlsStream};
+use tracing::debug;
+
+/// Slow or broken clients must not hold up the accept loop for long.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub fn load_acceptor(cert_path: &str, key_path: &str) -> Result<TlsAcceptor> {
+    let certs = rustls_pemfile::certs(&mut BufReader::new(
+        File::open(cert_path).with_context(|| format!("Failed to open {}", cert_path))?,
+    ))
+    .collect::<Result<Vec<_>, _>>()
+    .with_context(|| format!("Failed to parse certificates in {}", cert_path))?;
+
+    let key = rustls_pemfile::private_key(&mut BufReader::new(
+        File::open(key_path).with_context(|| format!("Failed to open {}", key_path))?,
+    ))
+    .with_context(|| format!("Failed to parse private key in {}", key_path))?
+    .ok_or_else(|| anyhow::anyhow!("No private key found in {}", key_path))?;
+
+    let config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .context("Invalid TLS certificate/key pair")?;
+
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+/// TCP listener that completes the TLS handshake before handing the
+/// connection to axum.
+pub struct TlsListener {
+    tcp: TcpListener,
+    acceptor: TlsAcceptor,
+}
+
+impl TlsListener {
+    pub fn new(tcp: TcpListener, acceptor: TlsAcceptor) -> Self {
+        Self { tcp, acceptor }
+    }
+}
+
+impl axum::serve::Listener for TlsListener {
+    type Io = TlsStream<TcpStream>;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        loop {
+            let (stream, addr) = match self.tcp.accept().await {
+                Ok(conn) => conn,
+                Err(e) => {
+                    debug!("TCP accept failed: {}", e);
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    continue;
+                }
+            };
+
+            match tokio::time::timeout(HANDSHAKE_TIMEOUT, self.acceptor.accept(stream)).await {
+                Ok(Ok(tls)) => return (tls, addr),
+                Ok(Err(e)) => debug!("TLS handshake with {} failed: {}", addr, e),
+                Err(_) => debug!("TLS handshake with {} timed out", addr),
+            }
+        }
+    }
+
+    fn local_addr(&self) -> std::io::Result<Self::Addr> {
+        self.tcp.local_addr()
+    }
+}
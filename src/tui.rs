@@ -0,0 +1,324 @@
+use crate::probe_result::ProbeResult;
+use crate::reporter::ProbeReporter;
+use crate::shutdown::{ShutdownSignal, ShutdownTrigger};
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Local};
+use ratatui::{
+    DefaultTerminal, Frame,
+    crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Layout},
+    style::{Color, Modifier, Style},
+    text::Line,
+    widgets::{Block, Borders, Cell, Paragraph, Row, Table, TableState},
+};
+use std::cmp::Ordering;
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+/// Rounds of latency kept per proxy for the sparkline column.
+const SPARKLINE_POINTS: usize = 20;
+const SPARK_CHARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Feeds probe rounds to the terminal UI, which runs on a blocking thread.
+pub struct TuiReporter {
+    sender: watch::Sender<Vec<ProbeResult>>,
+}
+
+impl TuiReporter {
+    /// Take over the terminal and start drawing. Quitting the UI requests
+    /// shutdown through `trigger`; the UI also closes on external shutdown.
+    pub fn start(trigger: ShutdownTrigger, shutdown: ShutdownSignal) -> (Self, JoinHandle<()>) {
+        let (sender, receiver) = watch::channel(Vec::new());
+        let handle = tokio::task::spawn_blocking(move || {
+            let terminal = ratatui::init();
+            let result = App::default().run(terminal, receiver, &shutdown);
+            ratatui::restore();
+            if let Err(e) = result {
+                eprintln!("TUI failed: {}", e);
+            }
+            let _ = trigger.send(true);
+        });
+        (Self { sender }, handle)
+    }
+}
+
+#[async_trait]
+impl ProbeReporter for TuiReporter {
+    async fn report(&self, results: &[ProbeResult]) -> Result<()> {
+        self.sender.send_replace(results.to_vec());
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "tui"
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+enum SortKey {
+    #[default]
+    Delay,
+    Name,
+    Protocol,
+    Loss,
+}
+
+impl SortKey {
+    fn next(self) -> Self {
+        match self {
+            SortKey::Delay => SortKey::Name,
+            SortKey::Name => SortKey::Protocol,
+            SortKey::Protocol => SortKey::Loss,
+            SortKey::Loss => SortKey::Delay,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            SortKey::Delay => "delay",
+            SortKey::Name => "name",
+            SortKey::Protocol => "protocol",
+            SortKey::Loss => "loss",
+        }
+    }
+
+    fn compare(self, a: &ProbeResult, b: &ProbeResult) -> Ordering {
+        match self {
+            // Alive first, fastest first; dead proxies sink to the bottom.
+            SortKey::Delay => (!a.alive, a.delay_ms.unwrap_or(u64::MAX))
+                .cmp(&(!b.alive, b.delay_ms.unwrap_or(u64::MAX))),
+            SortKey::Name => a.name.cmp(&b.name),
+            SortKey::Protocol => a.protocol.cmp(&b.protocol).then(a.name.cmp(&b.name)),
+            SortKey::Loss => a.loss_pct.total_cmp(&b.loss_pct),
+        }
+    }
+}
+
+#[derive(Default)]
+struct App {
+    results: Vec<ProbeResult>,
+    history: HashMap<String, VecDeque<Option<u64>>>,
+    rounds: u64,
+    last_round: Option<DateTime<Local>>,
+    sort: SortKey,
+    reverse: bool,
+    filter: String,
+    editing_filter: bool,
+    table: TableState,
+}
+
+impl App {
+    fn run(
+        mut self,
+        mut terminal: DefaultTerminal,
+        mut updates: watch::Receiver<Vec<ProbeResult>>,
+        shutdown: &ShutdownSignal,
+    ) -> std::io::Result<()> {
+        while !*shutdown.borrow() {
+            if updates.has_changed().unwrap_or(false) {
+                let results = updates.borrow_and_update().clone();
+                self.record(results);
+            }
+            terminal.draw(|frame| self.draw(frame))?;
+
+            if event::poll(Duration::from_millis(250))?
+                && let Event::Key(key) = event::read()?
+                && key.kind == KeyEventKind::Press
+                && !self.handle_key(key)
+            {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn record(&mut self, results: Vec<ProbeResult>) {
+        for result in &results {
+            let points = self.history.entry(result.name.clone()).or_default();
+            if points.len() == SPARKLINE_POINTS {
+                points.pop_front();
+            }
+            points.push_back(result.delay_ms);
+        }
+        self.rounds += 1;
+        self.last_round = Some(Local::now());
+        self.results = results;
+    }
+
+    /// Returns false when the user asked to quit.
+    fn handle_key(&mut self, key: KeyEvent) -> bool {
+        if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+            return false;
+        }
+
+        if self.editing_filter {
+            match key.code {
+                KeyCode::Enter | KeyCode::Esc => self.editing_filter = false,
+                KeyCode::Backspace => {
+                    self.filter.pop();
+                }
+                KeyCode::Char(c) => self.filter.push(c),
+                _ => {}
+            }
+            return true;
+        }
+
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Char('s') => self.sort = self.sort.next(),
+            KeyCode::Char('r') => self.reverse = !self.reverse,
+            KeyCode::Char('/') => {
+                self.filter.clear();
+                self.editing_filter = true;
+            }
+            KeyCode::Down | KeyCode::Char('j') => self.table.select_next(),
+            KeyCode::Up | KeyCode::Char('k') => self.table.select_previous(),
+            _ => {}
+        }
+        true
+    }
+
+    /// Results matching the filter, in display order.
+    fn visible(&self) -> Vec<&ProbeResult> {
+        let filter = self.filter.to_lowercase();
+        let mut rows: Vec<&ProbeResult> = self
+            .results
+            .iter()
+            .filter(|r| {
+                filter.is_empty()
+                    || r.name.to_lowercase().contains(&filter)
+                    || r.protocol.to_lowercase().contains(&filter)
+            })
+            .collect();
+        rows.sort_by(|a, b| self.sort.compare(a, b));
+        if self.reverse {
+            rows.reverse();
+        }
+        rows
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [summary_area, table_area, help_area] = Layout::vertical([
+            Constraint::Length(4),
+            Constraint::Min(0),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        frame.render_widget(self.summary(), summary_area);
+        let table = self.proxy_table();
+        frame.render_stateful_widget(table, table_area, &mut self.table);
+        frame.render_widget(
+            Paragraph::new("q quit  s sort  r reverse  / filter  ↑↓ select")
+                .style(Style::default().fg(Color::DarkGray)),
+            help_area,
+        );
+    }
+
+    fn summary(&self) -> Paragraph<'static> {
+        let total = self.results.len();
+        let delays: Vec<u64> = self
+            .results
+            .iter()
+            .filter(|r| r.alive)
+            .filter_map(|r| r.delay_ms)
+            .collect();
+        let alive_count = self.results.iter().filter(|r| r.alive).count();
+        let rate = if total > 0 {
+            alive_count as f64 / total as f64 * 100.0
+        } else {
+            0.0
+        };
+        let avg = if delays.is_empty() {
+            "-".to_string()
+        } else {
+            format!("{}ms", delays.iter().sum::<u64>() / delays.len() as u64)
+        };
+        let last = self
+            .last_round
+            .map(|t| t.format("%H:%M:%S").to_string())
+            .unwrap_or_else(|| "waiting for first round".to_string());
+
+        let filter = if self.editing_filter {
+            format!("{}▏", self.filter)
+        } else if self.filter.is_empty() {
+            "none".to_string()
+        } else {
+            self.filter.clone()
+        };
+
+        Paragraph::new(vec![
+            Line::from(format!(
+                "Round {}  ·  {}/{} alive ({:.1}%)  ·  avg {}  ·  last {}",
+                self.rounds, alive_count, total, rate, avg, last
+            )),
+            Line::from(format!(
+                "Sort: {}{}  ·  Filter: {}",
+                self.sort.label(),
+                if self.reverse { " (reversed)" } else { "" },
+                filter
+            )),
+        ])
+        .block(Block::default().title(" ClashProbe ").borders(Borders::ALL))
+    }
+
+    fn proxy_table(&self) -> Table<'static> {
+        let rows: Vec<Row> = self
+            .visible()
+            .into_iter()
+            .map(|r| {
+                let (status, color) = if r.alive {
+                    ("ALIVE", Color::Green)
+                } else {
+                    ("DEAD", Color::Red)
+                };
+                Row::new(vec![
+                    Cell::from(r.name.clone()),
+                    Cell::from(r.protocol.clone()),
+                    Cell::from(status).style(Style::default().fg(color)),
+                    Cell::from(
+                        r.delay_ms
+                            .map(|ms| format!("{}ms", ms))
+                            .unwrap_or_else(|| "-".to_string()),
+                    ),
+                    Cell::from(format!("{:.0}%", r.loss_pct)),
+                    Cell::from(self.history.get(&r.name).map(sparkline).unwrap_or_default()),
+                ])
+            })
+            .collect();
+
+        Table::new(
+            rows,
+            [
+                Constraint::Fill(1),
+                Constraint::Length(12),
+                Constraint::Length(6),
+                Constraint::Length(8),
+                Constraint::Length(5),
+                Constraint::Length(SPARKLINE_POINTS as u16),
+            ],
+        )
+        .header(
+            Row::new(["Name", "Protocol", "Status", "Delay", "Loss", "History"])
+                .style(Style::default().add_modifier(Modifier::BOLD)),
+        )
+        .block(Block::default().borders(Borders::ALL))
+        .row_highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+    }
+}
+
+/// Latency history as block characters scaled to the slowest point; failed
+/// rounds show as `·`.
+fn sparkline(points: &VecDeque<Option<u64>>) -> String {
+    let max = points.iter().flatten().copied().max().unwrap_or(0).max(1);
+    points
+        .iter()
+        .map(|point| match point {
+            Some(ms) => SPARK_CHARS[(ms * (SPARK_CHARS.len() as u64 - 1) / max) as usize],
+            None => '·',
+        })
+        .collect()
+}
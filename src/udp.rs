@@ -0,0 +1,85 @@
+use crate::config::UdpCheckConfig;
+use clash_lib::{
+    Session,
+    app::dns::ThreadSafeDNSResolver,
+    proxy::{AnyOutboundHandler, datagram::UdpPacket},
+    session::{Network, SocksAddr},
+};
+use futures::{SinkExt, StreamExt};
+use std::{io, net::SocketAddr, time::Duration};
+
+/// Send a DNS query for `config.domain` to `config.server` through the
+/// proxy's UDP relay and wait for the answer. Any well-formed reply to the
+/// query counts, NXDOMAIN included: only the relay is under test.
+pub async fn check(
+    handler: &AnyOutboundHandler,
+    resolver: ThreadSafeDNSResolver,
+    config: &UdpCheckConfig,
+    timeout: Duration,
+) -> io::Result<()> {
+    if !handler.support_udp().await {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "proxy has UDP disabled",
+        ));
+    }
+    tokio::time::timeout(timeout, exchange(handler, resolver, config))
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "UDP check timed out"))?
+}
+
+async fn exchange(
+    handler: &AnyOutboundHandler,
+    resolver: ThreadSafeDNSResolver,
+    config: &UdpCheckConfig,
+) -> io::Result<()> {
+    let server: SocketAddr = config
+        .server
+        .parse()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let sess = Session {
+        network: Network::Udp,
+        destination: SocksAddr::Ip(server),
+        ..Default::default()
+    };
+    let mut datagram = handler.connect_datagram(&sess, resolver).await?;
+
+    let id: u16 = rand::random();
+    let local = SocksAddr::Ip(SocketAddr::from(([0, 0, 0, 0], 0)));
+    datagram
+        .send(UdpPacket::new(
+            query(id, &config.domain),
+            local,
+            sess.destination.clone(),
+        ))
+        .await?;
+    while let Some(packet) = datagram.next().await {
+        if answers(&packet.data, id) {
+            return Ok(());
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::UnexpectedEof,
+        "UDP relay closed without an answer",
+    ))
+}
+
+/// A recursive query for the A records of `domain`.
+fn query(id: u16, domain: &str) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(domain.len() + 18);
+    packet.extend_from_slice(&id.to_be_bytes());
+    // Recursion desired; one question, no other records.
+    packet.extend_from_slice(&[0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in domain.trim_end_matches('.').split('.') {
+        packet.push(label.len() as u8);
+        packet.extend_from_slice(label.as_bytes());
+    }
+    // Root label, type A, class IN.
+    packet.extend_from_slice(&[0, 0, 1, 0, 1]);
+    packet
+}
+
+/// Whether `packet` is a DNS response to the query with `id`.
+fn answers(packet: &[u8], id: u16) -> bool {
+    packet.len() >= 12 && packet[..2] == id.to_be_bytes() && packet[2] & 0x80 != 0
+}
@@ -0,0 +1,57 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// Seconds covered by one bucket.
+const BUCKET_SECS: i64 = 300;
+
+/// Buckets kept, one day's worth.
+const BUCKETS: usize = (24 * 3600 / BUCKET_SECS) as usize;
+
+/// Probe outcomes of one proxy over the past day, counted in five-minute
+/// buckets so memory stays flat however short the probe interval is.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct UptimeWindow {
+    buckets: VecDeque<Bucket>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Bucket {
+    start: i64,
+    probed: u32,
+    alive: u32,
+}
+
+impl UptimeWindow {
+    pub fn record(&mut self, now: DateTime<Utc>, alive: bool) {
+        let start = now.timestamp() - now.timestamp().rem_euclid(BUCKET_SECS);
+        if self.buckets.back().is_none_or(|b| b.start != start) {
+            self.buckets.push_back(Bucket {
+                start,
+                probed: 0,
+                alive: 0,
+            });
+            if self.buckets.len() > BUCKETS {
+                self.buckets.pop_front();
+            }
+        }
+        let bucket = self.buckets.back_mut().unwrap();
+        bucket.probed += 1;
+        bucket.alive += u32::from(alive);
+    }
+
+    /// Percentage of probes in the past `hours` that found the proxy alive,
+    /// or `None` if it wasn't probed in that time.
+    pub fn uptime_pct(&self, now: DateTime<Utc>, hours: i64) -> Option<f64> {
+        let since = now.timestamp() - hours * 3600;
+        let (probed, alive) = self
+            .buckets
+            .iter()
+            .rev()
+            .take_while(|b| b.start + BUCKET_SECS > since)
+            .fold((0, 0), |(probed, alive), b| {
+                (probed + b.probed, alive + b.alive)
+            });
+        (probed > 0).then(|| alive as f64 * 100.0 / probed as f64)
+    }
+}
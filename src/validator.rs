@@ -0,0 +1,341 @@
+use crate::config::Expectation;
+use crate::probe_result::PhaseTimings;
+use clash_lib::{
+    Session, app::dns::ThreadSafeDNSResolver, proxy::AnyOutboundHandler, session::SocksAddr,
+};
+use std::{io, sync::Arc, time::Duration};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::time::Instant;
+use tokio_rustls::{
+    TlsConnector,
+    client::TlsStream,
+    rustls::{ClientConfig, RootCertStore, pki_types::ServerName},
+};
+
+/// Upper bound on how much of the response we read looking for the body.
+const MAX_RESPONSE_BYTES: u64 = 64 * 1024;
+
+/// Fetch `url` through `handler` and check the response against `expect`.
+///
+/// `url_test` accepts any HTTP response, so a captive portal or a provider
+/// block page answering 200 would count as alive. This issues its own plain
+/// HTTP/1.1 request over the proxied stream and looks at the status line and
+/// body.
+pub async fn validate(
+    handler: &AnyOutboundHandler,
+    resolver: ThreadSafeDNSResolver,
+    url: &str,
+    expect: &Expectation,
+    timeout: Duration,
+) -> io::Result<()> {
+    tokio::time::timeout(timeout, fetch(handler, resolver, url))
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "validation request timed out"))?
+        .and_then(|(response, _)| check(url, &response, expect))
+}
+
+/// Fetch `url` through `handler` and time each phase of the request. Any
+/// HTTP response will do.
+pub async fn measure_phases(
+    handler: &AnyOutboundHandler,
+    resolver: ThreadSafeDNSResolver,
+    url: &str,
+    timeout: Duration,
+) -> io::Result<PhaseTimings> {
+    let (response, phases) = tokio::time::timeout(timeout, fetch(handler, resolver, url))
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "timed request timed out"))??;
+    if !response.starts_with(b"HTTP/") {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid HTTP response from {}", url),
+        ));
+    }
+    Ok(phases)
+}
+
+/// Fetch `url` twice through `handler` over one connection. Returns the
+/// delay of the first request, connecting included, and of the second,
+/// which only has to send and receive. Needs a response with a length.
+pub async fn measure_reuse(
+    handler: &AnyOutboundHandler,
+    resolver: ThreadSafeDNSResolver,
+    url: &str,
+    timeout: Duration,
+) -> io::Result<[u64; 2]> {
+    tokio::time::timeout(timeout, reuse(handler, resolver, url))
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "repeated request timed out"))?
+}
+
+/// Where a test URL points and what to send it.
+struct Target {
+    host: String,
+    port: u16,
+    path: String,
+    https: bool,
+}
+
+impl Target {
+    fn parse(url: &str) -> io::Result<Self> {
+        let url =
+            url::Url::parse(url).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let host = url
+            .host_str()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "test URL has no host"))?
+            .to_string();
+        let path = match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_string(),
+        };
+        Ok(Self {
+            host,
+            port: url.port_or_known_default().unwrap_or(80),
+            path,
+            https: url.scheme() == "https",
+        })
+    }
+
+    fn session(&self) -> Session {
+        Session {
+            destination: SocksAddr::Domain(self.host.clone(), self.port),
+            ..Default::default()
+        }
+    }
+
+    fn request(&self, connection: &str) -> String {
+        format!(
+            "GET {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: clashprobe\r\nAccept: */*\r\nConnection: {}\r\n\r\n",
+            self.path, self.host, connection
+        )
+    }
+}
+
+async fn fetch(
+    handler: &AnyOutboundHandler,
+    resolver: ThreadSafeDNSResolver,
+    url: &str,
+) -> io::Result<(Vec<u8>, PhaseTimings)> {
+    let target = Target::parse(url)?;
+    let start = Instant::now();
+    let stream = handler.connect_stream(&target.session(), resolver).await?;
+    let mut phases = PhaseTimings {
+        handshake_ms: millis(start.elapsed()),
+        ..Default::default()
+    };
+
+    let request = target.request("close");
+    if target.https {
+        let start = Instant::now();
+        let stream = tls(stream, &target.host).await?;
+        phases.tls_ms = Some(millis(start.elapsed()));
+        exchange(stream, &request, phases).await
+    } else {
+        exchange(stream, &request, phases).await
+    }
+}
+
+async fn reuse(
+    handler: &AnyOutboundHandler,
+    resolver: ThreadSafeDNSResolver,
+    url: &str,
+) -> io::Result<[u64; 2]> {
+    let target = Target::parse(url)?;
+    let start = Instant::now();
+    let stream = handler.connect_stream(&target.session(), resolver).await?;
+    let request = target.request("keep-alive");
+    if target.https {
+        let stream = tls(stream, &target.host).await?;
+        twice(stream, &request, start).await
+    } else {
+        twice(stream, &request, start).await
+    }
+}
+
+async fn tls<S>(stream: S, host: &str) -> io::Result<TlsStream<S>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut roots = RootCertStore::empty();
+    roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    let tls_config = ClientConfig::builder()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    let server_name = ServerName::try_from(host.to_string())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    TlsConnector::from(Arc::new(tls_config))
+        .connect(server_name, stream)
+        .await
+}
+
+async fn exchange<S>(
+    mut stream: S,
+    request: &str,
+    mut phases: PhaseTimings,
+) -> io::Result<(Vec<u8>, PhaseTimings)>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    stream.write_all(request.as_bytes()).await?;
+    stream.flush().await?;
+    let sent = Instant::now();
+
+    let mut stream = stream.take(MAX_RESPONSE_BYTES);
+    let mut response = vec![0u8; 4096];
+    let read = stream.read(&mut response).await?;
+    phases.first_byte_ms = millis(sent.elapsed());
+    response.truncate(read);
+    stream.read_to_end(&mut response).await?;
+    Ok((response, phases))
+}
+
+/// Send `request` twice on `stream`. The first is timed from `start`, the
+/// second from when it is sent.
+async fn twice<S>(stream: S, request: &str, start: Instant) -> io::Result<[u64; 2]>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut stream = BufReader::new(stream);
+    round_trip(&mut stream, request).await?;
+    let first = millis(start.elapsed());
+    let sent = Instant::now();
+    round_trip(&mut stream, request).await?;
+    Ok([first, millis(sent.elapsed())])
+}
+
+/// Send `request` and read exactly one response, leaving the connection
+/// ready for the next request.
+async fn round_trip<S>(stream: &mut BufReader<S>, request: &str) -> io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    stream.write_all(request.as_bytes()).await?;
+    stream.flush().await?;
+
+    let mut status = None;
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if stream.read_line(&mut line).await? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "connection closed mid-response",
+            ));
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if status.is_none() {
+            status = line.split_whitespace().nth(1).map(str::to_string);
+        } else if let Some((name, value)) = line.split_once(':')
+            && name.eq_ignore_ascii_case("content-length")
+        {
+            length = value.trim().parse::<u64>().ok();
+        }
+    }
+    // These never have a body, whatever the headers say.
+    if matches!(status.as_deref(), Some("204" | "304")) {
+        length = Some(0);
+    }
+    let length = length.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "response has no Content-Length, so the connection cannot be reused",
+        )
+    })?;
+    tokio::io::copy(&mut (&mut *stream).take(length), &mut tokio::io::sink()).await?;
+    Ok(())
+}
+
+fn millis(elapsed: Duration) -> u64 {
+    elapsed.as_millis() as u64
+}
+
+fn check(url: &str, response: &[u8], expect: &Expectation) -> io::Result<()> {
+    let text = String::from_utf8_lossy(response);
+    let status = text
+        .lines()
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|code| code.parse::<u16>().ok())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid HTTP response from {}", url),
+            )
+        })?;
+
+    if status != expect.status {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "unexpected HTTP status {} from {}, expected {}",
+                status, url, expect.status
+            ),
+        ));
+    }
+
+    if let Some(needle) = &expect.body_contains {
+        let body = body(response);
+        if !String::from_utf8_lossy(&body).contains(needle.as_str()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unexpected response body from {}", url),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// The body of `response`, with chunked transfer-encoding undone.
+fn body(response: &[u8]) -> Vec<u8> {
+    let Some(end) = find(response, b"\r\n\r\n") else {
+        return Vec::new();
+    };
+    let head = String::from_utf8_lossy(&response[..end]);
+    let body = &response[end + 4..];
+    if is_chunked(&head) {
+        dechunk(body)
+    } else {
+        body.to_vec()
+    }
+}
+
+fn is_chunked(head: &str) -> bool {
+    head.lines()
+        .filter_map(|line| line.split_once(':'))
+        .any(|(name, value)| {
+            name.trim().eq_ignore_ascii_case("transfer-encoding")
+                && value.to_ascii_lowercase().contains("chunked")
+        })
+}
+
+/// Join the chunks of a chunked body, up to the last chunk or wherever
+/// `MAX_RESPONSE_BYTES` cut the response off.
+fn dechunk(mut rest: &[u8]) -> Vec<u8> {
+    let mut body = Vec::new();
+    while let Some(line_end) = find(rest, b"\r\n") {
+        let size_line = String::from_utf8_lossy(&rest[..line_end]);
+        // Chunk extensions follow the size after a `;`.
+        let size = size_line.split(';').next().unwrap_or_default().trim();
+        let Ok(size) = usize::from_str_radix(size, 16) else {
+            break;
+        };
+        if size == 0 {
+            break;
+        }
+        let data = &rest[line_end + 2..];
+        let size = size.min(data.len());
+        body.extend_from_slice(&data[..size]);
+        rest = data[size..].strip_prefix(b"\r\n").unwrap_or_default();
+    }
+    body
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
@@ -1,121 +1,626 @@
+use crate::agent::{self, Ingest, SharedNodeReports};
+use crate::auth::{WebAuth, require_auth};
+use crate::config::{ControllerConfig, PeersConfig, WebConfig};
+use crate::export::{self, SharedProxyConfigs};
+use crate::fleet::Fleet;
+use crate::history::HistoryStore;
+use crate::probe_engine::{EngineCommand, EngineCommandSender};
 use crate::probe_result::ProbeResult;
-use crate::reporter::ProbeReporter;
+use crate::reporter::{ProbeReporter, SharedReporterStats, SharedRoundInfo};
+use crate::shutdown::{self, ShutdownSignal};
+use crate::subscription::SharedSubscriptionChanges;
+use crate::tls::{self, TlsListener};
 use anyhow::Result;
 use async_trait::async_trait;
 use axum::{
     Json, Router,
-    extract::State,
-    response::{Html, Sse},
-    routing::get,
+    extract::{Path, Query, State},
+    http::{StatusCode, header},
+    middleware,
+    response::{Html, IntoResponse, Sse},
+    routing::{get, post},
+    serve::Listener,
 };
+use regex::Regex;
+use serde::Deserialize;
 use serde_json::json;
-use std::{convert::Infallible, sync::Arc, time::Duration};
-use tokio::sync::{RwLock, broadcast};
+use std::{
+    convert::Infallible,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+use tokio::{
+    sync::{RwLock, broadcast, oneshot},
+    task::JoinHandle,
+};
 use tokio_stream::{StreamExt as _, wrappers::BroadcastStream};
 use tower::ServiceBuilder;
 use tower_http::{cors::CorsLayer, services::ServeDir};
-use tracing::{error, info};
+use tracing::{debug, error, info};
 
-pub type ProbeResults = Arc<RwLock<Vec<ProbeResult>>>;
-pub type ProbeUpdateSender = broadcast::Sender<Vec<ProbeResult>>;
+pub type ProbeResults = Arc<RwLock<Arc<[ProbeResult]>>>;
+pub type ProbeUpdateSender = broadcast::Sender<RoundUpdate>;
+/// Serialized single results of the round in progress.
+pub type PartialResultSender = broadcast::Sender<Arc<str>>;
+
+/// A finished round as sent to SSE clients. Serialized once when it is
+/// published, so every subscriber only clones a pointer.
+#[derive(Clone)]
+pub struct RoundUpdate {
+    pub round_id: u64,
+    /// The `/api/status` document of the round.
+    pub data: Arc<str>,
+}
 
 #[derive(Clone)]
 pub struct AppState {
     pub results: ProbeResults,
     pub update_sender: ProbeUpdateSender,
+    /// Single results of the round in progress, as they come in.
+    pub partial_sender: PartialResultSender,
+    pub commands: EngineCommandSender,
+    pub history: Arc<RwLock<HistoryStore>>,
+    pub proxy_configs: SharedProxyConfigs,
+    pub reporter_stats: SharedReporterStats,
+    pub round_info: SharedRoundInfo,
+    pub subscription_changes: SharedSubscriptionChanges,
+    /// Rounds pushed by agents, with the `Controller` work mode.
+    pub node_reports: SharedNodeReports,
+    /// Polled state of the `[peers]`, if any are configured.
+    pub fleet: Option<Arc<Fleet>>,
+    /// Set once the first probe round has been reported.
+    pub ready: Arc<AtomicBool>,
 }
 
 impl AppState {
-    pub fn new() -> Self {
+    pub fn new(
+        commands: EngineCommandSender,
+        proxy_configs: SharedProxyConfigs,
+        reporter_stats: SharedReporterStats,
+        round_info: SharedRoundInfo,
+        subscription_changes: SharedSubscriptionChanges,
+        node_reports: SharedNodeReports,
+        history_size: usize,
+    ) -> Self {
         let (update_sender, _) = broadcast::channel(100);
+        let (partial_sender, _) = broadcast::channel(1024);
         Self {
-            results: Arc::new(RwLock::new(Vec::new())),
+            results: Arc::new(RwLock::new(Arc::from([]))),
             update_sender,
+            partial_sender,
+            commands,
+            history: Arc::new(RwLock::new(HistoryStore::new(history_size))),
+            proxy_configs,
+            reporter_stats,
+            round_info,
+            subscription_changes,
+            node_reports,
+            fleet: None,
+            ready: Arc::new(AtomicBool::new(false)),
         }
     }
 
-    pub async fn update_results(&self, new_results: Vec<ProbeResult>) {
-        {
-            let mut results = self.results.write().await;
-            *results = new_results.clone();
-        }
+    pub async fn update_results(&self, new_results: &[ProbeResult]) {
+        let update = RoundUpdate {
+            round_id: new_results
+                .iter()
+                .map(|r| r.round_id)
+                .max()
+                .unwrap_or_default(),
+            data: status_payload(new_results).to_string().into(),
+        };
+        *self.results.write().await = Arc::from(new_results);
+        self.history.write().await.record(new_results);
+        self.ready.store(true, Ordering::Relaxed);
 
-        if let Err(e) = self.update_sender.send(new_results) {
+        if let Err(e) = self.update_sender.send(update) {
             error!("Failed to broadcast update: {}", e);
         }
     }
+
+    /// Pass one result of the running round on to SSE clients. Nobody
+    /// listening is not an error here.
+    pub fn publish_partial(&self, result: &ProbeResult) {
+        let _ = self.partial_sender.send(json!(result).to_string().into());
+    }
 }
 
-pub async fn start_web_server(port: u16) -> AppState {
-    let app_state = AppState::new();
+pub async fn start_web_server(
+    config: &WebConfig,
+    commands: EngineCommandSender,
+    proxy_configs: SharedProxyConfigs,
+    reporter_stats: SharedReporterStats,
+    round_info: SharedRoundInfo,
+    subscription_changes: SharedSubscriptionChanges,
+    node_reports: SharedNodeReports,
+    ingest: Option<&ControllerConfig>,
+    peers: Option<&PeersConfig>,
+    shutdown: ShutdownSignal,
+) -> Result<(AppState, JoinHandle<()>)> {
+    let mut app_state = AppState::new(
+        commands,
+        proxy_configs,
+        reporter_stats,
+        round_info,
+        subscription_changes,
+        node_reports,
+        config.history_size,
+    );
+    if let Some(peers) = peers {
+        app_state.fleet = Some(Fleet::start(peers, shutdown.clone())?);
+    }
+    let port = config.port;
+    let auth = Arc::new(WebAuth::from_config(config));
 
-    let app = Router::new()
-        .route("/", get(index_handler))
+    let protected = Router::new()
         .route("/api/status", get(status_handler))
+        .route("/api/proxy/{name}", get(proxy_handler))
+        .route("/api/export/clash", get(export_clash_handler))
+        .route("/api/export/sub", get(export_sub_handler))
+        .route("/api/probe", post(probe_handler))
+        .route("/api/pause", post(pause_handler))
+        .route("/api/resume", post(resume_handler))
+        .route("/api/reporters", get(reporters_handler))
+        .route("/api/groups", get(groups_handler))
+        .route("/api/fleet", get(fleet_handler))
+        .route("/api/subscription", get(subscription_handler))
+        .route(
+            "/api/subscription/changes",
+            get(subscription_changes_handler),
+        )
         .route("/events", get(sse_handler))
+        .route_layer(middleware::from_fn_with_state(auth, require_auth));
+
+    // Agents authenticate with the controller token, not the web
+    // credentials, so they can't drive the rest of the API.
+    let mut ingested = Router::new();
+    if let Some(controller) = ingest {
+        let auth = Arc::new(WebAuth::token(&controller.token));
+        ingested = ingested
+            .route("/api/ingest", post(ingest_handler))
+            .route_layer(middleware::from_fn_with_state(auth, require_auth));
+    }
+
+    let app = Router::new()
+        .route("/", get(index_handler))
+        .route("/healthz", get(health_handler))
+        .route("/readyz", get(ready_handler))
+        .route("/api/openapi.json", get(openapi_handler))
+        .route("/api/docs", get(docs_handler))
+        .merge(protected)
+        .merge(ingested)
         .nest_service("/static", ServeDir::new("static"))
         .layer(ServiceBuilder::new().layer(CorsLayer::permissive()))
         .with_state(app_state.clone());
 
-    let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{}", port))
-        .await
-        .expect("Failed to bind to port");
+    #[cfg(unix)]
+    if let Some(path) = &config.unix_socket {
+        remove_stale_socket(path);
+        let listener = tokio::net::UnixListener::bind(path)
+            .map_err(|e| anyhow::anyhow!("Failed to bind Unix socket {}: {}", path, e))?;
+        info!("Web server starting on unix:{}", path);
+        let server = tokio::spawn(serve(listener, app, shutdown));
+        return Ok((app_state, server));
+    }
 
-    info!("Web server starting on http://localhost:{}", port);
+    // (host, port) rather than a formatted string so IPv6 literals work too.
+    let listener = tokio::net::TcpListener::bind((config.host.as_str(), port))
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to bind to {}:{}: {}", config.host, port, e))?;
+    let addr = listener.local_addr()?;
 
-    tokio::spawn(async move {
-        if let Err(e) = axum::serve(listener, app).await {
-            error!("Web server error: {}", e);
+    let server = match (&config.tls_cert, &config.tls_key) {
+        (Some(cert), Some(key)) => {
+            let acceptor = tls::load_acceptor(cert, key)?;
+            info!("Web server starting on https://{}", addr);
+            tokio::spawn(serve(TlsListener::new(listener, acceptor), app, shutdown))
         }
-    });
+        _ => {
+            info!("Web server starting on http://{}", addr);
+            tokio::spawn(serve(listener, app, shutdown))
+        }
+    };
+
+    Ok((app_state, server))
+}
+
+/// Remove a socket left behind by a previous run, which would make bind
+/// fail. Anything else at `path` is left for bind to report.
+#[cfg(unix)]
+fn remove_stale_socket(path: &str) {
+    use std::os::unix::fs::FileTypeExt;
+    if std::fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_socket()) {
+        let _ = std::fs::remove_file(path);
+    }
+}
 
-    app_state
+async fn serve<L>(listener: L, app: Router, mut shutdown: ShutdownSignal)
+where
+    L: Listener,
+    L::Addr: std::fmt::Debug,
+{
+    let result = axum::serve(listener, app)
+        .with_graceful_shutdown(async move { shutdown::requested(&mut shutdown).await })
+        .await;
+    match result {
+        Ok(()) => info!("Web server stopped"),
+        Err(e) => error!("Web server error: {}", e),
+    }
 }
 
 async fn index_handler() -> Html<&'static str> {
     Html(include_str!("static/index.html"))
 }
 
-async fn status_handler(State(state): State<AppState>) -> Json<serde_json::Value> {
-    let results = state.results.read().await;
-    let alive_count = results.iter().filter(|r| r.alive).count();
+async fn openapi_handler() -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "application/json")],
+        include_str!("static/openapi.json"),
+    )
+}
 
-    Json(json!({
+async fn docs_handler() -> Html<&'static str> {
+    Html(include_str!("static/swagger.html"))
+}
+
+/// Liveness: the process is up and serving requests.
+async fn health_handler() -> &'static str {
+    "ok"
+}
+
+/// Readiness: the subscription was parsed at startup and at least one probe
+/// round has produced results.
+async fn ready_handler(State(state): State<AppState>) -> (StatusCode, &'static str) {
+    if state.ready.load(Ordering::Relaxed) {
+        (StatusCode::OK, "ready")
+    } else {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "waiting for first probe round",
+        )
+    }
+}
+
+fn status_payload(results: &[ProbeResult]) -> serde_json::Value {
+    let alive_count = results.iter().filter(|r| r.alive).count();
+    json!({
         "timestamp": chrono::Utc::now(),
         "total": results.len(),
         "alive": alive_count,
         "dead": results.len() - alive_count,
         "success_rate": if results.is_empty() { 0.0 } else { (alive_count as f64 / results.len() as f64) * 100.0 },
-        "proxies": *results
-    }))
+        "proxies": results
+    })
+}
+
+/// `/api/status` query parameters. Summary counts always cover every proxy;
+/// the filters only narrow down the `proxies` list.
+#[derive(Deserialize)]
+struct StatusQuery {
+    alive: Option<bool>,
+    protocol: Option<String>,
+    region: Option<String>,
+    name_regex: Option<String>,
+    /// `delay` (default order), `name`, `protocol`, `region` or `loss`;
+    /// prefix with `-` to reverse.
+    sort: Option<String>,
+    limit: Option<usize>,
+    #[serde(default)]
+    offset: usize,
+}
+
+impl StatusQuery {
+    fn apply(&self, results: &[ProbeResult]) -> Result<(usize, Vec<ProbeResult>), String> {
+        let name_regex = self
+            .name_regex
+            .as_deref()
+            .map(Regex::new)
+            .transpose()
+            .map_err(|e| format!("Invalid name_regex: {}", e))?;
+
+        let mut matched: Vec<ProbeResult> = results
+            .iter()
+            .filter(|r| self.alive.is_none_or(|alive| r.alive == alive))
+            .filter(|r| {
+                self.protocol
+                    .as_deref()
+                    .is_none_or(|p| r.protocol.eq_ignore_ascii_case(p))
+            })
+            .filter(|r| {
+                self.region.as_deref().is_none_or(|region| {
+                    r.region
+                        .as_deref()
+                        .is_some_and(|r| r.eq_ignore_ascii_case(region))
+                })
+            })
+            .filter(|r| name_regex.as_ref().is_none_or(|re| re.is_match(&r.name)))
+            .cloned()
+            .collect();
+
+        if let Some(sort) = self.sort.as_deref() {
+            let (key, reverse) = match sort.strip_prefix('-') {
+                Some(key) => (key, true),
+                None => (sort, false),
+            };
+            match key {
+                // Results are already stored alive-first by delay.
+                "delay" => {}
+                "name" => matched.sort_by(|a, b| a.name.cmp(&b.name)),
+                "protocol" => matched.sort_by(|a, b| a.protocol.cmp(&b.protocol)),
+                "region" => matched.sort_by(|a, b| a.region.cmp(&b.region)),
+                "loss" => matched.sort_by(|a, b| a.loss_pct.total_cmp(&b.loss_pct)),
+                _ => return Err(format!("Unknown sort key: {}", key)),
+            }
+            if reverse {
+                matched.reverse();
+            }
+        }
+
+        let total = matched.len();
+        let page = matched
+            .into_iter()
+            .skip(self.offset)
+            .take(self.limit.unwrap_or(usize::MAX))
+            .collect();
+        Ok((total, page))
+    }
+}
+
+async fn status_handler(
+    State(state): State<AppState>,
+    Query(query): Query<StatusQuery>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let results = state.results.read().await;
+    let (matched, page) = query
+        .apply(&results)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    let overruns = state
+        .round_info
+        .read()
+        .unwrap()
+        .as_ref()
+        .map_or(0, |info| info.overruns);
+
+    let mut payload = status_payload(&results);
+    payload["matched"] = json!(matched);
+    payload["offset"] = json!(query.offset);
+    payload["overruns"] = json!(overruns);
+    payload["proxies"] = json!(page);
+    Ok(Json(payload))
+}
+
+#[derive(Deserialize)]
+struct ProxyQuery {
+    /// Number of history points to return, most recent last.
+    limit: Option<usize>,
+}
+
+async fn proxy_handler(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    Query(query): Query<ProxyQuery>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let current = state
+        .results
+        .read()
+        .await
+        .iter()
+        .find(|r| r.name == name)
+        .cloned()
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "No such proxy".to_string()))?;
+
+    let history = state
+        .history
+        .read()
+        .await
+        .recent(&name, query.limit.unwrap_or(usize::MAX));
+
+    Ok(Json(json!({
+        "proxy": current,
+        "history": history,
+    })))
+}
+
+#[derive(Deserialize)]
+struct ExportQuery {
+    /// Only the N fastest alive proxies.
+    top: Option<usize>,
+}
+
+async fn export_clash_handler(
+    State(state): State<AppState>,
+    Query(query): Query<ExportQuery>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let results = state.results.read().await;
+    let configs = state.proxy_configs.read().unwrap().clone();
+    let proxies = export::alive_configs(&results, &configs, query.top);
+    let yaml = export::clash_yaml(&proxies)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(([(header::CONTENT_TYPE, "text/yaml; charset=utf-8")], yaml))
+}
+
+async fn export_sub_handler(
+    State(state): State<AppState>,
+    Query(query): Query<ExportQuery>,
+) -> impl IntoResponse {
+    let results = state.results.read().await;
+    let configs = state.proxy_configs.read().unwrap().clone();
+    let proxies = export::alive_configs(&results, &configs, query.top);
+    (
+        [(header::CONTENT_TYPE, "text/plain; charset=utf-8")],
+        export::uri_list(&proxies),
+    )
+}
+
+#[derive(Deserialize)]
+struct ProbeQuery {
+    proxy: Option<String>,
+}
+
+async fn probe_handler(
+    State(state): State<AppState>,
+    Query(query): Query<ProbeQuery>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let filtered = query.proxy.is_some();
+    let (reply, receiver) = oneshot::channel();
+    state
+        .commands
+        .send(EngineCommand::ProbeNow {
+            proxy: query.proxy,
+            reply,
+        })
+        .await
+        .map_err(|_| {
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "Probe engine is not running".to_string(),
+            )
+        })?;
+
+    let results = receiver.await.map_err(|_| {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Probe engine dropped the request".to_string(),
+        )
+    })?;
+
+    if filtered && results.is_empty() {
+        return Err((StatusCode::NOT_FOUND, "No such proxy".to_string()));
+    }
+
+    Ok(Json(status_payload(&results)))
+}
+
+/// Delivery counters of every reporter that has handled a round.
+async fn reporters_handler(State(state): State<AppState>) -> Json<serde_json::Value> {
+    let stats = state.reporter_stats.read().unwrap().clone();
+    Json(json!({ "reporters": stats }))
+}
+
+/// Aggregates of every configured proxy group as of the latest round.
+async fn groups_handler(State(state): State<AppState>) -> Json<serde_json::Value> {
+    let groups = state
+        .round_info
+        .read()
+        .unwrap()
+        .as_ref()
+        .map(|info| info.groups.clone())
+        .unwrap_or_default();
+    Json(json!({ "groups": groups }))
+}
+
+/// The latest delay of every proxy from here and from each peer.
+async fn fleet_handler(
+    State(state): State<AppState>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let fleet = state
+        .fleet
+        .as_ref()
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "No [peers] configured".to_string()))?;
+    let results = state.results.read().await;
+    Ok(Json(json!(fleet.view(&results))))
+}
+
+async fn subscription_handler(State(state): State<AppState>) -> Json<serde_json::Value> {
+    let subscription = state
+        .round_info
+        .read()
+        .unwrap()
+        .as_ref()
+        .and_then(|info| info.subscription);
+    Json(json!({ "subscription": subscription }))
+}
+
+async fn subscription_changes_handler(State(state): State<AppState>) -> Json<serde_json::Value> {
+    let changes = state.subscription_changes.read().unwrap().clone();
+    Json(json!({ "changes": changes }))
+}
+
+/// An agent's round, merged into the next round here.
+async fn ingest_handler(
+    State(state): State<AppState>,
+    Json(ingest): Json<Ingest>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    if ingest.node.trim().is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "node must not be empty".to_string(),
+        ));
+    }
+    debug!(
+        "Ingested {} results from node {}",
+        ingest.results.len(),
+        ingest.node
+    );
+    agent::store(&state.node_reports, ingest);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn pause_handler(
+    State(state): State<AppState>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    set_paused(&state, true).await
+}
+
+async fn resume_handler(
+    State(state): State<AppState>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    set_paused(&state, false).await
+}
+
+async fn set_paused(
+    state: &AppState,
+    paused: bool,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let (reply, receiver) = oneshot::channel();
+    state
+        .commands
+        .send(EngineCommand::SetPaused { paused, reply })
+        .await
+        .map_err(|_| {
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "Probe engine is not running".to_string(),
+            )
+        })?;
+
+    let paused = receiver.await.map_err(|_| {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Probe engine dropped the request".to_string(),
+        )
+    })?;
+
+    Ok(Json(json!({ "paused": paused })))
 }
 
 async fn sse_handler(
     State(state): State<AppState>,
 ) -> Sse<impl futures::Stream<Item = Result<axum::response::sse::Event, Infallible>>> {
     let receiver = state.update_sender.subscribe();
-    let stream = BroadcastStream::new(receiver)
-        .filter_map(|result| match result {
-            Ok(results) => {
-                let alive_count = results.iter().filter(|r| r.alive).count();
-                let data = json!({
-                    "timestamp": chrono::Utc::now(),
-                    "total": results.len(),
-                    "alive": alive_count, 
-                    "dead": results.len() - alive_count,
-                    "success_rate": if results.is_empty() { 0.0 } else { (alive_count as f64 / results.len() as f64) * 100.0 },
-                    "proxies": results
-                });
-
-                Some(Ok(axum::response::sse::Event::default()
-                    .event("update")
-                    .data(data.to_string())))
-            }
-            Err(e) => {
-                error!("SSE broadcast error: {}", e);
-                None
-            }
-        });
+    let updates = BroadcastStream::new(receiver).filter_map(|update| match update {
+        Ok(update) => Some(Ok(axum::response::sse::Event::default()
+            .event("update")
+            .id(update.round_id.to_string())
+            .data(&*update.data))),
+        Err(e) => {
+            error!("SSE broadcast error: {}", e);
+            None
+        }
+    });
+    // A lagging client just misses some partial results; the next update
+    // event carries the full round anyway.
+    let partials = BroadcastStream::new(state.partial_sender.subscribe()).filter_map(|data| {
+        let data = data.ok()?;
+        Some(Ok(axum::response::sse::Event::default()
+            .event("result")
+            .data(&*data)))
+    });
+    let stream = updates.merge(partials);
 
     Sse::new(stream).keep_alive(
         axum::response::sse::KeepAlive::new()
@@ -137,10 +642,25 @@ impl WebReporter {
 #[async_trait]
 impl ProbeReporter for WebReporter {
     async fn report(&self, results: &[ProbeResult]) -> Result<()> {
-        self.app_state.update_results(results.to_vec()).await;
+        self.app_state.update_results(results).await;
         Ok(())
     }
 
+    async fn report_partial(&self, result: &ProbeResult) -> Result<()> {
+        self.app_state.publish_partial(result);
+        Ok(())
+    }
+
+    /// Shown until the first round finishes. Not recorded in history and
+    /// `/readyz` still waits for a real round.
+    async fn restore(&self, results: &[ProbeResult]) {
+        *self.app_state.results.write().await = Arc::from(results);
+    }
+
+    fn is_streaming(&self) -> bool {
+        true
+    }
+
     fn name(&self) -> &str {
         "Web"
     }
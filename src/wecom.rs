@@ -0,0 +1,77 @@
+use crate::config::WeComConfig;
+use crate::probe_result::ProbeResult;
+use crate::reporter::ProbeReporter;
+use crate::summary::{FASTEST, RoundSummary, Severity, StateTracker};
+use anyhow::Result;
+use async_trait::async_trait;
+use serde_json::{Value, json};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Posts a text summary of every round to a WeCom (WeChat Work) group
+/// robot, mentioning everyone when no proxy is alive.
+pub struct WeComReporter {
+    client: reqwest::Client,
+    config: WeComConfig,
+    state: Mutex<StateTracker>,
+}
+
+impl WeComReporter {
+    pub fn new(config: &WeComConfig) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.timeout))
+            .build()?;
+        Ok(Self {
+            client,
+            config: config.clone(),
+            state: Mutex::new(StateTracker::default()),
+        })
+    }
+
+    /// Post `summary` as a text message.
+    async fn send(&self, summary: &RoundSummary) -> Result<()> {
+        let mut text = json!({ "content": summary.chat_text(&self.config.node_name) });
+        if summary.severity() == Severity::Critical {
+            text["mentioned_list"] = json!(["@all"]);
+        }
+        // Robot errors come back as HTTP 200 with a non-zero errcode.
+        let response: Value = self
+            .client
+            .post(&self.config.webhook_url)
+            .json(&json!({ "msgtype": "text", "text": text }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let code = response["errcode"].as_i64().unwrap_or(0);
+        if code != 0 {
+            return Err(anyhow::anyhow!(
+                "WeCom rejected the message: {} {}",
+                code,
+                response["errmsg"].as_str().unwrap_or_default()
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ProbeReporter for WeComReporter {
+    async fn report(&self, results: &[ProbeResult]) -> Result<()> {
+        let summary = self.state.lock().unwrap().summarize(results, FASTEST);
+        if !self.config.only_on_change || summary.changed() {
+            self.send(&summary).await?;
+        }
+        self.state.lock().unwrap().record(results);
+        Ok(())
+    }
+
+    async fn restore(&self, results: &[ProbeResult]) {
+        self.state.lock().unwrap().record(results);
+    }
+
+    fn name(&self) -> &str {
+        "WeCom"
+    }
+}